@@ -0,0 +1,351 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::Duration;
+use structopt::StructOpt;
+use structopt::clap::{Error, ErrorKind};
+use gameboy::{Cartridge, CodeDataLog, Color, Gameboy, JoypadInput, Mode, Movie, Profiler, Symbols, Tracer, GAME_WIDTH, GAME_HEIGHT};
+use gameboy::screenshot;
+use gameboy::gdb::GdbStub;
+
+/// Runs a ROM without a display, writing frames and audio to files.
+#[derive(StructOpt)]
+#[structopt(name = "gameboy-headless")]
+struct Cli {
+    #[structopt(long)]
+    dmg: bool,
+    #[structopt(long)]
+    skip_boot_rom: bool,
+    #[structopt(long, parse(from_os_str))]
+    save_path: Option<PathBuf>,
+    /// Number of frames to run.
+    #[structopt(short, long, default_value = "60")]
+    frames: u64,
+    /// Run this many M-cycles instead of a number of frames.
+    #[structopt(long)]
+    cycles: Option<u64>,
+    /// Input script with one `<frame> [button ...]` line per change of input.
+    #[structopt(short, long, parse(from_os_str))]
+    input: Option<PathBuf>,
+    /// Directory frames are written to.
+    #[structopt(short, long, parse(from_os_str))]
+    out_dir: Option<PathBuf>,
+    /// Write every nth frame. With 0 only the last frame is written.
+    #[structopt(long, default_value = "0")]
+    every: u64,
+    /// Write frames as headerless 24 bit RGB instead of PNG.
+    #[structopt(long)]
+    raw: bool,
+    /// File that interleaved stereo f32 samples at CLOCK_FREQ are written to.
+    #[structopt(long, parse(from_os_str))]
+    audio: Option<PathBuf>,
+    /// Record the inputs of this run to a movie file.
+    #[structopt(long, parse(from_os_str))]
+    record: Option<PathBuf>,
+    /// Play back a movie file. Its mode and boot options replace the ones
+    /// given here and it runs for as many frames as were recorded.
+    #[structopt(long, parse(from_os_str))]
+    play: Option<PathBuf>,
+    /// Wait for a GDB remote protocol client on this local port before
+    /// starting.
+    #[structopt(long)]
+    gdb: Option<u16>,
+    /// Log the registers before every instruction to this file, in the
+    /// gameboy-doctor format.
+    #[structopt(long, parse(from_os_str))]
+    trace: Option<PathBuf>,
+    /// End the run after tracing this many instructions.
+    #[structopt(long)]
+    trace_limit: Option<u64>,
+    /// End the run when PC reaches this address, given in hex.
+    #[structopt(long, parse(try_from_str = "parse_hex"))]
+    trace_stop: Option<u16>,
+    /// Profile the cycles spent in each routine, printing a report and
+    /// writing the call stacks to this file in the folded flamegraph format.
+    #[structopt(long, parse(from_os_str))]
+    profile: Option<PathBuf>,
+    /// Apply the codes from the ROM's `.cht` cheat file.
+    #[structopt(long)]
+    cheats: bool,
+    /// Log which ROM and RAM bytes are run as code or read as data, adding
+    /// to this BizHawk CDL file if it exists.
+    #[structopt(long, parse(from_os_str))]
+    cdl: Option<PathBuf>,
+    #[structopt(parse(from_os_str))]
+    cartridge_path: Option<PathBuf>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Disassembles a ROM into RGBDS source.
+    #[structopt(name = "disasm")]
+    Disasm {
+        /// Symbol file to name addresses from. Defaults to the ROM's `.sym`.
+        #[structopt(long, parse(from_os_str))]
+        sym: Option<PathBuf>,
+        /// Write the source here instead of to stdout.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+        #[structopt(parse(from_os_str))]
+        rom: PathBuf,
+    }
+}
+
+fn parse_hex(s: &str) -> Result<u16, std::num::ParseIntError> {
+    u16::from_str_radix(s.trim_start_matches("0x").trim_start_matches('$'), 16)
+}
+
+type InputScript = Vec<(u64, Vec<JoypadInput>)>;
+
+fn parse_input_script(data: &str) -> Result<InputScript, String> {
+    let mut script = Vec::new();
+    for (line_num, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue
+        }
+        let mut parts = line.split_whitespace();
+        let frame = parts.next().unwrap().parse::<u64>()
+            .map_err(|e| format!("line {}: bad frame number: {}", line_num + 1, e))?;
+        let inputs = parts.map(|p| p.parse::<JoypadInput>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("line {}: {}", line_num + 1, e))?;
+        script.push((frame, inputs));
+    }
+    script.sort_by_key(|(frame, _)| *frame);
+    Ok(script)
+}
+
+fn fail(msg: String) -> ! {
+    eprintln!("{}", msg);
+    process::exit(1)
+}
+
+fn write_frame(out_dir: &Path, frame: u64, frame_buffer: &[Color], raw: bool) {
+    let path = out_dir.join(format!("frame_{:06}.{}", frame, if raw { "rgb" } else { "png" }));
+    let result = if raw {
+        screenshot::write_raw(&path, frame_buffer)
+    } else {
+        screenshot::write_png(&path, frame_buffer)
+    };
+    result.unwrap_or_else(|e| fail(format!("Can't write {}: {}", path.display(), e)));
+}
+
+fn disasm(rom: PathBuf, sym: Option<PathBuf>, output: Option<PathBuf>) {
+    let data = fs::read(&rom).unwrap_or_else(|e| fail(format!("Can't read {}: {}", rom.display(), e)));
+    let sym = sym.or_else(|| Some(rom.with_extension("sym")).filter(|path| path.exists()));
+    let symbols = sym.map(Symbols::new);
+    let source = gameboy::disasm::disassemble(&data, symbols.as_ref());
+    match output {
+        Some(path) => fs::write(&path, source)
+            .unwrap_or_else(|e| fail(format!("Can't write {}: {}", path.display(), e))),
+        None => print!("{}", source)
+    }
+}
+
+fn main() {
+    let args = Cli::from_args();
+    if let Some(Command::Disasm { sym, output, rom }) = args.command {
+        return disasm(rom, sym, output);
+    }
+    let cartridge_path = args.cartridge_path.clone().unwrap_or_else(|| {
+        Error::with_description("A cartridge path is needed", ErrorKind::MissingRequiredArgument).exit()
+    });
+
+    if args.play.is_some() && (args.input.is_some() || args.cycles.is_some()) {
+        fail("--play can't be combined with --input or --cycles".to_string());
+    }
+    if args.record.is_some() && args.cycles.is_some() {
+        fail("--record needs whole frames, not --cycles".to_string());
+    }
+    if args.trace.is_none() && (args.trace_limit.is_some() || args.trace_stop.is_some()) {
+        fail("--trace_limit and --trace_stop need --trace".to_string());
+    }
+    let movie = args.play.as_ref().map(|path| {
+        Movie::read(path).unwrap_or_else(|e| fail(format!("Can't read {}: {}", path.display(), e)))
+    });
+
+    let script = match &args.input {
+        None => Vec::new(),
+        Some(path) => {
+            let data = fs::read_to_string(path)
+                .unwrap_or_else(|e| fail(format!("Can't read {}: {}", path.display(), e)));
+            parse_input_script(&data).unwrap_or_else(|e| fail(format!("{}: {}", path.display(), e)))
+        }
+    };
+    if let Some(out_dir) = &args.out_dir {
+        fs::create_dir_all(out_dir)
+            .unwrap_or_else(|e| fail(format!("Can't create {}: {}", out_dir.display(), e)));
+    }
+    let mut audio_file = args.audio.as_ref().map(|path| {
+        let file = File::create(path)
+            .unwrap_or_else(|e| fail(format!("Can't create {}: {}", path.display(), e)));
+        BufWriter::new(file)
+    });
+
+    let mut cartridge = Cartridge::new(cartridge_path, args.save_path.clone())
+        .unwrap_or_else(|e| fail(format!("Can't load cartridge: {}", e)));
+    let (mode, skip_boot_rom) = match &movie {
+        Some(movie) => {
+            movie.prepare(&mut cartridge).unwrap_or_else(|e| fail(format!("Can't play movie: {}", e)));
+            (movie.mode(), movie.skip_boot_rom())
+        }
+        None => (if args.dmg { Mode::DMG } else { Mode::CGB }, args.skip_boot_rom)
+    };
+    let mut recording = args.record.as_ref().map(|_| Movie::new(&cartridge, mode, skip_boot_rom));
+    let symbols = cartridge.symbols();
+    let cheats = if args.cheats {
+        cartridge.cheats().unwrap_or_else(|e| fail(format!("Can't load cheats: {}", e)))
+    } else {
+        Vec::new()
+    };
+    let mut gameboy = Gameboy::new(false, mode);
+    gameboy.boot(cartridge, skip_boot_rom);
+    if args.profile.is_some() {
+        gameboy.set_profiler(Some(Profiler::new(symbols)));
+    }
+    gameboy.set_cheats(cheats);
+    if let Some(path) = &args.cdl {
+        let mut cdl = CodeDataLog::new(&mut gameboy);
+        if path.exists() {
+            fs::read(path).map_err(|e| e.to_string()).and_then(|data| cdl.load(&data))
+                .unwrap_or_else(|e| fail(format!("Can't load {}: {}", path.display(), e)));
+        }
+        gameboy.set_cdl(Some(cdl));
+    }
+    if let Some(path) = &args.trace {
+        let file = File::create(path)
+            .unwrap_or_else(|e| fail(format!("Can't create {}: {}", path.display(), e)));
+        let mut tracer = Tracer::new(Box::new(BufWriter::new(file)));
+        if let Some(limit) = args.trace_limit {
+            tracer = tracer.stop_after(limit);
+        }
+        if let Some(pc) = args.trace_stop {
+            tracer = tracer.stop_at(pc);
+        }
+        gameboy.set_tracer(Some(tracer));
+    }
+    let mut gdb = args.gdb.map(|port| {
+        println!("Waiting for a debugger on port {}", port);
+        GdbStub::listen(("127.0.0.1", port)).unwrap_or_else(|e| fail(format!("Can't listen on {}: {}", port, e)))
+    });
+
+    let mut frame_buffer = [Color::new(31, 31, 31); GAME_WIDTH * GAME_HEIGHT];
+    let mut audio_data = Vec::with_capacity(40_000);
+    let mut pressed = Vec::new();
+    let mut script = script.into_iter().peekable();
+    let mut frame = 0;
+
+    let finished = |gameboy: &Gameboy, frame: u64| gameboy.tracer().is_some_and(Tracer::is_done) ||
+        match (&movie, args.cycles) {
+            (Some(movie), _) => frame >= movie.len() as u64,
+            (None, Some(cycles)) => gameboy.clocks() >= cycles,
+            (None, None) => frame >= args.frames
+        };
+
+    while !finished(&gameboy, frame) {
+        match &movie {
+            Some(movie) => pressed = movie.frame(frame as usize).unwrap(),
+            None => {
+                while script.peek().is_some_and(|(f, _)| *f <= frame) {
+                    pressed = script.next().unwrap().1;
+                }
+            }
+        }
+        if let Some(recording) = recording.as_mut() {
+            recording.record_frame(&pressed);
+        }
+        if let Some(stub) = gdb.as_mut() {
+            while !stub.tick(&mut gameboy, &pressed, &mut frame_buffer, &mut audio_data)
+                .unwrap_or_else(|e| fail(format!("Lost the debugger connection: {}", e))) {
+                thread::sleep(Duration::from_millis(1));
+            }
+        } else {
+            gameboy.set_pressed(&pressed);
+            while !gameboy.step(&mut frame_buffer, &mut audio_data) {
+                if finished(&gameboy, frame) { break }
+            }
+        }
+        frame += 1;
+
+        if let Some(audio_file) = audio_file.as_mut() {
+            for sample in &audio_data {
+                audio_file.write_all(&sample.to_le_bytes())
+                    .unwrap_or_else(|e| fail(format!("Can't write audio: {}", e)));
+            }
+        }
+        audio_data.clear();
+
+        if let Some(out_dir) = &args.out_dir {
+            if args.every != 0 && frame % args.every == 0 {
+                write_frame(out_dir, frame, &frame_buffer, args.raw);
+            }
+        }
+    }
+
+    if let Some(out_dir) = &args.out_dir {
+        if args.every == 0 {
+            write_frame(out_dir, frame, &frame_buffer, args.raw);
+        }
+    }
+    if let Some(mut audio_file) = audio_file {
+        audio_file.flush().unwrap_or_else(|e| fail(format!("Can't write audio: {}", e)));
+    }
+    println!("Ran {} frames, {} cycles", frame, gameboy.clocks());
+    if let Some(tracer) = gameboy.tracer() {
+        println!("Traced {} instructions", tracer.lines());
+        if let Some(e) = tracer.error() {
+            fail(format!("Tracing stopped, can't write {}: {}", args.trace.as_ref().unwrap().display(), e));
+        }
+    }
+    if let (Some(profiler), Some(path)) = (gameboy.profiler(), &args.profile) {
+        profiler.write_report(&mut std::io::stdout()).unwrap();
+        File::create(path).and_then(|mut file| profiler.write_folded(&mut file))
+            .unwrap_or_else(|e| fail(format!("Can't write {}: {}", path.display(), e)));
+    }
+    if let (Some(cdl), Some(path)) = (gameboy.cdl(), &args.cdl) {
+        let rom = cdl.domain("ROM").unwrap().len();
+        println!("Logged {} of {} ROM bytes as code and {} as data",
+                 cdl.count("ROM", CodeDataLog::EXEC_FIRST | CodeDataLog::EXEC_OPERAND), rom,
+                 cdl.count("ROM", CodeDataLog::DATA | CodeDataLog::DMA));
+        fs::write(path, cdl.save()).unwrap_or_else(|e| fail(format!("Can't write {}: {}", path.display(), e)));
+    }
+
+    let hash = gameboy::frame_hash(&frame_buffer);
+    println!("Final frame hash: {:016x}", hash);
+    if let (Some(mut recording), Some(path)) = (recording, &args.record) {
+        recording.set_final_frame_hash(hash);
+        recording.write(path).unwrap_or_else(|e| fail(format!("Can't write {}: {}", path.display(), e)));
+    }
+    if let Some(expected) = movie.as_ref().and_then(Movie::final_frame_hash) {
+        if expected != hash {
+            fail(format!("Final frame hash differs from the recording: {:016x}", expected));
+        }
+        println!("Final frame hash matches the recording");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_input_script() {
+        let script = parse_input_script("# boot\n120 start\n\n60 a b\n130\n").unwrap();
+        assert_eq!(script, vec![
+            (60, vec![JoypadInput::A, JoypadInput::B]),
+            (120, vec![JoypadInput::Start]),
+            (130, vec![])
+        ]);
+    }
+
+    #[test]
+    fn test_parse_input_script_errors() {
+        assert!(parse_input_script("x start").is_err());
+        assert!(parse_input_script("10 jump").is_err());
+    }
+}
@@ -1,9 +1,12 @@
 use std::str;
 use std::fmt;
 use std::fs;
+use std::io;
 use crate::memory::memory_map::{MemoryMappedDevice, MappedArea};
 use super::Symbols;
 use super::mbc::{build_mbc, Mbc, MbcType};
+use crate::cheats::{Cheat, RomPatch};
+use crate::save_state::{self, SaveState, StateReader, StateWriter};
 
 const ROM_BANK0_SIZE: usize = 0x4000;
 
@@ -14,44 +17,56 @@ enum Mode {
     Both
 }
 
+/// A ROM image together with its memory bank controller and save RAM.
 pub struct Cartridge<> {
     path: std::path::PathBuf,
     data: Vec<u8>,
     rom_bank0: [u8; ROM_BANK0_SIZE],
-    mbc: Box<Mbc>
+    boot_rom_mapped: bool,
+    mbc: Box<dyn Mbc>,
+    rom_patches: Vec<RomPatch>
 }
 
 impl Cartridge {
-    pub fn new(path: std::path::PathBuf, save_path: Option<std::path::PathBuf>) -> Cartridge {
-        let boot_rom = include_bytes!("cgb_boot.bin");
-        let data = fs::read(path.clone()).unwrap();
-        let mut rom_bank0 = [0; ROM_BANK0_SIZE];
-
-        for i in 0..boot_rom.len() {
-            rom_bank0[i] = boot_rom[i];
+    /// Loads the ROM at `path`, and the battery backed RAM at `save_path` if
+    /// one is given. A `.sym` file next to the ROM is picked up as symbols.
+    pub fn new(path: std::path::PathBuf, save_path: Option<std::path::PathBuf>) -> io::Result<Cartridge> {
+        let read = |path: &std::path::Path| fs::read(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)));
+        let data = read(&path)?;
+        if data.len() < 2 * ROM_BANK0_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: ROM is smaller than 32K", path.display())));
         }
-        for i in 0x100..0x14F {
-            rom_bank0[i] = data[i];
-        }
-
-        let mut mbc = build_mbc(data[0x147]);
+        let mut cartridge = Self::from_data(path, data);
         if let Some(save_path) = save_path {
-            mbc.load_ram(&fs::read(save_path).unwrap());
+            cartridge.mbc.load_ram(&read(&save_path)?);
         }
+        Ok(cartridge)
+    }
 
-        Self {
-            data, path, rom_bank0, mbc
-        }
+    fn from_data(path: std::path::PathBuf, data: Vec<u8>) -> Cartridge {
+        let mbc = build_mbc(data[0x147]);
+        let mut cartridge = Self {
+            data, path, rom_bank0: [0; ROM_BANK0_SIZE], boot_rom_mapped: false, mbc, rom_patches: Vec::new()
+        };
+        cartridge.map_boot_rom();
+        cartridge
+    }
+
+    /// The path the ROM was loaded from.
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
     }
 
     pub fn mapped_areas() -> [MappedArea; 3] {
-        return [
+        [
             MappedArea(0xFF50, 0x1),
             MappedArea(0x0000, 0x8000),
             MappedArea(0xA000, 0x2000)
         ]
     }
 
+    /// Loads the `.sym` file that sits next to the ROM, if there is one.
     pub fn symbols(&self) -> Option<Symbols> {
         let mut symbol_path = self.path.clone();
         symbol_path.set_extension("sym");
@@ -62,28 +77,127 @@ impl Cartridge {
         }
     }
 
-    pub fn clear_boot_rom(&mut self) {
-        for i in 0..self.rom_bank0.len() {
-            self.rom_bank0[i] = self.data[i];
+    /// Loads the `.cht` cheat file that sits next to the ROM. There are no
+    /// cheats without one.
+    pub fn cheats(&self) -> Result<Vec<Cheat>, String> {
+        let mut cheat_path = self.path.clone();
+        cheat_path.set_extension("cht");
+        if !cheat_path.exists() {
+            return Ok(Vec::new());
         }
+        let data = fs::read_to_string(&cheat_path).map_err(|e| format!("{}: {}", cheat_path.display(), e))?;
+        Cheat::parse_file(&data).map_err(|e| format!("{}: {}", cheat_path.display(), e))
+    }
+
+    /// Sets the Game Genie codes applied to ROM reads.
+    pub(crate) fn set_rom_patches(&mut self, rom_patches: Vec<RomPatch>) {
+        self.rom_patches = rom_patches;
     }
 
+    fn read_rom(&self, addr: u16) -> u8 {
+        let byte = match addr {
+            0x0000 ..= 0x3FFF => self.rom_bank0[addr as usize],
+            _ => self.data[self.rom_bank1_start() + addr as usize - 0x4000]
+        };
+        // The compare byte is checked against the bank mapped right now.
+        self.rom_patches.iter().find_map(|patch| patch.apply(addr, byte)).unwrap_or(byte)
+    }
+
+    fn map_boot_rom(&mut self) {
+        let boot_rom = include_bytes!("cgb_boot.bin");
+        self.rom_bank0 = [0; ROM_BANK0_SIZE];
+        self.rom_bank0[..boot_rom.len()].copy_from_slice(boot_rom);
+        self.rom_bank0[0x100..0x14F].copy_from_slice(&self.data[0x100..0x14F]);
+        self.boot_rom_mapped = true;
+    }
+
+    pub fn clear_boot_rom(&mut self) {
+        self.rom_bank0.copy_from_slice(&self.data[..ROM_BANK0_SIZE]);
+        self.boot_rom_mapped = false;
+    }
+
+    /// Whether the header says the game runs on a Game Boy Color.
     pub fn cgb_compatible(&self) -> bool {
-        match self.cgb_support() {
-            Mode::DMG => false,
-            _ => true
-        }
+        !matches!(self.cgb_support(), Mode::DMG)
+    }
+
+    /// Replaces the cartridge RAM, as if it had been loaded from a `.sav`.
+    pub fn load_ram(&mut self, data: &[u8]) {
+        self.mbc.load_ram(data);
     }
 
+    /// The header checksum at 0x14D.
+    pub fn header_checksum(&self) -> u8 {
+        self.data[0x14D]
+    }
+
+    /// The checksum over the whole ROM at 0x14E-0x14F.
+    pub fn global_checksum(&self) -> u16 {
+        ((self.data[0x14E] as u16) << 8) | self.data[0x14F] as u16
+    }
+
+    /// Returns the cartridge RAM.
     pub fn dump_ram(&self) -> Vec<u8> {
         self.mbc.dump_ram()
     }
 
     fn rom_bank1_start(&self) -> usize {
-        (self.mbc.rom_bank_num() as usize * 0x4000) % self.data.len()
+        (self.mbc.rom_bank_num() * 0x4000) % self.data.len()
+    }
+
+    /// The ROM bank mapped at 0x4000-0x7FFF.
+    pub fn rom_bank(&self) -> usize {
+        self.rom_bank1_start() / 0x4000
+    }
+
+    /// Every RAM bank, one after another.
+    pub fn ram(&mut self) -> &mut [u8] {
+        self.mbc.ram()
     }
 
-    fn title(&self) -> &str {
+    /// The offset in the ROM file of the byte read at `addr`, or None when
+    /// it comes from the boot ROM.
+    pub fn rom_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x0100 ..= 0x014E => Some(addr as usize),
+            0x0000 ..= 0x3FFF if self.boot_rom_mapped => None,
+            0x0000 ..= 0x3FFF => Some(addr as usize),
+            0x4000 ..= 0x7FFF => Some(self.rom_bank1_start() + addr as usize - 0x4000),
+            _ => None
+        }
+    }
+
+    /// The offset in the cartridge RAM of the byte at `addr`.
+    pub fn ram_offset(&mut self, addr: u16) -> Option<usize> {
+        let offset = self.ram_bank() * 0x2000 + addr as usize - 0xA000;
+        if offset < self.ram().len() { Some(offset) } else { None }
+    }
+
+    /// The size of the ROM file.
+    pub fn rom_len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// The ROM file, without any Game Genie patches.
+    pub fn rom(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Changes a byte of the ROM file, as a ROM hack would.
+    pub fn patch_rom(&mut self, offset: usize, byte: u8) {
+        self.data[offset] = byte;
+        if offset < ROM_BANK0_SIZE && self.rom_offset(offset as u16) == Some(offset) {
+            self.rom_bank0[offset] = byte;
+        }
+    }
+
+    /// The RAM bank mapped at 0xA000-0xBFFF.
+    pub fn ram_bank(&self) -> usize {
+        self.mbc.ram_bank_num()
+    }
+
+    /// The title from the cartridge header.
+    pub fn title(&self) -> &str {
         str::from_utf8(&self.data[0x134..=0x143]).unwrap_or("UNKNOWN")
     }
 
@@ -111,7 +225,7 @@ impl Cartridge {
 
 impl Clone for Cartridge {
     fn clone(&self) -> Self {
-        let mut cartridge = Cartridge::new(self.path.clone(), None);
+        let mut cartridge = Cartridge::from_data(self.path.clone(), self.data.clone());
         cartridge.mbc.load_ram(&self.mbc.dump_ram());
         cartridge
     }
@@ -131,14 +245,38 @@ impl MemoryMappedDevice for Cartridge {
     fn get8(&self, addr: u16) -> u8 {
         match addr {
             0xFF50 => 0,
-            0x0000 ... 0x3FFF => self.rom_bank0[addr as usize],
-            0x4000 ... 0x7FFF => self.data[self.rom_bank1_start() + addr as usize - 0x4000],
-            0xA000 ... 0xBFFF => { self.mbc.get8(addr) }
+            0x0000 ..= 0x7FFF => self.read_rom(addr),
+            0xA000 ..= 0xBFFF => { self.mbc.get8(addr) }
             _ => { panic!("Can't read from Cartridge at 0x{:X}.", addr); }
         }
     }
 }
 
+impl SaveState for Cartridge {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.data[0x134..=0x143]);
+        w.write_u8(self.header_checksum());
+        w.write_u16(self.global_checksum());
+        w.write_bool(self.boot_rom_mapped);
+        self.mbc.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        let mut title = [0; 0x10];
+        r.read_bytes_into(&mut title)?;
+        if title[..] != self.data[0x134..=0x143] || r.read_u8()? != self.header_checksum() ||
+            r.read_u16()? != self.global_checksum() {
+            return Err(save_state::SaveStateError::RomMismatch)
+        }
+        if r.read_bool()? {
+            self.map_boot_rom();
+        } else {
+            self.clear_boot_rom();
+        }
+        self.mbc.load_state(r)
+    }
+}
+
 impl fmt::Debug for Cartridge {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{{ title: {}, type: {:?}, rom_size: {}, ram_size: {}, cgb_support: {:?} }}",
@@ -148,3 +286,31 @@ impl fmt::Debug for Cartridge {
         )
     }
 }
+
+/// A 32K ROM only cartridge with `program` at the entry point, for tests.
+#[cfg(test)]
+pub fn test_cartridge(name: &str, program: &[u8]) -> Cartridge {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x100 + program.len()].copy_from_slice(program);
+    let path = std::env::temp_dir().join(format!("gameboy-{}-{}.gb", name, std::process::id()));
+    fs::write(&path, rom).unwrap();
+    let cartridge = Cartridge::new(path.clone(), None).unwrap();
+    fs::remove_file(path).unwrap();
+    cartridge
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_errors() {
+        let path = std::env::temp_dir().join(format!("gameboy-missing-{}.gb", std::process::id()));
+        assert_eq!(Cartridge::new(path.clone(), None).unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        fs::write(&path, [0; 0x150]).unwrap();
+        let result = Cartridge::new(path.clone(), None);
+        fs::remove_file(path).unwrap();
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::save_state::{self, SaveState, SaveStateError, StateReader, StateWriter};
+
 #[derive(Debug)]
 pub enum MbcType {
     RomOnly,
@@ -11,13 +13,27 @@ enum Mode {
     Ram
 }
 
-pub trait Mbc {
+impl SaveState for Mode {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(match self { Mode::Rom => false, Mode::Ram => true });
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        *self = if r.read_bool()? { Mode::Ram } else { Mode::Rom };
+        Ok(())
+    }
+}
+
+pub trait Mbc: SaveState {
     fn rom_bank_num(&self) -> usize;
+    fn ram_bank_num(&self) -> usize;
     fn get8(&self, addr: u16) -> u8;
     fn set8(&mut self, addr: u16, byte: u8);
     fn mbc_type(&self) -> MbcType;
     fn dump_ram(&self) -> Vec<u8>;
     fn load_ram(&mut self, data: &[u8]);
+    /// Every RAM bank, one after another.
+    fn ram(&mut self) -> &mut [u8];
 }
 
 struct RomOnly {
@@ -37,8 +53,12 @@ impl Mbc for RomOnly {
         1
     }
 
-    fn set8(&mut self, addr: u16, _byte: u8) {
-        debug!("set8 {:x} in RomOnly", addr);
+    fn ram_bank_num(&self) -> usize {
+        0
+    }
+
+    fn set8(&mut self, _addr: u16, _byte: u8) {
+        debug!("set8 {:x} in RomOnly", _addr);
     }
 
     fn get8(&self, addr: u16) -> u8 {
@@ -54,7 +74,21 @@ impl Mbc for RomOnly {
         Vec::new()
     }
 
-    fn load_ram(&mut self, data: &[u8]) { }
+    fn load_ram(&mut self, _data: &[u8]) { }
+
+    fn ram(&mut self) -> &mut [u8] {
+        &mut []
+    }
+}
+
+impl SaveState for RomOnly {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.ram_bank);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        r.read_bytes_into(&mut self.ram_bank)
+    }
 }
 
 struct Mbc1 {
@@ -98,29 +132,33 @@ impl Mbc for Mbc1 {
         }
     }
 
+    fn ram_bank_num(&self) -> usize {
+        self.ram_bank_offset() / 0x2000
+    }
+
     fn get8(&self, addr: u16) -> u8 {
         self.ram[self.ram_bank_offset() + (addr as usize) - 0xA000]
     }
 
     fn set8(&mut self, addr: u16, byte: u8) {
         match addr {
-            0x0000 ... 0x1FFF => {
+            0x0000 ..= 0x1FFF => {
                 self.ram_bank_enabled = byte & 0x0A == 0x0A;
             }
-            0x2000 ... 0x3FFF => {
+            0x2000 ..= 0x3FFF => {
                 self.five_bit_reg = byte & 0x1F;
             }
-            0x4000 ... 0x5FFF => {
+            0x4000 ..= 0x5FFF => {
                 self.two_bit_reg = byte & 0x03;
             }
-            0x6000 ... 0x7FFF => {
+            0x6000 ..= 0x7FFF => {
                 self.mode = if byte & 0x1  == 0 {
                     Mode::Rom
                 } else {
                     Mode::Ram
                 }
             }
-            0xA000 ... 0xBFFF => {
+            0xA000 ..= 0xBFFF => {
                 let offset = self.ram_bank_offset();
                 self.ram[offset + (addr as usize) - 0xA000] = byte;
             }
@@ -139,6 +177,31 @@ impl Mbc for Mbc1 {
     fn load_ram(&mut self, data: &[u8]) {
         self.ram = data.to_owned();
     }
+
+    fn ram(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+impl SaveState for Mbc1 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.five_bit_reg);
+        w.write_u8(self.two_bit_reg);
+        w.write_bool(self.ram_bank_enabled);
+        self.mode.save_state(w);
+        w.write_bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        self.five_bit_reg = r.read_u8()?;
+        self.two_bit_reg = r.read_u8()?;
+        if self.five_bit_reg > 0x1F || self.two_bit_reg > 0x03 {
+            return Err(SaveStateError::Invalid("MBC1 bank register"))
+        }
+        self.ram_bank_enabled = r.read_bool()?;
+        self.mode.load_state(r)?;
+        r.read_bytes_into(&mut self.ram)
+    }
 }
 
 struct Mbc3 {
@@ -167,15 +230,22 @@ impl Mbc for Mbc3 {
         }
     }
 
+    fn ram_bank_num(&self) -> usize {
+        match self.ram_rtc_bank_reg {
+            0x0 ..= 0x3 => self.ram_rtc_bank_reg as usize,
+            _ => 0
+        }
+    }
+
     fn get8(&self, addr: u16) -> u8 {
         match addr {
-            0xA000 ... 0xBFFF => {
+            0xA000 ..= 0xBFFF => {
                 match self.ram_rtc_bank_reg {
-                    0x0 ... 0x3 => {
+                    0x0 ..= 0x3 => {
                         let bank_offset = (self.ram_rtc_bank_reg as usize) * 0x2000;
                         self.ram[bank_offset + (addr as usize) - 0xA000]
                     }
-                    0x8 ... 0xC => {
+                    0x8 ..= 0xC => {
 //                        debug!("GET RTC REGISTER");
                         0
                     }
@@ -188,25 +258,25 @@ impl Mbc for Mbc3 {
 
     fn set8(&mut self, addr: u16, byte: u8) {
         match addr {
-            0x0000 ... 0x1FFF => {
+            0x0000 ..= 0x1FFF => {
                 self.ram_rtc_bank_enabled = byte & 0x0A == 0x0A;
             }
-            0x2000 ... 0x3FFF => {
+            0x2000 ..= 0x3FFF => {
                 self.rom_bank_reg = byte & 0x7F;
             }
-            0x4000 ... 0x5FFF => {
+            0x4000 ..= 0x5FFF => {
                 self.ram_rtc_bank_reg = byte;
             }
-            0x6000 ... 0x7FFF => {
+            0x6000 ..= 0x7FFF => {
 //                debug!("LATCH");
             }
-            0xA000 ... 0xBFFF => {
+            0xA000 ..= 0xBFFF => {
                 match self.ram_rtc_bank_reg {
-                    0x0 ... 0x3 => {
+                    0x0 ..= 0x3 => {
                         let bank_offset = (self.ram_rtc_bank_reg as usize) * 0x2000;
                         self.ram[bank_offset + (addr as usize) - 0xA000] = byte;
                     }
-                    0x8 ... 0xC => {
+                    0x8 ..= 0xC => {
 //                        debug!("SET RTC REGISTER");
                     }
                     _ => panic!("Invalid ram_rtc_bank_reg {:X}", self.ram_rtc_bank_reg)
@@ -227,6 +297,29 @@ impl Mbc for Mbc3 {
     fn load_ram(&mut self, data: &[u8]) {
         self.ram = data.to_owned();
     }
+
+    fn ram(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+}
+
+impl SaveState for Mbc3 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.ram_rtc_bank_enabled);
+        w.write_u8(self.rom_bank_reg);
+        w.write_u8(self.ram_rtc_bank_reg);
+        w.write_bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        self.ram_rtc_bank_enabled = r.read_bool()?;
+        self.rom_bank_reg = r.read_u8()?;
+        self.ram_rtc_bank_reg = r.read_u8()?;
+        if self.rom_bank_reg > 0x7F || !matches!(self.ram_rtc_bank_reg, 0x0 ..= 0x3 | 0x8 ..= 0xC) {
+            return Err(SaveStateError::Invalid("MBC3 bank register"))
+        }
+        r.read_bytes_into(&mut self.ram)
+    }
 }
 
 struct Mbc5 {
@@ -264,32 +357,36 @@ impl Mbc for Mbc5 {
         }
     }
 
+    fn ram_bank_num(&self) -> usize {
+        self.ram_bank_offset() / 0x2000
+    }
+
     fn get8(&self, addr: u16) -> u8 {
         self.ram[self.ram_bank_offset() + (addr as usize) - 0xA000]
     }
 
     fn set8(&mut self, addr: u16, byte: u8) {
         match addr {
-            0x0000 ... 0x1FFF => {
+            0x0000 ..= 0x1FFF => {
                 self.ram_bank_enabled = byte & 0x0A == 0x0A;
             }
-            0x2000 ... 0x2FFF => {
+            0x2000 ..= 0x2FFF => {
                 self.rom_bank_num = (self.rom_bank_num & 0x100) | (byte as u16);
             }
-            0x3000 ... 0x3FFF => {
+            0x3000 ..= 0x3FFF => {
                 self.rom_bank_num = ((byte as u16 & 0x1) << 8) | (self.rom_bank_num & 0xFF);
             }
-            0x4000 ... 0x5FFF => {
+            0x4000 ..= 0x5FFF => {
                 self.ram_bank_num = byte & 0x03;
             }
-            0x6000 ... 0x7FFF => {
+            0x6000 ..= 0x7FFF => {
                 self.mode = if byte & 0x1  == 0 {
                     Mode::Rom
                 } else {
                     Mode::Ram
                 }
             }
-            0xA000 ... 0xBFFF => {
+            0xA000 ..= 0xBFFF => {
                 let offset = self.ram_bank_offset();
                 self.ram[offset + (addr as usize) - 0xA000] = byte;
             }
@@ -309,15 +406,63 @@ impl Mbc for Mbc5 {
         self.ram = data.to_owned();
         debug!("LOADED: {:X}", self.ram.len());
     }
+
+    fn ram(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
 }
 
-pub fn build_mbc(byte: u8) -> Box<Mbc> {
+impl SaveState for Mbc5 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u16(self.rom_bank_num);
+        w.write_u8(self.ram_bank_num);
+        w.write_bool(self.ram_bank_enabled);
+        self.mode.save_state(w);
+        w.write_bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        self.rom_bank_num = r.read_u16()?;
+        self.ram_bank_num = r.read_u8()?;
+        if self.rom_bank_num > 0x1FF || self.ram_bank_num > 0x03 {
+            return Err(SaveStateError::Invalid("MBC5 bank register"))
+        }
+        self.ram_bank_enabled = r.read_bool()?;
+        self.mode.load_state(r)?;
+        r.read_bytes_into(&mut self.ram)
+    }
+}
+
+pub fn build_mbc(byte: u8) -> Box<dyn Mbc> {
     match byte {
         0 => Box::new(RomOnly::new()),
-        1...3 => Box::new(Mbc1::new()),
-        5...6 => Box::new(Mbc1::new()),
-        0xF...0x13 => Box::new(Mbc3::new()),
-        0x19...0x1E => Box::new(Mbc5::new()),
+        1..=3 => Box::new(Mbc1::new()),
+        5..=6 => Box::new(Mbc1::new()),
+        0xF..=0x13 => Box::new(Mbc3::new()),
+        0x19..=0x1E => Box::new(Mbc5::new()),
         _ => panic!("Unsupported mbc type: {:X}", byte)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(mbc: &mut dyn Mbc, state: &[u8]) -> save_state::Result<()> {
+        mbc.load_state(&mut StateReader::new(state)?)
+    }
+
+    #[test]
+    fn test_load_bad_bank_register() {
+        // Each MBC with the offset of its RAM bank register past the header.
+        let header = StateWriter::new().into_inner().len();
+        for (mut mbc, offset, bad) in [(build_mbc(0x01), 1, 0x04), (build_mbc(0x13), 2, 0x05), (build_mbc(0x19), 2, 0x04)] {
+            let mut w = StateWriter::new();
+            mbc.save_state(&mut w);
+            let mut state = w.into_inner();
+            assert_eq!(load(mbc.as_mut(), &state), Ok(()));
+            state[header + offset] = bad;
+            assert!(matches!(load(mbc.as_mut(), &state), Err(SaveStateError::Invalid(_))));
+        }
+    }
+}
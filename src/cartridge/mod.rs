@@ -3,4 +3,6 @@ mod symbols;
 mod mbc;
 
 pub use self::cartridge::Cartridge;
+#[cfg(test)]
+pub use self::cartridge::test_cartridge;
 pub use self::symbols::Symbols;
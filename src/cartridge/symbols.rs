@@ -1,39 +1,161 @@
-use usize;
 use std::fs;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-#[derive(PartialEq, Eq, Hash, Debug)]
-struct Loc(usize, usize);
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Copy, Clone)]
+struct Loc(usize, u16);
 
+/// Labels read from a symbol file.
+///
+/// RGBDS `.sym`, no$gmb/BGB and WLA-DX files all list labels as
+/// `bank:address name`. Files with `[section]` headers only have their
+/// `[labels]` section read. Each label belongs to a bank, so the same
+/// address can carry different labels in different ROM, WRAM or SRAM banks.
 pub struct Symbols {
-    labels: HashMap<Loc, String>
+    labels: HashMap<Loc, String>,
+    addresses: HashMap<String, Loc>,
+    // Labels without a `.`, in address order.
+    globals: BTreeMap<Loc, String>
 }
 
 impl Symbols {
     pub fn new(path: std::path::PathBuf) -> Self {
-        let data = fs::read_to_string(path).unwrap();
-        let lines = data.lines().filter(|s| !s.starts_with(";"));
-        let mut label_lines = lines.skip_while(|s| !s.starts_with("[labels]"));
-        label_lines.next();
+        Self::parse(&fs::read_to_string(path).unwrap())
+    }
 
+    pub fn parse(data: &str) -> Self {
         let mut labels = HashMap::new();
+        let mut addresses = HashMap::new();
+        let mut globals = BTreeMap::new();
+        let mut in_labels = true;
 
-        for line in label_lines {
-            if line.contains(":") {
-                let parts: Vec<&str> = line.split(' ').collect();
-                let loc_vs: Vec<&str> = parts[0].split(':').collect();
-                let loc = Loc(
-                    usize::from_str_radix(loc_vs[0], 16).unwrap(),
-                    usize::from_str_radix(loc_vs[1], 16).unwrap()
-                );
-                labels.insert(loc, parts[1].to_owned());
+        for line in data.lines() {
+            let line = line.split(';').next().unwrap().trim();
+            if line.starts_with('[') {
+                in_labels = line.eq_ignore_ascii_case("[labels]");
+                continue;
+            }
+            if !in_labels {
+                continue;
+            }
+            if let Some((loc, name)) = parse_label(line) {
+                // The first label at a location wins, which is the global
+                // label when local labels follow it.
+                labels.entry(loc).or_insert_with(|| name.to_owned());
+                addresses.entry(name.to_owned()).or_insert(loc);
+                if !name.contains('.') {
+                    globals.entry(loc).or_insert_with(|| name.to_owned());
+                }
             }
         }
 
-        Self { labels }
+        Self { labels, addresses, globals }
+    }
+
+    /// The label at `addr` when `bank` is mapped there.
+    pub fn get(&self, bank: usize, addr: u16) -> Option<&str> {
+        self.labels.get(&Loc(bank, addr)).map(String::as_str)
+    }
+
+    /// The bank and address of the label called `name`.
+    pub fn find(&self, name: &str) -> Option<(usize, u16)> {
+        self.addresses.get(name).map(|loc| (loc.0, loc.1))
+    }
+
+    /// The closest label without a `.` at or before `addr`, in the same
+    /// bank and memory area, with its address. This is the routine that
+    /// `addr` is part of.
+    pub fn enclosing(&self, bank: usize, addr: u16) -> Option<(u16, &str)> {
+        let start = match addr {
+            0x0000 ..= 0x3FFF => 0x0000,
+            0x4000 ..= 0x7FFF => 0x4000,
+            0x8000 ..= 0x9FFF => 0x8000,
+            0xA000 ..= 0xBFFF => 0xA000,
+            0xC000 ..= 0xCFFF => 0xC000,
+            0xD000 ..= 0xDFFF => 0xD000,
+            0xE000 ..= 0xFF7F => 0xE000,
+            _ => 0xFF80
+        };
+        self.globals.range(Loc(bank, start)..=Loc(bank, addr)).next_back()
+            .map(|(loc, name)| (loc.1, name.as_str()))
+    }
+
+    /// The label at `addr`, or the routine it is part of with an offset,
+    /// e.g. `Main+$12`.
+    pub fn describe(&self, bank: usize, addr: u16) -> Option<String> {
+        match self.get(bank, addr) {
+            Some(label) => Some(label.to_string()),
+            None => self.enclosing(bank, addr).map(|(start, label)| format!("{}+${:X}", label, addr - start))
+        }
+    }
+
+    /// Every label with its bank and address, sorted by bank, address and
+    /// then name.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, u16, &str)> {
+        let mut sorted: Vec<_> = self.addresses.iter().map(|(name, loc)| (loc.0, loc.1, name.as_str())).collect();
+        sorted.sort();
+        sorted.into_iter()
     }
+}
+
+fn parse_label(line: &str) -> Option<(Loc, &str)> {
+    let mut parts = line.split_whitespace();
+    let (bank, addr) = parts.next()?.split_once(':')?;
+    let name = parts.next()?;
+    let bank = usize::from_str_radix(bank, 16).ok()?;
+    let addr = u16::from_str_radix(addr, 16).ok()?;
+    Some((Loc(bank, addr), name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rgbds() {
+        let symbols = Symbols::parse("; File generated by rgblink\n\
+                                      00:0150 Main\n\
+                                      00:0158 Main.loop\n\
+                                      01:4000 BankOne\n\
+                                      02:4000 BankTwo\n\
+                                      00:C000 wCounter\n\
+                                      03:D000 wBank3\n\
+                                      01:A000 sSave ; comment\n");
+        assert_eq!(symbols.get(0, 0x150), Some("Main"));
+        assert_eq!(symbols.get(1, 0x150), None);
+        assert_eq!(symbols.get(1, 0x4000), Some("BankOne"));
+        assert_eq!(symbols.get(2, 0x4000), Some("BankTwo"));
+        assert_eq!(symbols.get(3, 0xD000), Some("wBank3"));
+        assert_eq!(symbols.get(1, 0xA000), Some("sSave"));
+        assert_eq!(symbols.find("Main.loop"), Some((0, 0x158)));
+        assert_eq!(symbols.find("BankTwo"), Some((2, 0x4000)));
+        assert_eq!(symbols.find("Nowhere"), None);
+
+        assert_eq!(symbols.enclosing(0, 0x15A), Some((0x150, "Main")));
+        assert_eq!(symbols.enclosing(0, 0x14F), None);
+        assert_eq!(symbols.enclosing(1, 0x4123), Some((0x4000, "BankOne")));
+        assert_eq!(symbols.enclosing(0, 0xC010), Some((0xC000, "wCounter")));
+        assert_eq!(symbols.enclosing(0, 0x4000), None);
+        assert_eq!(symbols.describe(0, 0x158).as_deref(), Some("Main.loop"));
+        assert_eq!(symbols.describe(0, 0x15A).as_deref(), Some("Main+$A"));
+    }
+
+    #[test]
+    fn test_sectioned_formats() {
+        // no$gmb/BGB
+        let symbols = Symbols::parse("[labels]\n0000:0100 Entry\n0001:4000 Banked\n");
+        assert_eq!(symbols.get(0, 0x100), Some("Entry"));
+        assert_eq!(symbols.get(1, 0x4000), Some("Banked"));
 
-    pub fn get(&self, addr: usize) -> Option<&String> {
-        self.labels.get(&Loc(1, addr))
+        // WLA-DX, whose other sections mustn't be read as labels.
+        let symbols = Symbols::parse("; wla symbolic information\n\
+                                      [labels]\n\
+                                      00:0150 main\n\
+                                      [definitions]\n\
+                                      00000010 CONSTANT\n\
+                                      [addr-to-line mapping]\n\
+                                      00:0150 0000:00000012\n");
+        assert_eq!(symbols.get(0, 0x150), Some("main"));
+        assert_eq!(symbols.find("CONSTANT"), None);
+        assert_eq!(symbols.find("0000:00000012"), None);
     }
 }
@@ -0,0 +1,211 @@
+use std::convert::{TryFrom, TryInto};
+use crate::gameboy::Gameboy;
+use crate::memory::memory_map::MemoryMappedDeviceManager;
+
+const HEADER: &str = "BIZHAWK-CDL-2";
+const SUB_TYPE: &str = "GB";
+
+/// Records how every byte of ROM, cartridge RAM, WRAM and HRAM was
+/// accessed: as the first byte of an executed instruction, as one of its
+/// operands, as data or as a DMA source.
+///
+/// ROM and cartridge RAM are logged at their offset in the file, through
+/// whichever bank is mapped, and WRAM with bank N at N * $1000. Logs are
+/// saved in BizHawk's CDL format, with its ROM, HRAM, WRAM and CartRAM
+/// domains, so they can be loaded there and by tools that read it.
+pub struct CodeDataLog {
+    domains: Vec<(&'static str, Vec<u8>)>
+}
+
+impl CodeDataLog {
+    pub const EXEC_FIRST: u8 = 0x01;
+    pub const EXEC_OPERAND: u8 = 0x02;
+    pub const DATA: u8 = 0x04;
+    /// Not used by BizHawk, which leaves DMA reads unlogged.
+    pub const DMA: u8 = 0x08;
+
+    /// An empty log sized for the cartridge and mode of `gameboy`.
+    pub fn new(gameboy: &mut Gameboy) -> Self {
+        let (rom, wram, cart_ram) = gameboy.memory_sizes();
+        CodeDataLog {
+            domains: vec![
+                ("ROM", vec![0; rom]),
+                ("HRAM", vec![0; 0x80]),
+                ("WRAM", vec![0; wram]),
+                ("CartRAM", vec![0; cart_ram])
+            ]
+        }
+    }
+
+    /// The flags of each byte in a domain, by BizHawk's name for it.
+    pub fn domain(&self, name: &str) -> Option<&[u8]> {
+        self.domains.iter().find(|(n, _)| *n == name).map(|(_, flags)| flags.as_slice())
+    }
+
+    /// The domains with the flags of each byte.
+    pub fn domains(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.domains.iter().map(|(name, flags)| (*name, flags.as_slice()))
+    }
+
+    pub(crate) fn mark(&mut self, devices: &mut MemoryMappedDeviceManager, addr: u16, flag: u8) {
+        let (domain, offset) = match addr {
+            0x0000 ..= 0x7FFF => match devices.cartridge().rom_offset(addr) {
+                Some(offset) => (0, offset),
+                None => return
+            },
+            0xA000 ..= 0xBFFF => match devices.cartridge().ram_offset(addr) {
+                Some(offset) => (3, offset),
+                None => return
+            },
+            0xC000 ..= 0xCFFF => (2, addr as usize - 0xC000),
+            0xD000 ..= 0xDFFF => (2, devices.ram_bank1().bank() * 0x1000 + addr as usize - 0xD000),
+            0xFF80 ..= 0xFFFE => (1, addr as usize - 0xFF80),
+            _ => return
+        };
+        if let Some(flags) = self.domains[domain].1.get_mut(offset) {
+            *flags |= flag;
+        }
+    }
+
+    /// How many bytes of a domain have any of `flags` set.
+    pub fn count(&self, name: &str, flags: u8) -> usize {
+        self.domain(name).map_or(0, |domain| domain.iter().filter(|f| **f & flags != 0).count())
+    }
+
+    /// The log in BizHawk's format.
+    pub fn save(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_string(&mut out, HEADER);
+        write_string(&mut out, &format!("{:<15}", SUB_TYPE));
+        out.extend_from_slice(&(self.domains.len() as i32).to_le_bytes());
+        for (name, flags) in &self.domains {
+            write_string(&mut out, name);
+            out.extend_from_slice(&(flags.len() as i32).to_le_bytes());
+            out.extend_from_slice(flags);
+        }
+        out
+    }
+
+    /// Adds the flags from a saved log to this one. Its domains must be the
+    /// same sizes, which they are when it was made with the same ROM.
+    pub fn load(&mut self, data: &[u8]) -> Result<(), String> {
+        let mut r = Reader { data, pos: 0 };
+        if r.string()? != HEADER {
+            return Err("Not a BizHawk CDL file".to_string());
+        }
+        let sub_type = r.string()?;
+        if sub_type.trim_end() != SUB_TYPE {
+            return Err(format!("CDL file is for {}, not GB", sub_type.trim_end()));
+        }
+        for _ in 0..r.i32()? {
+            let name = r.string()?;
+            let len = usize::try_from(r.i32()?).map_err(|_| format!("Bad length for CDL domain {}", name))?;
+            let flags = r.bytes(len)?;
+            let (_, domain) = self.domains.iter_mut().find(|(n, _)| *n == name)
+                .ok_or_else(|| format!("Unknown CDL domain {}", name))?;
+            if domain.len() != len {
+                return Err(format!("CDL domain {} is {} bytes, expected {}", name, len, domain.len()));
+            }
+            for (flag, saved) in domain.iter_mut().zip(flags) {
+                *flag |= saved;
+            }
+        }
+        Ok(())
+    }
+}
+
+// A string as written by .NET's BinaryWriter, prefixed with its length in
+// 7 bit groups.
+fn write_string(out: &mut Vec<u8>, s: &str) {
+    let mut len = s.len();
+    while len >= 0x80 {
+        out.push((len as u8 & 0x7F) | 0x80);
+        len >>= 7;
+    }
+    out.push(len as u8);
+    out.extend_from_slice(s.as_bytes());
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(len).ok_or("CDL file is truncated")?;
+        let bytes = self.data.get(self.pos..end).ok_or("CDL file is truncated")?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        // A 32 bit length takes at most 5 groups.
+        let mut len = 0;
+        for shift in (0..35).step_by(7) {
+            let byte = self.bytes(1)?[0];
+            len |= ((byte & 0x7F) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return String::from_utf8(self.bytes(len)?.to_vec()).map_err(|_| "Bad string in CDL file".to_string());
+            }
+        }
+        Err("Bad string length in CDL file".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::{booted_gameboy, test_frame_buffer, Mode};
+
+    #[test]
+    fn test_cdl() {
+        let program = [
+            0xFA, 0x50, 0x01, // LD A,($0150)
+            0x3E, 0xC0,       // LD A,$C0
+            0xC3, 0x80, 0xFF  // JP $FF80
+        ];
+        let mut gameboy = booted_gameboy("cdl", &program, Mode::CGB);
+        // LDH ($46),A starts OAM DMA from $C000; JR -2 waits in HRAM
+        for (i, &byte) in [0xE0, 0x46, 0x18, 0xFE].iter().enumerate() {
+            gameboy.write8(0xFF80 + i as u16, byte);
+        }
+        let cdl = CodeDataLog::new(&mut gameboy);
+        gameboy.set_cdl(Some(cdl));
+        let mut frame_buffer = test_frame_buffer();
+        for _ in 0..100 {
+            gameboy.step(&mut frame_buffer, &mut Vec::new());
+        }
+        let cdl = gameboy.take_cdl().unwrap();
+
+        let rom = cdl.domain("ROM").unwrap();
+        assert_eq!(rom.len(), 0x8000);
+        assert_eq!(&rom[0x100..0x10A], &[1, 2, 2, 1, 2, 1, 2, 2, 0, 0]);
+        assert_eq!(rom[0x150], CodeDataLog::DATA);
+        let wram = cdl.domain("WRAM").unwrap();
+        assert_eq!(wram.len(), 0x8000);
+        assert!(wram[..0xA0].iter().all(|flags| *flags == CodeDataLog::DMA));
+        assert_eq!(wram[0xA0], 0);
+        assert_eq!(cdl.domain("CartRAM").unwrap().len(), 0);
+        assert_eq!(cdl.count("ROM", CodeDataLog::EXEC_FIRST | CodeDataLog::EXEC_OPERAND), 8);
+
+        let saved = cdl.save();
+        assert_eq!(&saved[..14], b"\x0DBIZHAWK-CDL-2");
+        assert_eq!(&saved[14..30], b"\x0FGB             ");
+        let mut loaded = CodeDataLog::new(&mut gameboy);
+        loaded.load(&saved).unwrap();
+        assert_eq!(loaded.save(), saved);
+        assert!(loaded.load(&saved[..40]).is_err());
+
+        // A negative domain length, and a string length that never ends.
+        let mut corrupt = saved.clone();
+        assert_eq!(&corrupt[34..38], b"\x03ROM");
+        corrupt[38..42].copy_from_slice(&(-1i32).to_le_bytes());
+        assert_eq!(loaded.load(&corrupt), Err("Bad length for CDL domain ROM".to_string()));
+        assert_eq!(loaded.load(&[0xFF; 16]), Err("Bad string length in CDL file".to_string()));
+    }
+}
@@ -0,0 +1,171 @@
+use std::fmt;
+
+/// A Game Genie substitution of a ROM byte.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct RomPatch {
+    pub addr: u16,
+    pub value: u8,
+    // Only substitute when the ROM has this byte, which picks out one bank.
+    pub compare: Option<u8>
+}
+
+impl RomPatch {
+    /// The byte read at `addr` when the ROM holds `byte` there.
+    pub fn apply(&self, addr: u16, byte: u8) -> Option<u8> {
+        if addr == self.addr && self.compare.is_none_or(|compare| compare == byte) {
+            Some(self.value)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Code {
+    GameGenie(RomPatch),
+    GameShark { bank: Option<usize>, addr: u16, value: u8 }
+}
+
+/// A Game Genie or GameShark code.
+///
+/// Game Genie codes, `ABC-DEF-GHI` or `ABC-DEF`, replace a byte read from
+/// ROM. With the last three digits the byte is only replaced when the ROM
+/// holds the compare value there, so the code only hits the intended bank.
+/// GameShark codes, `01VVLLHH`, write `VV` to `HHLL` every frame. A type of
+/// `9X` instead of `01` writes to WRAM bank `X`. Freezes made by a RAM
+/// search work the same way.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cheat {
+    code: Code,
+    text: String,
+    pub description: String,
+    pub enabled: bool
+}
+
+impl Cheat {
+    pub fn parse(text: &str) -> Result<Cheat, String> {
+        let digits: Vec<u8> = text.chars()
+            .filter(|c| *c != '-')
+            .map(|c| c.to_digit(16).map(|d| d as u8).ok_or_else(|| format!("Bad cheat code {}", text)))
+            .collect::<Result<_, _>>()?;
+        let byte = |i: usize| (digits[i] << 4) | digits[i + 1];
+        let code = match digits.len() {
+            6 | 9 if text.contains('-') => {
+                let addr = (((digits[5] ^ 0xF) as u16) << 12) | ((digits[2] as u16) << 8) |
+                    ((digits[3] as u16) << 4) | digits[4] as u16;
+                if addr >= 0x8000 {
+                    return Err(format!("Game Genie code {} isn't for a ROM address", text));
+                }
+                let compare = if digits.len() == 9 {
+                    Some(((digits[6] << 4) | digits[8]).rotate_right(2) ^ 0xBA)
+                } else {
+                    None
+                };
+                Code::GameGenie(RomPatch { addr, value: byte(0), compare })
+            }
+            8 => {
+                let bank = match byte(0) {
+                    0x01 => None,
+                    kind @ 0x90 ..= 0x97 => Some((kind & 0x7) as usize),
+                    kind => return Err(format!("Unsupported GameShark code type {:02X} in {}", kind, text))
+                };
+                let addr = ((byte(6) as u16) << 8) | byte(4) as u16;
+                if !matches!(addr, 0xA000 ..= 0xDFFF | 0xFF80 ..= 0xFFFE) {
+                    return Err(format!("GameShark code {} isn't for a RAM address", text));
+                }
+                Code::GameShark { bank, addr, value: byte(2) }
+            }
+            _ => return Err(format!("Bad cheat code {}", text))
+        };
+        Ok(Cheat { code, text: text.to_uppercase(), description: String::new(), enabled: true })
+    }
+
+    /// Reads a cheat file. Each line has a code and an optional
+    /// description. Codes starting with `!` are loaded disabled and `#`
+    /// starts a comment.
+    pub fn parse_file(data: &str) -> Result<Vec<Cheat>, String> {
+        let mut cheats = Vec::new();
+        for (line_num, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (code, description) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let (code, enabled) = match code.strip_prefix('!') {
+                Some(code) => (code, false),
+                None => (code, true)
+            };
+            let mut cheat = Cheat::parse(code).map_err(|e| format!("line {}: {}", line_num + 1, e))?;
+            cheat.description = description.trim().to_string();
+            cheat.enabled = enabled;
+            cheats.push(cheat);
+        }
+        Ok(cheats)
+    }
+
+    pub(crate) fn rom_patch(&self) -> Option<RomPatch> {
+        match self.code {
+            Code::GameGenie(patch) => Some(patch),
+            Code::GameShark { .. } => None
+        }
+    }
+
+    /// A code that keeps `value` at `addr` in `bank` of WRAM, cartridge RAM
+    /// or HRAM.
+    pub fn freeze(bank: usize, addr: u16, value: u8) -> Cheat {
+        Cheat {
+            code: Code::GameShark { bank: Some(bank), addr, value },
+            text: format!("{:02X}:{:04X}=${:02X}", bank, addr, value),
+            description: String::new(),
+            enabled: true
+        }
+    }
+
+    /// The bank, address and value a GameShark code writes. Without a bank
+    /// the write goes to whichever bank is mapped.
+    pub(crate) fn ram_write(&self) -> Option<(Option<usize>, u16, u8)> {
+        match self.code {
+            Code::GameShark { bank, addr, value } => Some((bank, addr, value)),
+            Code::GameGenie(_) => None
+        }
+    }
+}
+
+impl fmt::Display for Cheat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] {}", if self.enabled { 'x' } else { ' ' }, self.text)?;
+        if !self.description.is_empty() {
+            write!(f, " {}", self.description)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let patch = |text: &str| Cheat::parse(text).unwrap().rom_patch().unwrap();
+        assert_eq!(patch("3E1-23B-CEA"), RomPatch { addr: 0x4123, value: 0x3E, compare: Some(0x08) });
+        assert_eq!(patch("001-23F"), RomPatch { addr: 0x0123, value: 0x00, compare: None });
+        assert!(Cheat::parse("3E1-237-CEA").is_err());
+
+        assert_eq!(Cheat::parse("0199C0C1").unwrap().ram_write(), Some((None, 0xC1C0, 0x99)));
+        assert_eq!(Cheat::parse("9305C0D1").unwrap().ram_write(), Some((Some(3), 0xD1C0, 0x05)));
+        assert!(Cheat::parse("0A99C0C1").is_err());
+        assert_eq!(Cheat::parse("01990040").unwrap_err(), "GameShark code 01990040 isn't for a RAM address");
+        assert!(Cheat::parse("019900E0").is_err());
+        assert!(Cheat::parse("0199FFFF").is_err());
+        assert_eq!(Cheat::parse("019980FF").unwrap().ram_write(), Some((None, 0xFF80, 0x99)));
+        assert!(Cheat::parse("019900A0").is_ok());
+        assert!(Cheat::parse("hello").is_err());
+
+        let cheats = Cheat::parse_file("# Infinite lives\n0199C0C1 Lives\n!3e1-23b-cea\n").unwrap();
+        assert_eq!(cheats.len(), 2);
+        assert_eq!(cheats[0].to_string(), "[x] 0199C0C1 Lives");
+        assert_eq!(cheats[1].to_string(), "[ ] 3E1-23B-CEA");
+        assert_eq!(Cheat::parse_file("0199C0C1\nbad\n").unwrap_err(), "line 2: Bad cheat code bad");
+    }
+}
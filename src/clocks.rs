@@ -1,3 +1,4 @@
+/// M-cycles per second. Every cycle count in the core is in M-cycles.
 pub const CLOCK_FREQ: u32 = 1024 * 1024;
 pub const CLOCKS_PER_SCREEN_REFRESH: u32 = 17556;
 pub const NS_PER_SCREEN_REFRESH: u64 = 1_000_000_000 / (CLOCK_FREQ / CLOCKS_PER_SCREEN_REFRESH) as u64;
@@ -0,0 +1,165 @@
+use std::fmt;
+use crate::cartridge::Symbols;
+
+/// How a [`StackFrame`] was entered.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FrameKind {
+    Call,
+    Rst,
+    Interrupt
+}
+
+impl fmt::Display for FrameKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            FrameKind::Call => "call",
+            FrameKind::Rst => "rst",
+            FrameKind::Interrupt => "interrupted"
+        })
+    }
+}
+
+/// A routine entered by CALL, RST or an interrupt and not yet returned from.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StackFrame {
+    pub kind: FrameKind,
+    /// The bank and address of the CALL or RST, or of the instruction an
+    /// interrupt came before.
+    pub from: (usize, u16),
+    /// The bank and address jumped to.
+    pub to: (usize, u16),
+    /// The address pushed, which the matching RET returns to.
+    pub return_addr: u16,
+    /// Where the return address was pushed.
+    pub sp: u16
+}
+
+/// A RET or RETI that didn't return to where the innermost routine was
+/// called from, which usually means the stack was corrupted.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BadReturn {
+    /// The bank and address of the RET.
+    pub at: (usize, u16),
+    /// The address it returned to.
+    pub to: u16,
+    /// The frame whose return address it popped, if there was one.
+    pub expected: Option<StackFrame>
+}
+
+impl BadReturn {
+    pub fn describe(&self, symbols: Option<&Symbols>) -> String {
+        let ret = format!("Return at {} to ${:04X}", format_loc(self.at, symbols), self.to);
+        match self.expected {
+            Some(frame) => format!("{}, but {} was entered from {} to return to ${:04X}",
+                                   ret, format_loc(frame.to, symbols), format_loc(frame.from, symbols), frame.return_addr),
+            None => format!("{} without a matching call", ret)
+        }
+    }
+}
+
+/// A shadow of the call stack kept alongside the real one in memory.
+///
+/// Frames are pushed by CALL, RST and interrupts and popped by RET and RETI.
+/// Frames whose return address has been popped some other way, or whose
+/// stack slot is reused, are dropped.
+pub(crate) struct CallStack {
+    frames: Vec<StackFrame>
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        CallStack { frames: Vec::new() }
+    }
+
+    /// The frames, outermost first.
+    pub fn frames(&self) -> &[StackFrame] {
+        &self.frames
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    pub fn push(&mut self, frame: StackFrame) {
+        while self.frames.last().is_some_and(|top| top.sp <= frame.sp) {
+            self.frames.pop();
+        }
+        self.frames.push(frame);
+    }
+
+    /// A return at `at` popped `to` from `sp`.
+    pub fn pop(&mut self, at: (usize, u16), sp: u16, to: u16) -> Option<BadReturn> {
+        self.unwind(sp);
+        match self.frames.last() {
+            Some(top) if top.sp == sp => {
+                let frame = self.frames.pop().unwrap();
+                if frame.return_addr == to {
+                    None
+                } else {
+                    Some(BadReturn { at, to, expected: Some(frame) })
+                }
+            }
+            _ => Some(BadReturn { at, to, expected: None })
+        }
+    }
+
+    /// Drops the frames whose return address is below `sp`.
+    pub fn unwind(&mut self, sp: u16) {
+        while self.frames.last().is_some_and(|top| top.sp < sp) {
+            self.frames.pop();
+        }
+    }
+
+    /// One line per frame, innermost first, starting with `pc`.
+    pub fn backtrace(&self, pc: (usize, u16), symbols: Option<&Symbols>) -> String {
+        let mut lines = vec![format!("#0  {}", format_loc(pc, symbols))];
+        for (i, frame) in self.frames.iter().rev().enumerate() {
+            lines.push(format!("#{:<2} {} ({})", i + 1, format_loc(frame.from, symbols), frame.kind));
+        }
+        lines.join("\n")
+    }
+}
+
+fn format_loc((bank, addr): (usize, u16), symbols: Option<&Symbols>) -> String {
+    match symbols.and_then(|symbols| symbols.describe(bank, addr)) {
+        Some(name) => format!("{:02X}:{:04X} {}", bank, addr, name),
+        None => format!("{:02X}:{:04X}", bank, addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(kind: FrameKind, from: u16, to: u16, sp: u16) -> StackFrame {
+        StackFrame { kind, from: (0, from), to: (0, to), return_addr: from + 3, sp }
+    }
+
+    #[test]
+    fn test_call_stack() {
+        let mut stack = CallStack::new();
+        stack.push(frame(FrameKind::Call, 0x150, 0x200, 0xFFFC));
+        stack.push(frame(FrameKind::Interrupt, 0x205, 0x40, 0xFFFA));
+        assert_eq!(stack.pop((0, 0x45), 0xFFFA, 0x208), None);
+        assert_eq!(stack.frames().len(), 1);
+
+        // Returning somewhere else pops the frame but is flagged.
+        let bad = stack.pop((0, 0x210), 0xFFFC, 0x1234).unwrap();
+        assert_eq!(bad.expected, Some(frame(FrameKind::Call, 0x150, 0x200, 0xFFFC)));
+        assert!(stack.frames().is_empty());
+        assert_eq!(stack.pop((0, 0x210), 0xFFFE, 0x1234).unwrap().expected, None);
+
+        // A frame whose return address was popped by hand is dropped.
+        stack.push(frame(FrameKind::Call, 0x150, 0x200, 0xFFFC));
+        stack.push(frame(FrameKind::Rst, 0x203, 0x38, 0xFFFA));
+        stack.unwind(0xFFFC);
+        assert_eq!(stack.frames().len(), 1);
+        stack.push(frame(FrameKind::Call, 0x160, 0x300, 0xFFFC));
+        assert_eq!(stack.frames().len(), 1);
+
+        let symbols = Symbols::parse("00:0150 Main\n00:0300 Draw\n");
+        assert_eq!(stack.backtrace((0, 0x302), Some(&symbols)), "#0  00:0302 Draw+$2\n#1  00:0160 Main+$10 (call)");
+        assert_eq!(bad.describe(Some(&symbols)),
+                   "Return at 00:0210 Main+$C0 to $1234, but 00:0200 Main+$B0 was entered from 00:0150 Main to return to $0153");
+    }
+}
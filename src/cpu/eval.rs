@@ -1,21 +1,37 @@
 use super::registers::{Register};
 use crate::memory::memory_bus::{MemoryBus};
 use super::instr::{Src, FlagCondition, Opcode, Instr};
+use super::call_stack::{FrameKind, StackFrame};
 
 use super::Cpu;
 use Opcode::*;
 use Register::*;
 
 impl Cpu {
+    // Runs an instruction, after dispatching an interrupt if one is pending,
+    // and returns the M-cycles it took. Cycles that don't access memory
+    // are ticked here when a memory access follows them, and are otherwise
+    // left for the caller.
     pub fn eval(&mut self, memory_bus: &mut MemoryBus) -> u32 {
+        self.dispatched_interrupt = None;
+        self.bad_return = None;
+        let mut dispatch_cycles = 0;
         if self.halted || self.ime {
             let interrupt_ctrl = memory_bus.devices().interrupt_controller();
             match interrupt_ctrl.handle(self.ime) {
                 None => {}
                 Some(addr) => {
                     if self.ime {
+                        // Two idle cycles, two to push PC and one to jump.
+                        let pc = self.registers.pc();
+                        memory_bus.tick();
+                        memory_bus.tick();
                         self.push_pc(0, memory_bus);
+                        self.push_frame(FrameKind::Interrupt, pc, addr, pc, memory_bus);
                         self.registers.set16(PC, addr);
+                        memory_bus.tick();
+                        self.dispatched_interrupt = Some(addr);
+                        dispatch_cycles = 5;
                     }
                     self.halted = false;
                     self.ime = false;
@@ -24,7 +40,14 @@ impl Cpu {
         }
 
         if self.halted {
-            return 1;
+            return dispatch_cycles + 1;
+        }
+
+        if let Some(tracer) = self.tracer.as_mut() {
+            let pc = self.registers.pc();
+            let [b0, b1, b2] = memory_bus.get_arr3(pc);
+            let b3 = memory_bus.get_arr3(pc.wrapping_add(3))[0];
+            tracer.log(&self.registers, [b0, b1, b2, b3]);
         }
 
         let instr = self.disassemble(memory_bus);
@@ -39,6 +62,12 @@ impl Cpu {
             }
         }
 
+        if self.ld_b_b_breakpoint {
+            if let LD8(Src::Reg(B), Src::Reg(B)) = instr.opcode {
+                self.ld_b_b_hit = true;
+            }
+        }
+
         match instr.opcode {
             NOP => {}
             LD8(dst, src) => {
@@ -66,6 +95,7 @@ impl Cpu {
                 self.ld16(dst, src, memory_bus);
             }
             PUSH(reg) => {
+                memory_bus.tick();
                 let sp = self.registers.get16(SP);
                 self.registers.set16(SP, sp - 2);
                 let v = self.registers.get16(reg);
@@ -272,8 +302,8 @@ impl Cpu {
             }
             SWAP(dst) => {
                 let a = self.get_u8(dst, memory_bus);
-                let n1 = (a >> 4) & 0xFF;
-                let n2 = a & 0xFF;
+                let n1 = a >> 4;
+                let n2 = a;
                 let r = (n2 << 4) | n1;
                 self.ld8(dst, Src::D8(r), memory_bus);
                 self.registers.set_flags(r == 0, false, false, false);
@@ -309,7 +339,7 @@ impl Cpu {
                     let r = if offset >= 0 {
                         self.registers.get16(PC) + (offset as u16)
                     } else {
-                        self.registers.get16(PC) - ((offset as i16) * -1) as u16
+                        self.registers.get16(PC) - -(offset as i16) as u16
                     };
                     self.registers.set16(PC, r);
                 } else {
@@ -318,7 +348,10 @@ impl Cpu {
             }
             CALL(fc, addr) => {
                 if self.flag_match(fc) {
+                    let pc = self.registers.pc();
+                    memory_bus.tick();
                     self.push_pc(instr.n_bytes, memory_bus);
+                    self.push_frame(FrameKind::Call, pc, addr, pc + instr.n_bytes, memory_bus);
                     self.registers.set16(PC, addr);
                     inc_pc = false;
                 } else {
@@ -327,7 +360,11 @@ impl Cpu {
             }
             RET(fc) => {
                 if self.flag_match(fc) {
-                    self.pop_pc(memory_bus);
+                    // Checking the condition takes a cycle.
+                    if !matches!(fc, FlagCondition::ALWAYS) {
+                        memory_bus.tick();
+                    }
+                    self.pop_frame(memory_bus);
                     inc_pc = false;
                 } else {
                     cycles -= 3
@@ -335,11 +372,14 @@ impl Cpu {
             }
             RETI => {
                 self.ime = true;
-                self.pop_pc(memory_bus);
+                self.pop_frame(memory_bus);
                 inc_pc = false;
             }
             RST(addr) => {
+                let pc = self.registers.pc();
+                memory_bus.tick();
                 self.push_pc(instr.n_bytes, memory_bus);
+                self.push_frame(FrameKind::Rst, pc, addr as u16, pc + instr.n_bytes, memory_bus);
                 self.registers.set16(PC, addr as u16);
                 inc_pc = false;
             }
@@ -396,15 +436,19 @@ impl Cpu {
                 debug!("STOP not implemented");
             }
             INVALID(b) => {
-                panic!("Invalid opcode: {:X}: {:X?}", self.registers.pc(), b)
+                let pc = self.registers.pc();
+                let bank = memory_bus.devices().bank(pc);
+                panic!("Invalid opcode: {:X}: {:X?}\n{}", pc, b,
+                       self.call_stack.backtrace((bank, pc), memory_bus.symbols()))
             }
         }
 
         if inc_pc {
             self.registers.inc_pc(instr.n_bytes);
         }
+        self.call_stack.unwind(self.registers.get16(SP));
 
-        return cycles;
+        dispatch_cycles + cycles
     }
 
     fn push_pc(&mut self, n: u16, memory_bus: &mut MemoryBus) {
@@ -427,6 +471,23 @@ impl Cpu {
         self.registers.set16(SP, sp+2);
     }
 
+    // Records a frame on the call stack once its return address is pushed.
+    fn push_frame(&mut self, kind: FrameKind, from: u16, to: u16, return_addr: u16, memory_bus: &mut MemoryBus) {
+        let from = (memory_bus.devices().bank(from), from);
+        let to = (memory_bus.devices().bank(to), to);
+        let sp = self.registers.get16(SP);
+        self.call_stack.push(StackFrame { kind, from, to, return_addr, sp });
+    }
+
+    // Pops PC for a RET or RETI, checking it against the call stack.
+    fn pop_frame(&mut self, memory_bus: &mut MemoryBus) {
+        let pc = self.registers.pc();
+        let at = (memory_bus.devices().bank(pc), pc);
+        let sp = self.registers.get16(SP);
+        self.pop_pc(memory_bus);
+        self.bad_return = self.call_stack.pop(at, sp, self.registers.pc());
+    }
+
     fn flag_match(&self, fc: FlagCondition) -> bool {
         match fc {
             FlagCondition::Z => self.registers.z_flag() == 1,
@@ -480,10 +541,16 @@ impl Cpu {
     }
 
 
+    // Decodes the instruction at PC, taking a cycle to fetch each byte.
     fn disassemble(&self, memory_bus: &mut MemoryBus) -> Instr {
         let instr_bytes = memory_bus.get_arr3(self.registers.pc());
 
-        Instr::disassemble(&instr_bytes)
+        let instr = Instr::disassemble(&instr_bytes);
+        memory_bus.mark_executed(self.registers.pc(), instr.n_bytes);
+        for _ in 0..instr.n_bytes {
+            memory_bus.tick();
+        }
+        instr
     }
 }
 
@@ -508,8 +575,8 @@ mod tests {
 
     #[test]
     fn test_nop() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x00]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x00]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
 
         assert_eq!(cpu.eval(&mut mb), 1);
@@ -518,8 +585,8 @@ mod tests {
 
     #[test]
     fn test_02() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x02]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x02]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(BC, 0x1234);
         cpu.registers.set8(A, 42);
@@ -531,8 +598,8 @@ mod tests {
 
     #[test]
     fn test_06() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x06, 42]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x06, 42]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
 
         assert_eq!(cpu.eval(&mut mb), 2);
@@ -540,10 +607,49 @@ mod tests {
         assert_eq!(cpu.registers.get8(B), 42);
     }
 
+    #[test]
+    fn test_memory_cycles() {
+        // Every read, write and fetch takes a cycle of the instruction, with
+        // conditional jumps both taken and not.
+        for &(prefix, flags) in &[(false, 0x00), (false, 0xF0), (true, 0x00)] {
+            for op in 0..=0xFF {
+                let bytes = if prefix { [0xCB, op, 0x00] } else { [op, 0x00, 0xC0] };
+                if let INVALID(_) = Instr::disassemble(&bytes).opcode {
+                    continue;
+                }
+                let (mm, mut mmdm) = new_from_slice(&bytes);
+                let mut mb = MemoryBus::new(&mm, &mut mmdm);
+                let mut cpu = Cpu::new(Mode::DMG);
+                cpu.registers.set16(HL, 0xC000);
+                cpu.registers.set16(SP, 0xD000);
+                cpu.registers.set8(F, flags);
+
+                let cycles = cpu.eval(&mut mb);
+                assert!(mb.cycles() <= cycles, "{:02X?} took {} memory cycles in {}", bytes, mb.cycles(), cycles);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ld_b_b_breakpoint() {
+        let (mm, mut mmdm) = new_from_slice(&[0x40, 0x40, 0x41]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
+        let mut cpu = Cpu::new(Mode::DMG);
+
+        cpu.eval(&mut mb);
+        assert!(!cpu.take_ld_b_b_hit());
+        cpu.set_ld_b_b_breakpoint(true);
+        cpu.eval(&mut mb);
+        assert!(cpu.take_ld_b_b_hit());
+        assert!(!cpu.take_ld_b_b_hit());
+        cpu.eval(&mut mb);
+        assert!(!cpu.take_ld_b_b_hit());
+    }
+
     #[test]
     fn test_0a() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x0A, 42]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x0A, 42]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(BC, 0x1234);
         mb.set8(0x1234, 42);
@@ -555,8 +661,8 @@ mod tests {
 
     #[test]
     fn test_e2() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xE2]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xE2]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(C, 0x34);
         cpu.registers.set8(A, 42);
@@ -568,8 +674,8 @@ mod tests {
 
     #[test]
     fn test_ea() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xEA, 0xAD, 0xDE]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xEA, 0xAD, 0xDE]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(C, 0x34);
         cpu.registers.set8(A, 42);
@@ -581,8 +687,8 @@ mod tests {
 
     #[test]
     fn test_f2() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xF2]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xF2]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         mb.set8(0xFF34, 42);
         cpu.registers.set8(C, 0x34);
@@ -594,8 +700,8 @@ mod tests {
 
     #[test]
     fn test_fa() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xFA, 0xAD, 0xDE]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xFA, 0xAD, 0xDE]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         mb.set8(0xDEAD, 42);
 
@@ -606,8 +712,8 @@ mod tests {
 
     #[test]
     fn test_e0() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xE0, 0x34]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xE0, 0x34]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 42);
 
@@ -618,8 +724,8 @@ mod tests {
 
     #[test]
     fn test_f0() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xF0, 0x34]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xF0, 0x34]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         mb.set8(0xFF34, 42);
 
@@ -630,8 +736,8 @@ mod tests {
 
     #[test]
     fn test_22() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x22]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x22]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(HL, 0xDEAD);
         cpu.registers.set8(A, 42);
@@ -644,8 +750,8 @@ mod tests {
 
     #[test]
     fn test_2a() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x2a]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x2a]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(HL, 0xDEAD);
         mb.set8(0xDEAD, 42);
@@ -658,8 +764,8 @@ mod tests {
 
     #[test]
     fn test_32() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x32]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x32]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(HL, 0xDEAD);
         cpu.registers.set8(A, 42);
@@ -672,8 +778,8 @@ mod tests {
 
     #[test]
     fn test_3a() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x3a]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x3a]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(HL, 0xDEAD);
         mb.set8(0xDEAD, 42);
@@ -686,8 +792,8 @@ mod tests {
 
     #[test]
     fn test_01() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x01, 0xAD, 0xDE]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x01, 0xAD, 0xDE]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
 
         assert_eq!(cpu.eval(&mut mb), 3);
@@ -697,8 +803,8 @@ mod tests {
 
     #[test]
     fn test_08() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x08, 0xAD, 0xDE]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x08, 0xAD, 0xDE]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(SP, 0x1234);
 
@@ -710,8 +816,8 @@ mod tests {
 
     #[test]
     fn test_c5() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xC5]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xC5]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(SP, 0xFFFE);
         cpu.registers.set16(BC, 0x1234);
@@ -725,8 +831,8 @@ mod tests {
 
     #[test]
     fn test_e1() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xE1]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xE1]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(SP, 0xFFFC);
         mb.set16(0xFFFC, 0xDEAD);
@@ -740,8 +846,8 @@ mod tests {
 
     #[test]
     fn test_82() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x82]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x82]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 2);
         cpu.registers.set8(D, 3);
@@ -758,8 +864,8 @@ mod tests {
 
     #[test]
     fn test_82_zhcy() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x82]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x82]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0x3A);
         cpu.registers.set8(D, 0xC6);
@@ -776,8 +882,8 @@ mod tests {
 
     #[test]
     fn test_82_h() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x82]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x82]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 10);
         cpu.registers.set8(D, 11);
@@ -793,8 +899,8 @@ mod tests {
 
     #[test]
     fn test_c6_hcy() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xC6, 0x7]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xC6, 0x7]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 250);
 
@@ -809,8 +915,8 @@ mod tests {
 
     #[test]
     fn test_8b() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x8B]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x8B]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0xE1);
         cpu.registers.set8(E, 0x0F);
@@ -827,8 +933,8 @@ mod tests {
 
     #[test]
     fn test_ce() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xCE, 0x3B]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xCE, 0x3B]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0xE1);
         cpu.registers.set_flags(false, false, false, true);
@@ -844,8 +950,8 @@ mod tests {
 
     #[test]
     fn test_8e() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x8E]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x8E]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0xE1);
         cpu.registers.set16(HL, 0x1234);
@@ -863,8 +969,8 @@ mod tests {
 
     #[test]
     fn test_93() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x93]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x93]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0x3E);
         cpu.registers.set8(E, 0x3E);
@@ -880,8 +986,8 @@ mod tests {
 
     #[test]
     fn test_d6() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xD6, 0x0F]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xD6, 0x0F]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0x3E);
 
@@ -896,8 +1002,8 @@ mod tests {
 
     #[test]
     fn test_96() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x96]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x96]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0x3E);
         cpu.registers.set16(HL, 0x1234);
@@ -914,8 +1020,8 @@ mod tests {
 
     #[test]
     fn test_9c() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x9c]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x9c]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0x3B);
         cpu.registers.set8(H, 0x2A);
@@ -932,8 +1038,8 @@ mod tests {
 
     #[test]
     fn test_de() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xDE, 0x3A]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xDE, 0x3A]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0x3B);
         cpu.registers.set_flags(false, false, false, true);
@@ -949,8 +1055,8 @@ mod tests {
 
     #[test]
     fn test_9e() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x9E]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x9E]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0x3B);
         cpu.registers.set16(HL, 0x1234);
@@ -968,8 +1074,8 @@ mod tests {
 
     #[test]
     fn test_a5() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xa5]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xa5]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0x5A);
         cpu.registers.set8(L, 0x3F);
@@ -985,8 +1091,8 @@ mod tests {
 
     #[test]
     fn test_f6() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xF6, 0x03]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xF6, 0x03]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0x5A);
 
@@ -1001,8 +1107,8 @@ mod tests {
 
     #[test]
     fn test_ae() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xae]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xae]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0xFF);
         cpu.registers.set16(HL, 0x1234);
@@ -1019,8 +1125,8 @@ mod tests {
 
     #[test]
     fn test_b8() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xB8]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xB8]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0x3C);
         cpu.registers.set8(B, 0x2F);
@@ -1036,8 +1142,8 @@ mod tests {
 
     #[test]
     fn test_fe() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xFE, 0x3C]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xFE, 0x3C]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0x3C);
 
@@ -1052,8 +1158,8 @@ mod tests {
 
     #[test]
     fn test_be() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xBE]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xBE]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0x3C);
         cpu.registers.set16(HL, 0x1234);
@@ -1070,8 +1176,8 @@ mod tests {
 
     #[test]
     fn test_3c() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x3C]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x3C]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0xFF);
         cpu.registers.set_flags(false, false, false, true);
@@ -1087,8 +1193,8 @@ mod tests {
 
     #[test]
     fn test_34() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x34]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x34]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(HL, 0x1234);
         mb.set8(0x1234, 0x50);
@@ -1104,8 +1210,8 @@ mod tests {
 
     #[test]
     fn test_2d() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x2D]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x2D]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(L, 0x01);
         cpu.registers.set_flags(false, false, false, true);
@@ -1121,8 +1227,8 @@ mod tests {
 
     #[test]
     fn test_35() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x35]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x35]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(HL, 0x1234);
         mb.set8(0x1234, 0x00);
@@ -1138,8 +1244,8 @@ mod tests {
 
     #[test]
     fn test_09() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x09]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x09]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(HL, 0x8A23);
         cpu.registers.set16(BC, 0x0605);
@@ -1156,8 +1262,8 @@ mod tests {
 
     #[test]
     fn test_29() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x29]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x29]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(HL, 0x8A23);
 
@@ -1172,8 +1278,8 @@ mod tests {
 
     #[test]
     fn test_e8() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xE8, 0x02]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xE8, 0x02]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(SP, 0xFFF8);
 
@@ -1188,8 +1294,8 @@ mod tests {
 
     #[test]
     fn test_e8_neg() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xE8, 0xFE]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xE8, 0xFE]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(SP, 0xFFF8);
 
@@ -1204,8 +1310,8 @@ mod tests {
 
     #[test]
     fn test_13() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x13]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x13]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(DE, 0x235F);
 
@@ -1216,8 +1322,8 @@ mod tests {
 
     #[test]
     fn test_1b() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x1B]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x1B]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(DE, 0x235F);
 
@@ -1228,8 +1334,8 @@ mod tests {
 
     #[test]
     fn test_07() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x07]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x07]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0x85);
 
@@ -1242,8 +1348,8 @@ mod tests {
 
     #[test]
     fn test_17() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x17]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x17]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0x95);
         cpu.registers.set_flags(false, false, false, true);
@@ -1256,8 +1362,8 @@ mod tests {
 
     #[test]
     fn test_0f() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x0F]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x0F]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0x3B);
 
@@ -1269,8 +1375,8 @@ mod tests {
 
     #[test]
     fn test_1f() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x1F]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x1F]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0x81);
         cpu.registers.set_flags(false, false, false, false);
@@ -1283,8 +1389,8 @@ mod tests {
 
     #[test]
     fn test_cb00() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xCB, 0x00]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xCB, 0x00]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(B, 0x85);
 
@@ -1296,8 +1402,8 @@ mod tests {
 
     #[test]
     fn test_cb06() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xCB, 0x06]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xCB, 0x06]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(HL, 0x1234);
         mb.set8(0x1234, 0);
@@ -1311,8 +1417,8 @@ mod tests {
 
     #[test]
     fn test_cb15() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xCB, 0x15]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xCB, 0x15]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(L, 0x80);
 
@@ -1325,8 +1431,8 @@ mod tests {
 
     #[test]
     fn test_cb16() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xCB, 0x16]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xCB, 0x16]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(HL, 0x1234);
         mb.set8(0x1234, 0x11);
@@ -1340,8 +1446,8 @@ mod tests {
 
     #[test]
     fn test_cb09() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xCB, 0x09]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xCB, 0x09]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(C, 0x01);
 
@@ -1355,8 +1461,8 @@ mod tests {
 
     #[test]
     fn test_cb1f() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xCB, 0x1F]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xCB, 0x1F]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0x01);
 
@@ -1369,8 +1475,8 @@ mod tests {
 
     #[test]
     fn test_cb22() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xCB, 0x22]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xCB, 0x22]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(D, 0x80);
 
@@ -1383,8 +1489,8 @@ mod tests {
 
     #[test]
     fn test_cb2e() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xCB, 0x2E]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xCB, 0x2E]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(HL, 0x1234);
         mb.set8(0x1234, 0x8A);
@@ -1398,8 +1504,8 @@ mod tests {
 
     #[test]
     fn test_cb36() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xCB, 0x36]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xCB, 0x36]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(HL, 0x1234);
         mb.set8(0x1234, 0xF0);
@@ -1413,8 +1519,8 @@ mod tests {
 
     #[test]
     fn test_cb3e() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xCB, 0x3E]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xCB, 0x3E]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(HL, 0x1234);
         mb.set8(0x1234, 0xFF);
@@ -1428,8 +1534,8 @@ mod tests {
 
     #[test]
     fn test_cb7f() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xCB, 0x7F]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xCB, 0x7F]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0x80);
         cpu.registers.set_flags(false, false, false, true);
@@ -1444,8 +1550,8 @@ mod tests {
 
     #[test]
     fn test_cb65() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xCB, 0x65]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xCB, 0x65]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(L, 0xEF);
         cpu.registers.set_flags(false, false, false, true);
@@ -1460,8 +1566,8 @@ mod tests {
 
     #[test]
     fn test_cb46() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xCB, 0x46]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xCB, 0x46]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(HL, 0x1234);
         mb.set8(0x1234, 0xFE);
@@ -1477,8 +1583,8 @@ mod tests {
 
     #[test]
     fn test_cb4e() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xCB, 0x4E]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xCB, 0x4E]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(HL, 0x1234);
         mb.set8(0x1234, 0xFE);
@@ -1494,8 +1600,8 @@ mod tests {
 
     #[test]
     fn test_cbbf() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xCB, 0xBF]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xCB, 0xBF]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0x80);
 
@@ -1506,8 +1612,8 @@ mod tests {
 
     #[test]
     fn test_cb93() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xCB, 0x9E]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xCB, 0x9E]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(HL, 0x1234);
         mb.set8(0x1234, 0xFF);
@@ -1519,8 +1625,8 @@ mod tests {
 
     #[test]
     fn test_cbfd() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xCB, 0xFD]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xCB, 0xFD]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(L, 0x3B);
 
@@ -1532,8 +1638,8 @@ mod tests {
 
     #[test]
     fn test_cbde() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xCB, 0xDE]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xCB, 0xDE]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(HL, 0x1234);
         mb.set8(0x1234, 0x00);
@@ -1546,8 +1652,8 @@ mod tests {
 
     #[test]
     fn test_c3() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xC3, 0x12, 0x80]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xC3, 0x12, 0x80]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
 
         assert_eq!(cpu.eval(&mut mb), 4);
@@ -1556,8 +1662,8 @@ mod tests {
 
     #[test]
     fn test_e9() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xE9]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xE9]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(HL, 0x8012);
 
@@ -1567,8 +1673,8 @@ mod tests {
 
     #[test]
     fn test_c2() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xC2, 0x00, 0x80]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xC2, 0x00, 0x80]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set_flags(true, false, false, false);
 
@@ -1578,8 +1684,8 @@ mod tests {
 
     #[test]
     fn test_ca() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xCA, 0x00, 0x80]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xCA, 0x00, 0x80]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set_flags(true, false, false, false);
 
@@ -1589,8 +1695,8 @@ mod tests {
 
     #[test]
     fn test_d2() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xD2, 0x00, 0x80]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xD2, 0x00, 0x80]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set_flags(true, false, false, false);
 
@@ -1600,8 +1706,8 @@ mod tests {
 
     #[test]
     fn test_da() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xDA, 0x00, 0x80]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xDA, 0x00, 0x80]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set_flags(true, false, false, false);
 
@@ -1611,8 +1717,8 @@ mod tests {
 
     #[test]
     fn test_18() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x18, 0x03]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x18, 0x03]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
 
         assert_eq!(cpu.eval(&mut mb), 3);
@@ -1621,8 +1727,8 @@ mod tests {
 
     #[test]
     fn test_28() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x28, 0xFD]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x28, 0xFD]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set_flags(true, false, false, false);
         cpu.registers.set16(PC, 4);
@@ -1633,8 +1739,8 @@ mod tests {
 
     #[test]
     fn test_cd() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x00, 0x00, 0x00, 0xcd, 0x34, 0x12]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x00, 0x00, 0x00, 0xcd, 0x34, 0x12]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(SP, 0xFFFE);
         cpu.registers.set16(PC, 0x03);
@@ -1649,8 +1755,8 @@ mod tests {
 
     #[test]
     fn test_c9() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xC9]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xC9]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         mb.set8(0xFFFC, 0x02);
         mb.set8(0xFFFD, 0x00);
@@ -1664,8 +1770,8 @@ mod tests {
 
     #[test]
     fn test_d9() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xD9]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xD9]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         mb.set8(0xFFFC, 0x02);
         mb.set8(0xFFFD, 0x00);
@@ -1680,8 +1786,8 @@ mod tests {
 
     #[test]
     fn test_e7() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x00, 0x00, 0x00, 0xe7]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x00, 0x00, 0x00, 0xe7]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(SP, 0xFFFE);
         cpu.registers.set16(PC, 0x03);
@@ -1696,8 +1802,8 @@ mod tests {
 
     #[test]
     fn test_27() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x80, 0x27, 0x90, 0x27]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x80, 0x27, 0x90, 0x27]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0x45);
         cpu.registers.set8(B, 0x38);
@@ -1713,8 +1819,8 @@ mod tests {
 
     #[test]
     fn test_2f() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x2F]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x2F]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set8(A, 0x35);
 
@@ -1726,8 +1832,8 @@ mod tests {
 
     #[test]
     fn test_3f() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x3F]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x3F]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set_flags(true, true, true, false);
 
@@ -1740,8 +1846,8 @@ mod tests {
 
     #[test]
     fn test_37() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0x37]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0x37]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set_flags(true, true, true, false);
 
@@ -1754,8 +1860,8 @@ mod tests {
 
     #[test]
     fn test_f8() {
-        let (mut mm, mut mmdm) = new_from_slice(&[0xF8, 0x2]);
-        let mut mb = MemoryBus::new(&mut mm, &mut mmdm);
+        let (mm, mut mmdm) = new_from_slice(&[0xF8, 0x2]);
+        let mut mb = MemoryBus::new(&mm, &mut mmdm);
         let mut cpu = Cpu::new(Mode::DMG);
         cpu.registers.set16(SP, 0xFFF8);
 
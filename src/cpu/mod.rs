@@ -1,7 +1,13 @@
 use crate::gameboy::Mode;
 use crate::memory::memory_bus::{MemoryBus};
-use self::registers::{Registers, Register};
+use crate::save_state::{self, SaveState, StateReader, StateWriter};
+use crate::trace::Tracer;
+pub use self::registers::{Registers, Register};
+pub use self::call_stack::{BadReturn, FrameKind, StackFrame};
+pub(crate) use self::instr::{Instr, Opcode, Src, FlagCondition};
+pub(crate) use self::call_stack::CallStack;
 
+mod call_stack;
 mod eval;
 mod instr;
 mod registers;
@@ -10,27 +16,46 @@ pub struct Cpu {
     registers: Registers,
     ime: bool,
     halted: bool,
-    debug: bool
+    debug: bool,
+    ld_b_b_breakpoint: bool,
+    ld_b_b_hit: bool,
+    dispatched_interrupt: Option<u16>,
+    call_stack: CallStack,
+    bad_return: Option<BadReturn>,
+    tracer: Option<Tracer>
 }
 
 impl Cpu {
     pub fn new(mode: Mode) -> Cpu {
         let mut registers = Registers::new();
-        match mode {
-            Mode::CGB => {
-                registers.set8(Register::A, 0x11);
-            }
-            _ => {}
+        if let Mode::CGB = mode {
+            registers.set8(Register::A, 0x11);
         }
         Cpu {
             registers,
             ime: false,
             halted: false,
-            debug: false
+            debug: false,
+            ld_b_b_breakpoint: false,
+            ld_b_b_hit: false,
+            dispatched_interrupt: None,
+            call_stack: CallStack::new(),
+            bad_return: None,
+            tracer: None
         }
     }
 
-    pub fn skip_boot_rom(&mut self) {
+    /// Sets the registers the boot ROM leaves behind.
+    pub fn skip_boot_rom(&mut self, mode: Mode) {
+        let (af, bc, de, hl) = match mode {
+            Mode::DMG => (0x01B0, 0x0013, 0x00D8, 0x014D),
+            Mode::CGB => (0x1180, 0x0000, 0xFF56, 0x000D)
+        };
+        self.registers.set16(Register::AF, af);
+        self.registers.set16(Register::BC, bc);
+        self.registers.set16(Register::DE, de);
+        self.registers.set16(Register::HL, hl);
+        self.registers.set16(Register::SP, 0xFFFE);
         self.registers.set16(Register::PC, 0x100);
     }
 
@@ -38,7 +63,74 @@ impl Cpu {
         self.debug = true;
     }
 
+    /// Makes `LD B,B` (0x40) a software breakpoint, as used by test ROMs
+    /// to signal they are done.
+    pub fn set_ld_b_b_breakpoint(&mut self, enabled: bool) {
+        self.ld_b_b_breakpoint = enabled;
+    }
+
+    /// Whether an `LD B,B` ran since the last call.
+    pub fn take_ld_b_b_hit(&mut self) -> bool {
+        std::mem::replace(&mut self.ld_b_b_hit, false)
+    }
+
+    /// The vector jumped to if the last step dispatched an interrupt.
+    pub fn dispatched_interrupt(&self) -> Option<u16> {
+        self.dispatched_interrupt
+    }
+
+    pub fn call_stack(&self) -> &CallStack {
+        &self.call_stack
+    }
+
+    /// The return made by the last step if it didn't match the call stack.
+    pub fn bad_return(&self) -> Option<&BadReturn> {
+        self.bad_return.as_ref()
+    }
+
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        &mut self.registers
+    }
+
+    /// Runs an instruction, ticking the bus once for each of its M-cycles,
+    /// and returns how many there were.
     pub fn step(&mut self, memory_bus: &mut MemoryBus) -> u32 {
-        self.eval(memory_bus)
+        let start = memory_bus.cycles();
+        let cycles = self.eval(memory_bus);
+        let taken = memory_bus.cycles() - start;
+        debug_assert!(taken <= cycles, "{} memory cycles in a {} cycle instruction", taken, cycles);
+        // The rest don't access memory and come after the last access.
+        for _ in taken..cycles {
+            memory_bus.tick();
+        }
+        cycles
+    }
+}
+
+impl SaveState for Cpu {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.registers.save_state(w);
+        w.write_bool(self.ime);
+        w.write_bool(self.halted);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        self.registers.load_state(r)?;
+        self.ime = r.read_bool()?;
+        self.halted = r.read_bool()?;
+        self.call_stack.clear();
+        Ok(())
     }
 }
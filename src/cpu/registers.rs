@@ -1,4 +1,7 @@
-#[derive(Copy, Clone, Debug)]
+use crate::save_state::{self, SaveState, StateReader, StateWriter};
+
+/// An 8 or 16 bit CPU register. `AF`, `BC`, `DE` and `HL` are the pairs.
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Register {
     A,
     B,
@@ -16,6 +19,7 @@ pub enum Register {
     PC
 }
 
+/// The SM83 register file.
 #[derive(Default, Debug)]
 pub struct Registers {
     a: u8,
@@ -127,6 +131,26 @@ impl Registers {
     }
 }
 
+impl SaveState for Registers {
+    fn save_state(&self, w: &mut StateWriter) {
+        for v in &[self.a, self.b, self.c, self.d, self.e, self.f, self.h, self.l] {
+            w.write_u8(*v);
+        }
+        w.write_u16(self.sp);
+        w.write_u16(self.pc);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        for v in &mut [&mut self.a, &mut self.b, &mut self.c, &mut self.d,
+                       &mut self.e, &mut self.f, &mut self.h, &mut self.l] {
+            **v = r.read_u8()?;
+        }
+        self.sp = r.read_u16()?;
+        self.pc = r.read_u16()?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
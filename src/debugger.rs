@@ -0,0 +1,826 @@
+//! An interactive debugger console.
+//!
+//! [`Debugger::tick`] stands in for [`Gameboy::tick`]. When a breakpoint or
+//! watchpoint is hit, or a break was requested, it stops and reads commands
+//! until told to continue. Type `help` at the prompt for the commands.
+
+use std::io::{self, BufRead, Write};
+use crate::cartridge::Symbols;
+use crate::cpu::Register::{self, *};
+use crate::gameboy::{Color, Gameboy, JoypadInput};
+use crate::memory::memory_bus::MemoryAccess;
+use crate::ram_search::{RamSearch, SearchFilter, SearchFormat};
+
+// How many search candidates `search list` shows.
+const MAX_LISTED: usize = 20;
+
+const HELP: &str = "\
+break <loc> [if <expr>]   stop before running the instruction at <loc>
+watch <loc> [if <expr>]   stop after <loc> is written
+rwatch <loc> [if <expr>]  stop after <loc> is read
+awatch <loc> [if <expr>]  stop after <loc> is read or written
+delete <n>                remove breakpoint or watchpoint <n>
+info                      list breakpoints and watchpoints
+step [n]                  run one (or n) instructions
+next                      run one instruction, stepping over CALL and RST
+finish                    run until the current routine returns
+continue                  run until the next breakpoint or watchpoint
+regs                      show the registers
+backtrace                 show the call stack
+catch                     toggle stopping after a RET that doesn't match its call
+cheat [n]                 list the cheat codes, or turn code <n> on or off
+search new [format]       start a RAM search, format u8 (default), u16, bcd8 or bcd16
+search <filter>           keep the candidates that are same, changed, up or down
+                          since the last search, or equal to a number
+search list               show the candidates
+search watch <n>          watch candidate <n> for writes
+search freeze <n>         hold candidate <n> at its current value
+x <addr> [len]            dump memory
+print <expr>              evaluate an expression
+quit                      stop the emulator
+
+Numbers are decimal, or hex with a $ or 0x prefix. A location is an
+address, a label from the symbol file or bank:address in hex, e.g. 02:4000.
+Labels and bank:address only match while that bank is mapped. Expressions
+can use labels, the registers (a, f, b, c, d, e, h, l, af, bc, de, hl, sp,
+pc), [addr] for the byte at addr, parentheses and
++ - & | == != < <= > >= && || !.
+An empty line repeats the last command.";
+
+/// An expression over registers, memory and labels, e.g. `a == $10 && [hl] != 0`.
+#[derive(Debug, PartialEq)]
+pub enum Expr {
+    Num(i64),
+    Reg(Register),
+    Mem(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BinOp {
+    Add, Sub, BitAnd, BitOr, Eq, Ne, Lt, Le, Gt, Ge, And, Or
+}
+
+impl Expr {
+    /// Parses `s`, resolving labels through `symbols`.
+    pub fn parse(s: &str, symbols: Option<&Symbols>) -> Result<Expr, String> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens: &tokens, pos: 0, symbols };
+        let expr = parser.expr(0)?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(expr),
+            Some(token) => Err(format!("Unexpected {}", token))
+        }
+    }
+
+    pub fn eval(&self, gameboy: &mut Gameboy) -> i64 {
+        match self {
+            Expr::Num(v) => *v,
+            Expr::Reg(r) => read_register(gameboy, *r) as i64,
+            Expr::Mem(addr) => {
+                let addr = addr.eval(gameboy);
+                gameboy.read8(addr as u16) as i64
+            }
+            Expr::Not(e) => (e.eval(gameboy) == 0) as i64,
+            Expr::Binary(op, lhs, rhs) => {
+                let l = lhs.eval(gameboy);
+                // && and || short circuit so [addr] reads stay cheap.
+                match op {
+                    BinOp::And => return (l != 0 && rhs.eval(gameboy) != 0) as i64,
+                    BinOp::Or => return (l != 0 || rhs.eval(gameboy) != 0) as i64,
+                    _ => {}
+                }
+                let r = rhs.eval(gameboy);
+                match op {
+                    BinOp::Add => l.wrapping_add(r),
+                    BinOp::Sub => l.wrapping_sub(r),
+                    BinOp::BitAnd => l & r,
+                    BinOp::BitOr => l | r,
+                    BinOp::Eq => (l == r) as i64,
+                    BinOp::Ne => (l != r) as i64,
+                    BinOp::Lt => (l < r) as i64,
+                    BinOp::Le => (l <= r) as i64,
+                    BinOp::Gt => (l > r) as i64,
+                    BinOp::Ge => (l >= r) as i64,
+                    BinOp::And | BinOp::Or => unreachable!()
+                }
+            }
+        }
+    }
+}
+
+fn read_register(gameboy: &Gameboy, r: Register) -> u16 {
+    match r {
+        AF | BC | DE | HL | SP | PC => gameboy.registers().get16(r),
+        _ => gameboy.registers().get8(r) as u16
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<String>, String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(chars[start..i].iter().collect());
+        } else {
+            let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            if ["==", "!=", "<=", ">=", "&&", "||"].contains(&two.as_str()) {
+                tokens.push(two);
+                i += 2;
+            } else if "+-&|<>!()[]".contains(c) {
+                tokens.push(c.to_string());
+                i += 1;
+            } else {
+                return Err(format!("Unexpected character {}", c));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parses `$1F`, `0x1F` and `31`.
+pub fn parse_number(s: &str) -> Option<i64> {
+    if let Some(hex) = s.strip_prefix('$').or_else(|| s.strip_prefix("0x")) {
+        i64::from_str_radix(hex, 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+fn parse_register(s: &str) -> Option<Register> {
+    match s.to_lowercase().as_str() {
+        "a" => Some(A), "f" => Some(F), "b" => Some(B), "c" => Some(C),
+        "d" => Some(D), "e" => Some(E), "h" => Some(H), "l" => Some(L),
+        "af" => Some(AF), "bc" => Some(BC), "de" => Some(DE), "hl" => Some(HL),
+        "sp" => Some(SP), "pc" => Some(PC),
+        _ => None
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [String],
+    pos: usize,
+    symbols: Option<&'a Symbols>
+}
+
+// Binary operators from loosest to tightest binding.
+const PRECEDENCE: [&[(&str, BinOp)]; 5] = [
+    &[("||", BinOp::Or)],
+    &[("&&", BinOp::And)],
+    &[("==", BinOp::Eq), ("!=", BinOp::Ne), ("<=", BinOp::Le), (">=", BinOp::Ge), ("<", BinOp::Lt), (">", BinOp::Gt)],
+    &[("|", BinOp::BitOr), ("&", BinOp::BitAnd)],
+    &[("+", BinOp::Add), ("-", BinOp::Sub)]
+];
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Result<&'a str, String> {
+        let token = self.tokens.get(self.pos).ok_or_else(|| "Unexpected end of expression".to_string())?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        match self.next()? {
+            t if t == token => Ok(()),
+            t => Err(format!("Expected {} but found {}", token, t))
+        }
+    }
+
+    fn expr(&mut self, level: usize) -> Result<Expr, String> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+        let mut lhs = self.expr(level + 1)?;
+        while let Some(op) = self.peek().and_then(|t| PRECEDENCE[level].iter().find(|(s, _)| *s == t)) {
+            self.pos += 1;
+            let rhs = self.expr(level + 1)?;
+            lhs = Expr::Binary(op.1, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.next()? {
+            "!" => Ok(Expr::Not(Box::new(self.unary()?))),
+            "-" => Ok(Expr::Binary(BinOp::Sub, Box::new(Expr::Num(0)), Box::new(self.unary()?))),
+            "(" => {
+                let expr = self.expr(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            "[" => {
+                let expr = self.expr(0)?;
+                self.expect("]")?;
+                Ok(Expr::Mem(Box::new(expr)))
+            }
+            token => {
+                if let Some(v) = parse_number(token) {
+                    Ok(Expr::Num(v))
+                } else if let Some(r) = parse_register(token) {
+                    Ok(Expr::Reg(r))
+                } else if let Some((_, addr)) = self.symbols.and_then(|s| s.find(token)) {
+                    Ok(Expr::Num(addr as i64))
+                } else {
+                    Err(format!("Unknown value {}", token))
+                }
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum WatchKind {
+    Read,
+    Write,
+    Access
+}
+
+// An address, limited to one bank when it came from a label or bank:addr.
+#[derive(Copy, Clone)]
+struct Location {
+    bank: Option<usize>,
+    addr: u16
+}
+
+impl Location {
+    fn matches(&self, gameboy: &mut Gameboy, addr: u16) -> bool {
+        self.addr == addr && self.bank.is_none_or(|bank| gameboy.bank(addr) == bank)
+    }
+}
+
+enum Point {
+    Break(Location),
+    Watch(WatchKind, Location)
+}
+
+// A stop's condition, with the text it was parsed from for `info`.
+struct Condition {
+    source: String,
+    expr: Expr
+}
+
+struct Stop {
+    id: usize,
+    point: Point,
+    condition: Option<Condition>
+}
+
+pub struct Debugger {
+    symbols: Option<Symbols>,
+    stops: Vec<Stop>,
+    next_id: usize,
+    break_requested: bool,
+    catch_bad_returns: bool,
+    search: Option<RamSearch>,
+    // Set when resuming so the breakpoint we are sitting on doesn't fire again.
+    resuming: bool,
+    last_command: String,
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>
+}
+
+enum Resume {
+    Continue,
+    Quit
+}
+
+impl Debugger {
+    /// A debugger that talks on stdin and stdout.
+    pub fn new(symbols: Option<Symbols>) -> Self {
+        Self::with_io(symbols, Box::new(io::BufReader::new(io::stdin())), Box::new(io::stdout()))
+    }
+
+    pub fn with_io(symbols: Option<Symbols>, input: Box<dyn BufRead>, output: Box<dyn Write>) -> Self {
+        Debugger {
+            symbols,
+            stops: Vec::new(),
+            next_id: 1,
+            break_requested: false,
+            catch_bad_returns: false,
+            search: None,
+            resuming: false,
+            last_command: String::new(),
+            input,
+            output
+        }
+    }
+
+    /// Stops before the next instruction.
+    pub fn request_break(&mut self) {
+        self.break_requested = true;
+    }
+
+    /// Adds a breakpoint at an address or label, e.g. from `--break-at`.
+    pub fn break_at(&mut self, location: &str) -> Result<(), String> {
+        let location = self.parse_location(location)?;
+        self.add(Point::Break(location), None);
+        Ok(())
+    }
+
+    fn active(&self) -> bool {
+        self.break_requested || self.catch_bad_returns || !self.stops.is_empty()
+    }
+
+    /// Runs until the next VBlank, stopping for the console whenever a
+    /// breakpoint or watchpoint is hit. Returns false once the user quits.
+    pub fn tick(&mut self, gameboy: &mut Gameboy, pressed_inputs: &[JoypadInput],
+                frame_buffer: &mut [Color], audio_queue: &mut Vec<f32>) -> bool {
+        if !self.active() {
+            gameboy.tick(pressed_inputs, frame_buffer, audio_queue);
+            return true;
+        }
+
+        gameboy.set_pressed(pressed_inputs);
+        gameboy.set_access_logging(self.has_watchpoints());
+        loop {
+            if let Some(why) = self.check_before(gameboy) {
+                if let Resume::Quit = self.console(gameboy, frame_buffer, audio_queue, &why) {
+                    return false;
+                }
+            }
+            let vblank = gameboy.step(frame_buffer, audio_queue);
+            if let Some(why) = self.check_after(gameboy) {
+                if let Resume::Quit = self.console(gameboy, frame_buffer, audio_queue, &why) {
+                    return false;
+                }
+            }
+            if vblank {
+                return true;
+            }
+        }
+    }
+
+    fn check_before(&mut self, gameboy: &mut Gameboy) -> Option<String> {
+        if std::mem::replace(&mut self.break_requested, false) {
+            return Some("Interrupted".to_string());
+        }
+        if std::mem::replace(&mut self.resuming, false) {
+            return None;
+        }
+        let pc = gameboy.registers().pc();
+        for stop in &self.stops {
+            if let Point::Break(location) = stop.point {
+                if location.matches(gameboy, pc) && stop.condition.as_ref().is_none_or(|c| c.expr.eval(gameboy) != 0) {
+                    return Some(format!("Breakpoint {}", stop.id));
+                }
+            }
+        }
+        None
+    }
+
+    fn check_after(&mut self, gameboy: &mut Gameboy) -> Option<String> {
+        if self.catch_bad_returns {
+            if let Some(bad_return) = gameboy.bad_return() {
+                return Some(bad_return.describe(self.symbols.as_ref()));
+            }
+        }
+        for stop in &self.stops {
+            if let Point::Watch(kind, location) = stop.point {
+                let addr = location.addr;
+                let hit = gameboy.accesses().iter().find_map(|access| match (*access, kind) {
+                    (MemoryAccess::Read(a), WatchKind::Read) |
+                    (MemoryAccess::Read(a), WatchKind::Access) if a == addr => Some("read".to_string()),
+                    (MemoryAccess::Write(a, v), WatchKind::Write) |
+                    (MemoryAccess::Write(a, v), WatchKind::Access) if a == addr => Some(format!("write of ${:02X}", v)),
+                    _ => None
+                });
+                if let Some(hit) = hit.filter(|_| location.matches(gameboy, addr)) {
+                    if stop.condition.as_ref().is_none_or(|c| c.expr.eval(gameboy) != 0) {
+                        return Some(format!("Watchpoint {}: {} at ${:04X}", stop.id, hit, addr));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn add(&mut self, point: Point, condition: Option<Condition>) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.stops.push(Stop { id, point, condition });
+        id
+    }
+
+    fn has_watchpoints(&self) -> bool {
+        self.stops.iter().any(|stop| matches!(stop.point, Point::Watch(..)))
+    }
+
+    fn parse_location(&self, s: &str) -> Result<Location, String> {
+        if let Some((bank, addr)) = s.split_once(':') {
+            let bank = usize::from_str_radix(bank, 16).map_err(|_| format!("Bad bank {}", bank))?;
+            let addr = u16::from_str_radix(addr, 16).map_err(|_| format!("Bad address {}", addr))?;
+            return Ok(Location { bank: Some(bank), addr });
+        }
+        if let Some((bank, addr)) = self.symbols.as_ref().and_then(|symbols| symbols.find(s)) {
+            return Ok(Location { bank: Some(bank), addr });
+        }
+        parse_number(s)
+            .filter(|addr| (0..=0xFFFF).contains(addr))
+            .map(|addr| Location { bank: None, addr: addr as u16 })
+            .ok_or_else(|| format!("Unknown location {}", s))
+    }
+
+    fn label(&self, bank: usize, addr: u16) -> String {
+        match self.symbols.as_ref().and_then(|s| s.get(bank, addr)) {
+            Some(label) => format!(" <{}>", label),
+            None => String::new()
+        }
+    }
+
+    fn format_location(&self, location: Location) -> String {
+        match location.bank {
+            Some(bank) => format!("${:02X}:{:04X}{}", bank, location.addr, self.label(bank, location.addr)),
+            None => format!("${:04X}", location.addr)
+        }
+    }
+
+    fn describe(&self, stop: &Stop) -> String {
+        let what = match stop.point {
+            Point::Break(location) => format!("break  {}", self.format_location(location)),
+            Point::Watch(kind, location) => {
+                let name = match kind { WatchKind::Read => "rwatch", WatchKind::Write => "watch ", WatchKind::Access => "awatch" };
+                format!("{} {}", name, self.format_location(location))
+            }
+        };
+        match &stop.condition {
+            Some(condition) => format!("{:>3}  {} if {}", stop.id, what, condition.source),
+            None => format!("{:>3}  {}", stop.id, what)
+        }
+    }
+
+    fn print_location(&mut self, gameboy: &mut Gameboy) {
+        let pc = gameboy.registers().pc();
+        let (text, _) = gameboy.disassemble(pc);
+        let label = self.label(gameboy.bank(pc), pc);
+        let _ = writeln!(self.output, "${:04X}{}: {}", pc, label, text);
+    }
+
+    fn print_registers(&mut self, gameboy: &Gameboy) {
+        let r = gameboy.registers();
+        let flags: String = [(r.z_flag(), 'Z'), (r.n_flag(), 'N'), (r.h_flag(), 'H'), (r.cy_flag(), 'C')].iter()
+            .map(|(set, name)| if *set == 1 { *name } else { '-' })
+            .collect();
+        let _ = writeln!(self.output, "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} {}",
+                         r.get8(A), r.get8(F), r.get8(B), r.get8(C), r.get8(D), r.get8(E),
+                         r.get8(H), r.get8(L), r.get16(SP), r.get16(PC), flags);
+    }
+
+    // Reads and runs commands until one resumes the machine.
+    fn console(&mut self, gameboy: &mut Gameboy, frame_buffer: &mut [Color],
+               audio_queue: &mut Vec<f32>, why: &str) -> Resume {
+        let _ = writeln!(self.output, "{}", why);
+        self.print_location(gameboy);
+        loop {
+            let _ = write!(self.output, "(gbdb) ");
+            let _ = self.output.flush();
+            let mut line = String::new();
+            match self.input.read_line(&mut line) {
+                Ok(0) | Err(_) => return Resume::Quit,
+                Ok(_) => {}
+            }
+            let mut line = line.trim().to_string();
+            if line.is_empty() {
+                line = self.last_command.clone();
+            } else {
+                self.last_command = line.clone();
+            }
+
+            match self.command(&line, gameboy, frame_buffer, audio_queue) {
+                Ok(Some(resume)) => {
+                    gameboy.set_access_logging(self.has_watchpoints());
+                    self.resuming = true;
+                    return resume;
+                }
+                Ok(None) => gameboy.set_access_logging(self.has_watchpoints()),
+                Err(e) => { let _ = writeln!(self.output, "{}", e); }
+            }
+        }
+    }
+
+    fn command(&mut self, line: &str, gameboy: &mut Gameboy, frame_buffer: &mut [Color],
+               audio_queue: &mut Vec<f32>) -> Result<Option<Resume>, String> {
+        let (command, args) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, "")
+        };
+        // "<target> if <condition>"
+        let (target, condition) = match args.find(" if ") {
+            Some(i) => {
+                let source = args[i + 4..].trim();
+                let expr = Expr::parse(source, self.symbols.as_ref())?;
+                (&args[..i], Some(Condition { source: source.to_string(), expr }))
+            }
+            None => (args, None)
+        };
+
+        match command {
+            "" => {}
+            "h" | "help" => { let _ = writeln!(self.output, "{}", HELP); }
+            "b" | "break" => {
+                let location = self.parse_location(target)?;
+                let id = self.add(Point::Break(location), condition);
+                let _ = writeln!(self.output, "Breakpoint {} at {}", id, self.format_location(location));
+            }
+            "watch" | "rwatch" | "awatch" => {
+                let kind = match command { "rwatch" => WatchKind::Read, "awatch" => WatchKind::Access, _ => WatchKind::Write };
+                let location = self.parse_location(target)?;
+                let id = self.add(Point::Watch(kind, location), condition);
+                let _ = writeln!(self.output, "Watchpoint {} at {}", id, self.format_location(location));
+            }
+            "d" | "delete" => {
+                let id: usize = args.parse().map_err(|_| format!("Bad number {}", args))?;
+                let len = self.stops.len();
+                self.stops.retain(|stop| stop.id != id);
+                if self.stops.len() == len {
+                    return Err(format!("No breakpoint or watchpoint {}", id));
+                }
+            }
+            "i" | "info" => {
+                if self.stops.is_empty() {
+                    let _ = writeln!(self.output, "No breakpoints or watchpoints");
+                }
+                let lines: Vec<String> = self.stops.iter().map(|stop| self.describe(stop)).collect();
+                for line in lines {
+                    let _ = writeln!(self.output, "{}", line);
+                }
+            }
+            "s" | "step" => {
+                let n = if args.is_empty() { 1 } else { args.parse().map_err(|_| format!("Bad count {}", args))? };
+                for _ in 0..n {
+                    gameboy.step(frame_buffer, audio_queue);
+                }
+                self.print_location(gameboy);
+            }
+            "n" | "next" => {
+                let pc = gameboy.registers().pc();
+                let (_, len) = gameboy.disassemble(pc);
+                let sp = gameboy.registers().get16(SP);
+                match gameboy.read8(pc) {
+                    0xCD | 0xC4 | 0xCC | 0xD4 | 0xDC | 0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => {
+                        let ret = pc.wrapping_add(len);
+                        self.run_until(gameboy, frame_buffer, audio_queue, |gameboy| {
+                            gameboy.registers().pc() == ret && gameboy.registers().get16(SP) >= sp
+                        });
+                    }
+                    _ => {
+                        gameboy.step(frame_buffer, audio_queue);
+                        self.print_location(gameboy);
+                    }
+                }
+            }
+            "finish" => {
+                let sp = gameboy.registers().get16(SP);
+                let mut returning = false;
+                self.run_until(gameboy, frame_buffer, audio_queue, |gameboy| {
+                    let done = returning && gameboy.registers().get16(SP) > sp;
+                    let pc = gameboy.registers().pc();
+                    returning = matches!(gameboy.read8(pc), 0xC9 | 0xD9 | 0xC0 | 0xC8 | 0xD0 | 0xD8);
+                    done
+                });
+            }
+            "c" | "continue" => return Ok(Some(Resume::Continue)),
+            "r" | "regs" => self.print_registers(gameboy),
+            "bt" | "backtrace" => {
+                let backtrace = gameboy.backtrace();
+                let _ = writeln!(self.output, "{}", backtrace);
+            }
+            "cheat" => {
+                if args.is_empty() {
+                    for (i, cheat) in gameboy.cheats().iter().enumerate() {
+                        let _ = writeln!(self.output, "{:>3}  {}", i, cheat);
+                    }
+                } else {
+                    let n: usize = args.parse().map_err(|_| format!("Bad cheat {}", args))?;
+                    let enabled = !gameboy.cheats().get(n).ok_or_else(|| format!("No cheat {}", n))?.enabled;
+                    gameboy.set_cheat_enabled(n, enabled);
+                }
+            }
+            "search" => self.search(args, gameboy)?,
+            "catch" => {
+                self.catch_bad_returns = !self.catch_bad_returns;
+                let _ = writeln!(self.output, "{} on mismatched returns",
+                                 if self.catch_bad_returns { "Stopping" } else { "Not stopping" });
+            }
+            "x" => {
+                let mut parts = args.split_whitespace();
+                let addr = self.parse_location(parts.next().ok_or("x needs an address")?)?.addr;
+                let len = match parts.next() {
+                    Some(len) => parse_number(len).ok_or_else(|| format!("Bad length {}", len))? as usize,
+                    None => 16
+                };
+                for row in (0..len).step_by(16) {
+                    let start = addr.wrapping_add(row as u16);
+                    let bytes: Vec<String> = (0..(len - row).min(16))
+                        .map(|i| format!("{:02X}", gameboy.read8(start.wrapping_add(i as u16))))
+                        .collect();
+                    let _ = writeln!(self.output, "${:04X}: {}", start, bytes.join(" "));
+                }
+            }
+            "p" | "print" => {
+                let v = Expr::parse(args, self.symbols.as_ref())?.eval(gameboy);
+                let _ = writeln!(self.output, "{} (${:X})", v, v);
+            }
+            "q" | "quit" => return Ok(Some(Resume::Quit)),
+            _ => return Err(format!("Unknown command {}, try help", command))
+        }
+        Ok(None)
+    }
+
+    fn search(&mut self, args: &str, gameboy: &mut Gameboy) -> Result<(), String> {
+        let (verb, arg) = args.split_once(' ').map_or((args, ""), |(verb, arg)| (verb, arg.trim()));
+        if verb == "new" {
+            let format = if arg.is_empty() { SearchFormat::U8 } else { arg.parse()? };
+            self.search = Some(RamSearch::new(gameboy, format));
+        } else {
+            let search = self.search.as_mut().ok_or("No search, start one with search new")?;
+            let candidate = |search: &RamSearch| {
+                let n: usize = arg.parse().map_err(|_| format!("Bad candidate {}", arg))?;
+                search.results().nth(n).ok_or_else(|| format!("No candidate {}", n))
+            };
+            match verb {
+                "list" => {
+                    for (i, result) in search.results().take(MAX_LISTED).enumerate() {
+                        let _ = writeln!(self.output, "{:>3}  ${:02X}:{:04X}  {} (was {})",
+                                         i, result.bank, result.addr, result.value, result.previous);
+                    }
+                    if search.len() > MAX_LISTED {
+                        let _ = writeln!(self.output, "     ... {} more", search.len() - MAX_LISTED);
+                    }
+                    return Ok(());
+                }
+                "watch" => {
+                    let result = candidate(search)?;
+                    for i in 0..search.format().size() as u16 {
+                        let location = Location { bank: Some(result.bank), addr: result.addr + i };
+                        let id = self.add(Point::Watch(WatchKind::Write, location), None);
+                        let _ = writeln!(self.output, "Watchpoint {} at {}", id, self.format_location(location));
+                    }
+                    return Ok(());
+                }
+                "freeze" => {
+                    let result = candidate(search)?;
+                    for cheat in search.freeze(gameboy, &result) {
+                        gameboy.add_cheat(cheat);
+                    }
+                    return Ok(());
+                }
+                _ => {}
+            }
+            let filter = match verb {
+                "same" => SearchFilter::Unchanged,
+                "changed" => SearchFilter::Changed,
+                "up" => SearchFilter::Increased,
+                "down" => SearchFilter::Decreased,
+                _ => SearchFilter::EqualTo(parse_number(verb).ok_or_else(|| format!("Unknown search filter {}", verb))? as u32)
+            };
+            search.filter(gameboy, filter);
+        }
+        let _ = writeln!(self.output, "{} candidates", self.search.as_ref().unwrap().len());
+        Ok(())
+    }
+
+    // Steps until `done` says so, stopping early for breakpoints and
+    // watchpoints. Either way the console stays open at the new location.
+    fn run_until<P>(&mut self, gameboy: &mut Gameboy, frame_buffer: &mut [Color],
+                    audio_queue: &mut Vec<f32>, mut done: P)
+        where P: FnMut(&mut Gameboy) -> bool {
+        gameboy.set_access_logging(self.has_watchpoints());
+        done(gameboy);
+        self.resuming = true;
+        loop {
+            if let Some(why) = self.check_before(gameboy) {
+                let _ = writeln!(self.output, "{}", why);
+                self.print_location(gameboy);
+                return;
+            }
+            gameboy.step(frame_buffer, audio_queue);
+            if let Some(why) = self.check_after(gameboy) {
+                let _ = writeln!(self.output, "{}", why);
+                self.print_location(gameboy);
+                return;
+            }
+            if done(gameboy) {
+                self.print_location(gameboy);
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::{booted_gameboy, test_frame_buffer, Mode, CALL_PROGRAM};
+
+    fn test_gameboy() -> Gameboy {
+        booted_gameboy("debugger", &CALL_PROGRAM, Mode::DMG)
+    }
+
+    fn run(gameboy: &mut Gameboy, script: &str) -> bool {
+        let symbols = Symbols::parse("00:0109 sub\n01:0100 elsewhere\n");
+        let mut debugger = Debugger::with_io(Some(symbols), Box::new(io::Cursor::new(script.to_string())), Box::new(io::sink()));
+        debugger.request_break();
+        let mut frame_buffer = test_frame_buffer();
+        debugger.tick(gameboy, &[], &mut frame_buffer, &mut Vec::new())
+    }
+
+    #[test]
+    fn test_expressions() {
+        let mut gameboy = test_gameboy();
+        gameboy.registers_mut().set8(A, 0x10);
+        gameboy.write8(0xC000, 7);
+        let eval = |s: &str, gameboy: &mut Gameboy| Expr::parse(s, None).unwrap().eval(gameboy);
+
+        assert_eq!(eval("a == $10 && [0xC000] == 7", &mut gameboy), 1);
+        assert_eq!(eval("a + 1 - 2", &mut gameboy), 0x0F);
+        assert_eq!(eval("(pc | 1) & $FF", &mut gameboy), 0x01);
+        assert_eq!(eval("!(a < 16) || sp >= $FFFE", &mut gameboy), 1);
+        assert_eq!(eval("[$C000 + a - 16]", &mut gameboy), 7);
+        assert!(Expr::parse("a ==", None).is_err());
+        assert!(Expr::parse("nowhere", None).is_err());
+        assert!(Expr::parse("(a", None).is_err());
+    }
+
+    #[test]
+    fn test_step_next_finish() {
+        let mut gameboy = test_gameboy();
+        assert!(!run(&mut gameboy, "step\nnext\nquit\n"));
+        assert_eq!(gameboy.registers().pc(), 0x104);
+        assert_eq!(gameboy.registers().get8(B), 1);
+
+        let mut gameboy = test_gameboy();
+        assert!(!run(&mut gameboy, "step 2\nfinish\nquit\n"));
+        assert_eq!(gameboy.registers().pc(), 0x104);
+        assert_eq!(gameboy.registers().get16(SP), 0xFFFE);
+    }
+
+    #[test]
+    fn test_breakpoints_and_watchpoints() {
+        let mut gameboy = test_gameboy();
+        run(&mut gameboy, "break $109 if b == 2\ncontinue\nquit\n");
+        assert_eq!(gameboy.registers().pc(), 0x109);
+        assert_eq!(gameboy.registers().get8(B), 2);
+
+        // A label in another bank never matches.
+        let mut gameboy = test_gameboy();
+        run(&mut gameboy, "break elsewhere\nbreak sub if b == 1\ncontinue\nquit\n");
+        assert_eq!(gameboy.registers().pc(), 0x109);
+        assert_eq!(gameboy.registers().get8(B), 1);
+
+        let mut gameboy = test_gameboy();
+        run(&mut gameboy, "watch $C000 if [$C000] == 3\ncontinue\nquit\n");
+        assert_eq!(gameboy.registers().pc(), 0x107);
+        assert_eq!(gameboy.read8(0xC000), 3);
+    }
+
+    #[test]
+    fn test_info() {
+        let mut gameboy = test_gameboy();
+        let mut debugger = Debugger::with_io(None, Box::new(io::empty()), Box::new(io::sink()));
+        let mut frame_buffer = test_frame_buffer();
+        debugger.command("break $109 if b == 2", &mut gameboy, &mut frame_buffer, &mut Vec::new()).unwrap();
+        debugger.command("watch $C000", &mut gameboy, &mut frame_buffer, &mut Vec::new()).unwrap();
+        let lines: Vec<String> = debugger.stops.iter().map(|stop| debugger.describe(stop)).collect();
+        assert_eq!(lines, ["  1  break  $0109 if b == 2", "  2  watch  $C000"]);
+    }
+
+    #[test]
+    fn test_backtrace_and_catch() {
+        let mut gameboy = test_gameboy();
+        run(&mut gameboy, "break sub\ncontinue\nquit\n");
+        assert_eq!(gameboy.backtrace(), "#0  00:0109\n#1  00:0101 (call)");
+
+        // CALL $0106; JR -5; PUSH BC; RET returns to BC instead.
+        let mut gameboy = booted_gameboy("debugger", &[0xCD, 0x06, 0x01, 0x18, 0xFB, 0x00, 0xC5, 0xC9], Mode::DMG);
+        run(&mut gameboy, "catch\ncontinue\nquit\n");
+        assert_eq!(gameboy.registers().pc(), 0x0013);
+        assert_eq!(gameboy.bad_return().unwrap().at, (0, 0x107));
+        assert_eq!(gameboy.call_stack().len(), 1);
+    }
+
+    #[test]
+    fn test_search() {
+        // A is stored to $C000 after every INC A. The return address pushed to
+        // $FFFD also goes up but comes after it.
+        let mut gameboy = test_gameboy();
+        run(&mut gameboy, "search new\nstep 5\nsearch up\nsearch 2\nsearch watch 0\nsearch freeze 0\ncontinue\nquit\n");
+        assert_eq!(gameboy.cheats().len(), 1);
+        assert_eq!(gameboy.cheats()[0].to_string(), "[x] 00:C000=$02");
+        assert_eq!(gameboy.registers().pc(), 0x107);
+        assert_eq!(gameboy.read8(0xC000), 3);
+    }
+}
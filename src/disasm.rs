@@ -0,0 +1,517 @@
+//! A ROM disassembler that writes RGBDS source.
+//!
+//! Code is found by tracing from the entry point and the RST and interrupt
+//! vectors, following every jump and call. Everything that isn't reached
+//! that way is written as data, so the output always reassembles to the
+//! same bytes.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use crate::cartridge::Symbols;
+use crate::cpu::{FlagCondition, Instr, Opcode, Register, Src};
+
+const BANK_SIZE: usize = 0x4000;
+const ENTRY_POINTS: [u16; 14] = [
+    0x0100,
+    0x0000, 0x0008, 0x0010, 0x0018, 0x0020, 0x0028, 0x0030, 0x0038,
+    0x0040, 0x0048, 0x0050, 0x0058, 0x0060
+];
+// Runs of at least this many equal bytes are written with `ds`.
+const MIN_FILL: usize = 16;
+
+fn reg(r: Register) -> String {
+    format!("{:?}", r).to_lowercase()
+}
+
+fn condition(c: FlagCondition) -> &'static str {
+    match c {
+        FlagCondition::Z => "z, ",
+        FlagCondition::NZ => "nz, ",
+        FlagCondition::C => "c, ",
+        FlagCondition::NC => "nc, ",
+        FlagCondition::ALWAYS => ""
+    }
+}
+
+fn address(addr: u16, name: &dyn Fn(u16) -> Option<String>) -> String {
+    name(addr).unwrap_or_else(|| format!("${:04X}", addr))
+}
+
+fn src(s: Src, name: &dyn Fn(u16) -> Option<String>) -> String {
+    match s {
+        Src::Reg(r) => reg(r),
+        Src::Deref(Register::C) => "[$FF00+c]".to_string(),
+        Src::Deref(r) => format!("[{}]", reg(r)),
+        Src::A8(n) => format!("[{}]", address(0xFF00 | n as u16, name)),
+        Src::A16(addr) => format!("[{}]", address(addr, name)),
+        Src::D8(v) => format!("${:02X}", v),
+        Src::D16(v) => format!("${:04X}", v),
+        Src::I8(v) => format!("{}", v)
+    }
+}
+
+/// Writes the instruction at `addr` in RGBDS syntax. `name` gives the label
+/// to use for an address the instruction refers to. Relative jumps with no
+/// label are written as `@+n`.
+pub(crate) fn format_opcode(opcode: Opcode, addr: u16, name: &dyn Fn(u16) -> Option<String>) -> String {
+    use Opcode::*;
+    let s = |s| src(s, name);
+    match opcode {
+        NOP => "nop".to_string(),
+        LD8(dst @ Src::A8(_), from) | LD8(dst, from @ Src::A8(_)) => format!("ldh {}, {}", s(dst), s(from)),
+        LD8(dst, from) => format!("ld {}, {}", s(dst), s(from)),
+        LD8I(Src::Deref(Register::HL), from) => format!("ld [hl+], {}", s(from)),
+        LD8I(dst, _) => format!("ld {}, [hl+]", s(dst)),
+        LD8D(Src::Deref(Register::HL), from) => format!("ld [hl-], {}", s(from)),
+        LD8D(dst, _) => format!("ld {}, [hl-]", s(dst)),
+        LD16(dst, Src::I8(offset)) => format!("ld {}, sp{:+}", s(dst), offset),
+        LD16(dst, from) => format!("ld {}, {}", s(dst), s(from)),
+        PUSH(r) => format!("push {}", reg(r)),
+        POP(r) => format!("pop {}", reg(r)),
+        ADD8(from) => format!("add a, {}", s(from)),
+        ADC8(from) => format!("adc a, {}", s(from)),
+        SUB8(from) => format!("sub {}", s(from)),
+        SBC8(from) => format!("sbc a, {}", s(from)),
+        AND(from) => format!("and {}", s(from)),
+        OR(from) => format!("or {}", s(from)),
+        XOR(from) => format!("xor {}", s(from)),
+        CP(from) => format!("cp {}", s(from)),
+        INC8(dst) => format!("inc {}", s(dst)),
+        DEC8(dst) => format!("dec {}", s(dst)),
+        ADD16(r, from) => format!("add {}, {}", reg(r), s(from)),
+        INC16(r) => format!("inc {}", reg(r)),
+        DEC16(r) => format!("dec {}", reg(r)),
+        RLCA => "rlca".to_string(),
+        RLA => "rla".to_string(),
+        RRCA => "rrca".to_string(),
+        RRA => "rra".to_string(),
+        RLC(dst) => format!("rlc {}", s(dst)),
+        RL(dst) => format!("rl {}", s(dst)),
+        RRC(dst) => format!("rrc {}", s(dst)),
+        RR(dst) => format!("rr {}", s(dst)),
+        SLA(dst) => format!("sla {}", s(dst)),
+        SRA(dst) => format!("sra {}", s(dst)),
+        SRL(dst) => format!("srl {}", s(dst)),
+        SWAP(dst) => format!("swap {}", s(dst)),
+        BIT(n, dst) => format!("bit {}, {}", n, s(dst)),
+        SET(n, dst) => format!("set {}, {}", n, s(dst)),
+        RES(n, dst) => format!("res {}, {}", n, s(dst)),
+        JP(_, Src::Reg(r)) => format!("jp {}", reg(r)),
+        JP(c, target) => match target {
+            Src::D16(target) => format!("jp {}{}", condition(c), address(target, name)),
+            _ => format!("jp {}{}", condition(c), s(target))
+        },
+        JR(c, offset) => {
+            let target = addr.wrapping_add(2).wrapping_add(offset as i16 as u16);
+            let target = name(target).unwrap_or_else(|| format!("@{:+}", offset as i16 + 2));
+            format!("jr {}{}", condition(c), target)
+        }
+        CALL(c, target) => format!("call {}{}", condition(c), address(target, name)),
+        RET(FlagCondition::ALWAYS) => "ret".to_string(),
+        RET(c) => format!("ret {}", &condition(c)[..condition(c).len() - 2]),
+        RETI => "reti".to_string(),
+        RST(n) => format!("rst ${:02X}", n),
+        DAA => "daa".to_string(),
+        CPL => "cpl".to_string(),
+        CCF => "ccf".to_string(),
+        SCF => "scf".to_string(),
+        DI => "di".to_string(),
+        EI => "ei".to_string(),
+        HALT => "halt".to_string(),
+        STOP => "stop".to_string(),
+        INVALID(b) => format!("db ${:02X}", b)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Kind {
+    Data,
+    Code(u16),
+    Operand
+}
+
+// Why an address is a label, in increasing order of importance.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Target {
+    Jr,
+    Jump,
+    Call
+}
+
+struct Disassembler<'a> {
+    rom: &'a [u8],
+    symbols: Option<&'a Symbols>,
+    kinds: Vec<Kind>,
+    targets: HashMap<(usize, u16), Target>,
+    // The bank a jump from bank 0 into 0x4000-0x7FFF was found to go to,
+    // by the offset of the jump.
+    switched_banks: HashMap<usize, usize>,
+    queue: Vec<(usize, u16)>,
+    names: HashMap<(usize, u16), String>,
+    ram_names: HashMap<u16, String>,
+    constants: Vec<(String, u16)>
+}
+
+impl<'a> Disassembler<'a> {
+    fn new(rom: &'a [u8], symbols: Option<&'a Symbols>) -> Self {
+        Disassembler {
+            rom,
+            symbols,
+            kinds: vec![Kind::Data; rom.len()],
+            targets: HashMap::new(),
+            switched_banks: HashMap::new(),
+            queue: Vec::new(),
+            names: HashMap::new(),
+            ram_names: HashMap::new(),
+            constants: Vec::new()
+        }
+    }
+
+    fn banks(&self) -> usize {
+        self.rom.len().div_ceil(BANK_SIZE).max(1)
+    }
+
+    fn offset(&self, bank: usize, addr: u16) -> Option<usize> {
+        let offset = match (bank, addr) {
+            (0, 0x0000 ..= 0x3FFF) => addr as usize,
+            (1 .., 0x4000 ..= 0x7FFF) => bank * BANK_SIZE + addr as usize - BANK_SIZE,
+            _ => return None
+        };
+        Some(offset).filter(|offset| *offset < self.rom.len())
+    }
+
+    // The bank `target` is in when jumped to from `bank`. Jumps from bank 0
+    // into the switchable bank use `switched`, the bank last selected.
+    fn target_bank(&self, bank: usize, target: u16, switched: Option<usize>) -> Option<usize> {
+        match target {
+            0x0000 ..= 0x3FFF => Some(0),
+            0x4000 ..= 0x7FFF if bank != 0 => Some(bank),
+            0x4000 ..= 0x7FFF => switched.or(if self.banks() == 2 { Some(1) } else { None }),
+            _ => None
+        }
+    }
+
+    fn decode(&self, bank: usize, addr: u16) -> Option<(Instr, u16)> {
+        let offset = self.offset(bank, addr)?;
+        let bytes: Vec<u8> = (0..3).map(|i| self.rom.get(offset + i).copied().unwrap_or(0)).collect();
+        let instr = Instr::disassemble(&bytes);
+        // rgbasm always writes STOP as 10 00.
+        let len = match instr.opcode {
+            Opcode::STOP if bytes[1] == 0 => 2,
+            Opcode::STOP => return None,
+            _ => instr.n_bytes
+        };
+        Some((instr, len))
+    }
+
+    fn add_target(&mut self, bank: Option<usize>, target: u16, kind: Target) {
+        if let Some(bank) = bank.filter(|bank| self.offset(*bank, target).is_some()) {
+            let entry = self.targets.entry((bank, target)).or_insert(kind);
+            *entry = (*entry).max(kind);
+            self.queue.push((bank, target));
+        }
+    }
+
+    fn trace(&mut self, bank: usize, mut addr: u16) {
+        let mut switched = None;
+        let mut loaded_a = None;
+        loop {
+            let (instr, len) = match self.decode(bank, addr) {
+                Some(decoded) => decoded,
+                None => return
+            };
+            let offset = self.offset(bank, addr).unwrap();
+            let free = (0..len).all(|i| {
+                addr.checked_add(i).and_then(|a| self.offset(bank, a))
+                    .is_some_and(|o| self.kinds[o] == Kind::Data)
+            });
+            if !free {
+                return;
+            }
+            self.kinds[offset] = Kind::Code(len);
+            for i in 1..len as usize {
+                self.kinds[offset + i] = Kind::Operand;
+            }
+
+            let next = addr.wrapping_add(len);
+            let a = loaded_a.take();
+            let ends = match instr.opcode {
+                Opcode::LD8(Src::Reg(Register::A), Src::D8(v)) => {
+                    loaded_a = Some(v);
+                    false
+                }
+                // ld a, n / ld [$2000], a selects a ROM bank.
+                Opcode::LD8(Src::A16(0x2000 ..= 0x3FFF), Src::Reg(Register::A)) => {
+                    if let Some(v) = a {
+                        switched = Some((v as usize).max(1));
+                    }
+                    false
+                }
+                Opcode::JP(c, Src::D16(target)) | Opcode::CALL(c, target) => {
+                    let kind = if let Opcode::CALL(..) = instr.opcode { Target::Call } else { Target::Jump };
+                    let target_bank = self.target_bank(bank, target, switched);
+                    if bank == 0 && (0x4000 ..= 0x7FFF).contains(&target) {
+                        if let Some(target_bank) = target_bank {
+                            self.switched_banks.insert(offset, target_bank);
+                        }
+                    }
+                    self.add_target(target_bank, target, kind);
+                    kind == Target::Jump && matches!(c, FlagCondition::ALWAYS)
+                }
+                Opcode::JR(c, jump) => {
+                    let target = next.wrapping_add(jump as i16 as u16);
+                    self.add_target(self.target_bank(bank, target, switched), target, Target::Jr);
+                    matches!(c, FlagCondition::ALWAYS)
+                }
+                Opcode::RST(n) => {
+                    self.add_target(Some(0), n as u16, Target::Call);
+                    false
+                }
+                Opcode::JP(..) | Opcode::RET(FlagCondition::ALWAYS) | Opcode::RETI | Opcode::INVALID(_) => true,
+                _ => false
+            };
+            if ends {
+                return;
+            }
+            addr = next;
+        }
+    }
+
+    fn trace_all(&mut self) {
+        for addr in ENTRY_POINTS.iter() {
+            self.queue.push((0, *addr));
+        }
+        while let Some((bank, addr)) = self.queue.pop() {
+            self.trace(bank, addr);
+        }
+    }
+
+    fn labelable(&self, bank: usize, addr: u16) -> bool {
+        self.offset(bank, addr).is_some_and(|offset| self.kinds[offset] != Kind::Operand)
+    }
+
+    // Names every label that can be placed, keeping rgbasm's rules for
+    // local labels: `Parent.child` may only follow `Parent`.
+    fn name_labels(&mut self) {
+        let mut labels: HashMap<(usize, u16), Option<String>> = self.targets.keys()
+            .map(|loc| (*loc, None))
+            .collect();
+        let mut ram_labels: HashMap<u16, Vec<String>> = HashMap::new();
+        if let Some(symbols) = self.symbols {
+            for (bank, addr, name) in symbols.iter() {
+                if addr < 0x8000 {
+                    // An address with several labels takes the first.
+                    if self.labelable(bank, addr) {
+                        labels.entry((bank, addr)).or_insert(None).get_or_insert_with(|| name.to_string());
+                    }
+                } else {
+                    ram_labels.entry(addr).or_default().push(name.replace('.', "_"));
+                }
+            }
+        }
+
+        let mut sorted: Vec<_> = labels.into_iter().filter(|(loc, _)| self.labelable(loc.0, loc.1)).collect();
+        sorted.sort();
+        let mut scope: Option<(usize, String)> = None;
+        for ((bank, addr), name) in sorted {
+            let scope_name = scope.as_ref().filter(|(b, _)| *b == bank).map(|(_, name)| name.clone());
+            let name = match name {
+                Some(name) => match name.split_once('.') {
+                    Some((parent, _)) if Some(parent) == scope_name.as_deref() => name,
+                    Some(_) => name.replace('.', "_"),
+                    None => name
+                },
+                None => {
+                    let prefix = match self.targets[&(bank, addr)] {
+                        Target::Jr => "jr",
+                        Target::Jump => "Jump",
+                        Target::Call => "Call"
+                    };
+                    let auto = format!("{}_{:03X}_{:04X}", prefix, bank, addr);
+                    match (&scope_name, self.symbols) {
+                        (Some(parent), Some(_)) => format!("{}.{}", parent, auto),
+                        _ => auto
+                    }
+                }
+            };
+            if !name.contains('.') {
+                scope = Some((bank, name.clone()));
+            }
+            self.names.insert((bank, addr), name);
+        }
+
+        let mut addrs: Vec<_> = ram_labels.into_iter().collect();
+        addrs.sort();
+        for (addr, names) in addrs {
+            // An address with labels in several banks can't be named.
+            if names.len() == 1 {
+                self.ram_names.insert(addr, names[0].clone());
+            }
+            for name in names {
+                self.constants.push((name, addr));
+            }
+        }
+    }
+
+    fn name(&self, bank: usize, switched: Option<usize>, addr: u16) -> Option<String> {
+        if addr >= 0x8000 {
+            return self.ram_names.get(&addr).cloned();
+        }
+        let bank = self.target_bank(bank, addr, switched)?;
+        self.names.get(&(bank, addr)).cloned()
+    }
+
+    fn write(&self) -> String {
+        let mut out = String::new();
+        for (name, addr) in &self.constants {
+            writeln!(out, "DEF {} EQU ${:04X}", name, addr).unwrap();
+        }
+        for bank in 0..self.banks() {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            if bank == 0 {
+                writeln!(out, "SECTION \"ROM Bank $000\", ROM0[$0000]\n").unwrap();
+            } else {
+                writeln!(out, "SECTION \"ROM Bank ${:03X}\", ROMX[$4000], BANK[${:X}]\n", bank, bank).unwrap();
+            }
+            let start = if bank == 0 { 0 } else { 0x4000 };
+            let mut addr = start;
+            while let Some(offset) = self.offset(bank, addr) {
+                if let Some(name) = self.names.get(&(bank, addr)) {
+                    writeln!(out, "{}:", name).unwrap();
+                }
+                match self.kinds[offset] {
+                    Kind::Code(len) => {
+                        let (instr, _) = self.decode(bank, addr).unwrap();
+                        let switched = self.switched_banks.get(&offset).copied();
+                        let text = format_opcode(instr.opcode, addr, &|target| self.name(bank, switched, target));
+                        writeln!(out, "    {}", text).unwrap();
+                        addr += len;
+                    }
+                    _ => addr += self.write_data(&mut out, bank, addr)
+                }
+            }
+        }
+        out
+    }
+
+    // Writes the data from `addr` up to the next label or code, returning
+    // its length.
+    fn write_data(&self, out: &mut String, bank: usize, addr: u16) -> u16 {
+        let mut len = 1;
+        while self.offset(bank, addr + len).is_some_and(|offset| self.kinds[offset] == Kind::Data)
+            && !self.names.contains_key(&(bank, addr + len)) {
+            len += 1;
+        }
+
+        let start = self.offset(bank, addr).unwrap();
+        let data = &self.rom[start..start + len as usize];
+        let run_at = |i: usize| data[i..].iter().take_while(|b| **b == data[i]).count();
+        let mut i = 0;
+        while i < data.len() {
+            let run = run_at(i);
+            if run >= MIN_FILL {
+                writeln!(out, "    ds {}, ${:02X}", run, data[i]).unwrap();
+                i += run;
+                continue;
+            }
+            let mut bytes = Vec::new();
+            while i < data.len() && bytes.len() < 16 && run_at(i) < MIN_FILL {
+                bytes.push(format!("${:02X}", data[i]));
+                i += 1;
+            }
+            writeln!(out, "    db {}", bytes.join(", ")).unwrap();
+        }
+        len
+    }
+}
+
+/// Disassembles a whole ROM image into RGBDS source, naming addresses with
+/// the labels in `symbols`.
+pub fn disassemble(rom: &[u8], symbols: Option<&Symbols>) -> String {
+    let mut disassembler = Disassembler::new(rom, symbols);
+    disassembler.trace_all();
+    disassembler.name_labels();
+    disassembler.write()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(bytes: &[u8], addr: u16) -> String {
+        format_opcode(Instr::disassemble(bytes).opcode, addr, &|_| None)
+    }
+
+    #[test]
+    fn test_format_opcode() {
+        assert_eq!(format(&[0xE0, 0x40, 0], 0), "ldh [$FF40], a");
+        assert_eq!(format(&[0xFA, 0x00, 0xC0], 0), "ld a, [$C000]");
+        assert_eq!(format(&[0x2A, 0, 0], 0), "ld a, [hl+]");
+        assert_eq!(format(&[0x32, 0, 0], 0), "ld [hl-], a");
+        assert_eq!(format(&[0xE2, 0, 0], 0), "ld [$FF00+c], a");
+        assert_eq!(format(&[0xF8, 0xFE, 0], 0), "ld hl, sp-2");
+        assert_eq!(format(&[0xE8, 0x05, 0], 0), "add sp, 5");
+        assert_eq!(format(&[0x08, 0x00, 0xD0], 0), "ld [$D000], sp");
+        assert_eq!(format(&[0x20, 0xFE, 0], 0x150), "jr nz, @+0");
+        assert_eq!(format(&[0xC4, 0x00, 0x40], 0), "call nz, $4000");
+        assert_eq!(format(&[0xD8, 0, 0], 0), "ret c");
+        assert_eq!(format(&[0xE9, 0, 0], 0), "jp hl");
+        assert_eq!(format(&[0xCB, 0x7E, 0], 0), "bit 7, [hl]");
+        assert_eq!(format(&[0xFF, 0, 0], 0), "rst $38");
+        assert_eq!(format(&[0xD3, 0, 0], 0), "db $D3");
+        assert_eq!(format_opcode(Instr::disassemble(&[0x18, 0x02, 0]).opcode, 0x200, &|addr| Some(format!("L{:04X}", addr))),
+                   "jr L0204");
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let mut rom = vec![0xFF; 0x8000];
+        // Entry: nop; jp Main
+        rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+        // Main: ld a, [wCounter]; call Sub; jr Main
+        rom[0x150..0x158].copy_from_slice(&[0xFA, 0x00, 0xC0, 0xCD, 0x00, 0x40, 0x18, 0xF8]);
+        // Bank 1: Sub: inc a; ret; then data.
+        rom[0x4000..0x4004].copy_from_slice(&[0x3C, 0xC9, 0x12, 0x34]);
+        let symbols = Symbols::parse("00:0150 Main\n00:C000 wCounter\n");
+        let asm = disassemble(&rom, Some(&symbols));
+
+        assert!(asm.starts_with("DEF wCounter EQU $C000\n"));
+        assert!(asm.contains("SECTION \"ROM Bank $000\", ROM0[$0000]\n"));
+        assert!(asm.contains("    nop\n    jp Main\n"));
+        assert!(asm.contains("Main:\n    ld a, [wCounter]\n    call Call_001_4000\n    jr Main\n"));
+        assert!(asm.contains("SECTION \"ROM Bank $001\", ROMX[$4000], BANK[$1]\n"));
+        assert!(asm.contains("    ds 76, $FF\nMain:"));
+        assert!(asm.contains("Call_001_4000:\n    inc a\n    ret\n    db $12, $34\n    ds 16380, $FF\n"));
+    }
+
+    #[test]
+    fn test_shared_addresses() {
+        let mut rom = vec![0xFF; 0x8000];
+        rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+        rom[0x150..0x152].copy_from_slice(&[0x18, 0xFE]);
+        // The output must not depend on the order symbols are stored in.
+        for _ in 0..10 {
+            let symbols = Symbols::parse("00:0150 Main\n00:0150 Alias\n00:C000 wB\n00:C000 wA\n");
+            let asm = disassemble(&rom, Some(&symbols));
+            assert!(asm.starts_with("DEF wA EQU $C000\nDEF wB EQU $C000\n"));
+            assert!(asm.contains("    jp Alias\n"));
+        }
+    }
+
+    #[test]
+    fn test_local_labels() {
+        let mut rom = vec![0x00; 0x8000];
+        // Main: jr .loop; .loop: jr .skip; Other: ret, never reached; .skip: jr @+1,
+        // which lands inside itself.
+        rom[0x100..0x102].copy_from_slice(&[0x18, 0x00]);
+        rom[0x102..0x104].copy_from_slice(&[0x18, 0x01]);
+        rom[0x104] = 0xC9;
+        rom[0x105..0x107].copy_from_slice(&[0x18, 0xFF]);
+        let symbols = Symbols::parse("00:0100 Main\n00:0102 Main.loop\n00:0104 Other\n00:0105 Main.skip\n");
+        let asm = disassemble(&rom, Some(&symbols));
+
+        assert!(asm.contains("Main:\n    jr Main.loop\nMain.loop:\n    jr Main_skip\nOther:\n    db $C9\nMain_skip:\n    jr @+1\n"));
+    }
+}
@@ -0,0 +1,98 @@
+use crate::gameboy::Color;
+
+/// The space a character takes up, including the gap after it.
+pub(crate) const GLYPH_WIDTH: usize = 4;
+pub(crate) const GLYPH_HEIGHT: usize = 6;
+
+/// The colors and border every debug view shares.
+pub(crate) const BACKGROUND: Color = Color { r: 2, g: 2, b: 3 };
+pub(crate) const TEXT: Color = Color { r: 26, g: 26, b: 26 };
+pub(crate) const MARGIN: usize = 2;
+
+// Each glyph is 3x5 pixels, one row per byte with the leftmost pixel in
+// bit 2. Characters without a glyph are drawn blank.
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        _ => [0; 5]
+    }
+}
+
+/// Draws `text` with its top left corner at (`x`, `y`) in a frame buffer
+/// `width` pixels wide. Pixels off the edge are clipped.
+pub(crate) fn draw_text(frame_buffer: &mut [Color], width: usize, x: usize, y: usize, text: &str, color: Color) {
+    for (i, c) in text.chars().enumerate() {
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..3 {
+                let (px, py) = (x + i * GLYPH_WIDTH + col, y + row);
+                if bits & (0b100 >> col) != 0 && px < width {
+                    if let Some(pixel) = frame_buffer.get_mut(py * width + px) {
+                        *pixel = color;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Fills a `w` by `h` rectangle, clipped to the frame buffer.
+pub(crate) fn fill_rect(frame_buffer: &mut [Color], width: usize, x: usize, y: usize, w: usize, h: usize, color: Color) {
+    for py in y..y + h {
+        for px in (x..x + w).filter(|px| *px < width) {
+            if let Some(pixel) = frame_buffer.get_mut(py * width + px) {
+                *pixel = color;
+            }
+        }
+    }
+}
+
+/// Draws the one pixel border of a `w` by `h` rectangle.
+pub(crate) fn outline_rect(frame_buffer: &mut [Color], width: usize, x: usize, y: usize, w: usize, h: usize, color: Color) {
+    fill_rect(frame_buffer, width, x, y, w, 1, color);
+    fill_rect(frame_buffer, width, x, y + h - 1, w, 1, color);
+    fill_rect(frame_buffer, width, x, y, 1, h, color);
+    fill_rect(frame_buffer, width, x + w - 1, y, 1, h, color);
+}
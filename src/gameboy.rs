@@ -1,19 +1,29 @@
-use crate::memory::memory_bus::MemoryBus;
+use std::str::FromStr;
+use crate::memory::memory_bus::{MemoryAccess, MemoryBus};
 use crate::memory::memory_map::{MemoryMap, MappedArea, MemoryMappedDeviceManager, MemoryMappedDeviceId};
 use crate::ram_device::RamDevice;
-use crate::interrupt_controller::{Interrupt, InterruptController};
+use crate::interrupt_controller::InterruptController;
 use crate::joypad_controller::{JoypadController};
 use crate::timer_controller::TimerController;
-use crate::lcd::LcdController;
+use crate::lcd::{LcdController, TilePalette};
 use crate::sound::SoundController;
 use crate::serial::SerialController;
-use crate::cartridge::Cartridge;
-use crate::cpu::Cpu;
+use crate::cartridge::{Cartridge, Symbols};
+use crate::cpu::{BadReturn, Cpu, FrameKind, Instr, Registers, StackFrame};
+use crate::disasm;
+use crate::trace::Tracer;
+use crate::profiler::Profiler;
+use crate::cheats::Cheat;
+use crate::cdl::CodeDataLog;
+use crate::save_state::{SaveState, SaveStateError, StateReader, StateWriter};
 
+/// Width of the LCD in pixels.
 pub const GAME_WIDTH: usize = 160;
+/// Height of the LCD in pixels.
 pub const GAME_HEIGHT: usize = 144;
 
-#[derive(Copy, Clone, Debug)]
+/// An RGB555 color as produced by the LCD. Each channel is in `0..=31`.
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -24,8 +34,16 @@ impl Color {
     pub fn new(r: u8, g: u8, b: u8) -> Self {
         Color { r, g, b }
     }
+
+    /// Scales each channel up to the full 8 bit range.
+    pub fn to_rgb24(&self) -> [u8; 3] {
+        let scale = |c: u8| (c << 3) | (c >> 2);
+        [scale(self.r), scale(self.g), scale(self.b)]
+    }
 }
 
+/// A button on the joypad. [`Gameboy::tick`] takes the set of buttons held
+/// down for the frame.
 #[derive(Debug, Clone, PartialEq)]
 pub enum JoypadInput {
     Up,
@@ -38,6 +56,25 @@ pub enum JoypadInput {
     B
 }
 
+impl FromStr for JoypadInput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "up" => Ok(JoypadInput::Up),
+            "down" => Ok(JoypadInput::Down),
+            "left" => Ok(JoypadInput::Left),
+            "right" => Ok(JoypadInput::Right),
+            "start" => Ok(JoypadInput::Start),
+            "select" => Ok(JoypadInput::Select),
+            "a" => Ok(JoypadInput::A),
+            "b" => Ok(JoypadInput::B),
+            _ => Err(format!("Unknown button: {}", s))
+        }
+    }
+}
+
+/// The hardware model to emulate.
 #[derive(Clone, Copy)]
 pub enum Mode {
     DMG,
@@ -45,7 +82,8 @@ pub enum Mode {
 }
 
 impl Mode {
-    fn is_cgb(&self) -> bool {
+    /// Whether this is the Game Boy Color.
+    pub fn is_cgb(&self) -> bool {
         match self {
             Mode::DMG => false,
             Mode::CGB => true
@@ -53,14 +91,23 @@ impl Mode {
     }
 }
 
+/// A complete machine: CPU, memory map and every memory mapped device.
 pub struct Gameboy {
     cpu: Cpu,
     memory_map: MemoryMap,
     device_manager: MemoryMappedDeviceManager,
-    mode: Mode
+    mode: Mode,
+    clocks: u64,
+    access_log: Option<Vec<MemoryAccess>>,
+    profiler: Option<Profiler>,
+    cheats: Vec<Cheat>,
+    cheats_enabled: bool,
+    cdl: Option<CodeDataLog>
 }
 
 impl Gameboy {
+    /// Builds a powered off machine. `debug` makes the CPU print every
+    /// instruction it executes.
     pub fn new(debug: bool, mode: Mode) -> Self {
         let mut cpu = Cpu::new(mode);
         if debug { cpu.enable_debug(); }
@@ -89,20 +136,29 @@ impl Gameboy {
             cpu,
             memory_map: MemoryMap::new(),
             device_manager,
-            mode
+            mode,
+            clocks: 0,
+            access_log: None,
+            profiler: None,
+            cheats: Vec::new(),
+            cheats_enabled: true,
+            cdl: None
         }
     }
 
+    /// Inserts the cartridge and maps the devices. With `skip_boot_rom` the
+    /// CPU starts at 0x100 instead of running the boot ROM.
     pub fn boot(&mut self, mut cartridge: Cartridge, skip_boot_rom: bool) {
         debug!("Booting: {:?}", cartridge);
         if skip_boot_rom {
-            self.cpu.skip_boot_rom();
+            self.cpu.skip_boot_rom(self.mode);
             cartridge.clear_boot_rom();
         }
 
         self.map_devices(cartridge);
     }
 
+    /// Returns the cartridge RAM, suitable for writing out as a `.sav` file.
     pub fn dump_ram(&mut self) -> Vec<u8> {
         self.device_manager.cartridge().dump_ram()
     }
@@ -129,37 +185,605 @@ impl Gameboy {
         self.memory_map.register(MemoryMappedDeviceId::HRAM, &[MappedArea(0xFF80, 0xFFFF - 0xFF80)]);
     }
 
-    pub fn tick(&mut self, pressed_inputs: &[JoypadInput], mut frame_buffer: &mut [Color], mut audio_queue: &mut Vec<f32>) {
-        let mut mb = MemoryBus::new(&self.memory_map, &mut self.device_manager);
-        let mut interrupts = Vec::with_capacity(10);
+    /// Runs the machine until the next VBlank, drawing scanlines into
+    /// `frame_buffer` and pushing interleaved stereo samples at
+    /// [`CLOCK_FREQ`](crate::clocks::CLOCK_FREQ) into `audio_queue`.
+    pub fn tick(&mut self, pressed_inputs: &[JoypadInput], frame_buffer: &mut [Color], audio_queue: &mut Vec<f32>) {
+        self.set_pressed(pressed_inputs);
+        while !self.step(frame_buffer, audio_queue) {}
+    }
 
-        mb.devices().joypad_controller().set_pressed(pressed_inputs);
-        loop {
-            let clocks = self.cpu.step(&mut mb);
+    /// Sets the buttons that are held down until the next call.
+    pub fn set_pressed(&mut self, pressed_inputs: &[JoypadInput]) {
+        self.device_manager.joypad_controller().set_pressed(pressed_inputs);
+    }
 
-            let mut fire_interrupt = |interrupt| interrupts.push(interrupt);
-            mb.devices().timer().tick(clocks, &mut fire_interrupt);
-            if let Some(dma_executor) = mb.devices().lcd_controller().tick(clocks, &mut frame_buffer, &mut fire_interrupt) {
-                dma_executor.execute(&mut mb);
+    /// Executes a single instruction, advancing the devices along with each
+    /// of its M-cycles so they see its reads and writes when they happen.
+    /// Returns true when the LCD entered VBlank.
+    pub fn step(&mut self, frame_buffer: &mut [Color], audio_queue: &mut Vec<f32>) -> bool {
+        let mut mb = match self.access_log.as_mut() {
+            Some(access_log) => {
+                access_log.clear();
+                MemoryBus::with_access_log(&self.memory_map, &mut self.device_manager, access_log)
             }
-            mb.devices().serial_controller().tick(clocks, &mut fire_interrupt);
-            mb.devices().sound_controller().tick(clocks, &mut audio_queue);
-
-            for interrupt in &interrupts {
-                mb.devices().interrupt_controller().request(*interrupt);
-
-                match *interrupt {
-                    Interrupt::VBlank => {
-                        return
-                    }
-                    _ => {}
-                }
+            None => MemoryBus::new(&self.memory_map, &mut self.device_manager)
+        };
+        if let Some(cdl) = self.cdl.as_mut() {
+            mb.set_cdl(cdl);
+        }
+        mb.set_clock(frame_buffer, audio_queue);
+
+        if let Some(profiler) = self.profiler.as_mut() {
+            let pc = self.cpu.registers().pc();
+            profiler.start((mb.devices().bank(pc), pc), self.cpu.call_stack().frames());
+        }
+
+        let clocks = self.cpu.step(&mut mb);
+        self.clocks += clocks as u64;
+
+        // The cycles of an interrupt belong to its handler.
+        if let (Some(profiler), Some(vector)) = (self.profiler.as_mut(), self.cpu.dispatched_interrupt()) {
+            let frames = self.cpu.call_stack().frames();
+            let depth = frames.iter().rposition(|frame| frame.kind == FrameKind::Interrupt).map_or(0, |i| i + 1);
+            profiler.start((0, vector), &frames[..depth]);
+        }
+
+        let vblank = mb.vblank();
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.finish(clocks, vblank);
+        }
+        if vblank {
+            self.apply_ram_cheats();
+        }
+        vblank
+    }
+
+    /// Replaces the cheat codes. Game Genie codes take effect straight away
+    /// and GameShark codes are written at every VBlank.
+    pub fn set_cheats(&mut self, cheats: Vec<Cheat>) {
+        self.cheats = cheats;
+        self.update_rom_patches();
+    }
+
+    pub fn add_cheat(&mut self, cheat: Cheat) {
+        self.cheats.push(cheat);
+        self.update_rom_patches();
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    /// Turns the cheat at `index` on or off. Returns false, changing
+    /// nothing, when there is no such cheat.
+    pub fn set_cheat_enabled(&mut self, index: usize, enabled: bool) -> bool {
+        match self.cheats.get_mut(index) {
+            Some(cheat) => cheat.enabled = enabled,
+            None => return false
+        }
+        self.update_rom_patches();
+        true
+    }
+
+    /// Turns all cheats on or off, keeping which ones are enabled.
+    pub fn set_cheats_enabled(&mut self, enabled: bool) {
+        self.cheats_enabled = enabled;
+        self.update_rom_patches();
+    }
+
+    pub fn cheats_enabled(&self) -> bool {
+        self.cheats_enabled
+    }
+
+    fn active_cheats(&self) -> impl Iterator<Item = &Cheat> {
+        self.cheats.iter().filter(move |cheat| self.cheats_enabled && cheat.enabled)
+    }
+
+    fn update_rom_patches(&mut self) {
+        let patches = self.active_cheats().filter_map(Cheat::rom_patch).collect();
+        self.device_manager.cartridge().set_rom_patches(patches);
+    }
+
+    fn apply_ram_cheats(&mut self) {
+        let writes: Vec<_> = self.active_cheats().filter_map(Cheat::ram_write).collect();
+        let cgb = self.mode.is_cgb();
+        for (bank, addr, value) in writes {
+            match bank {
+                // A DMG has a single switchable WRAM bank, so the bank is
+                // ignored there.
+                Some(_) if !cgb && matches!(addr, 0xD000 ..= 0xDFFF) => self.write8(addr, value),
+                Some(bank) => self.write_banked(bank, addr, value),
+                None => self.write8(addr, value)
             }
-            interrupts.clear();
         }
     }
 
-    pub fn fill_tile_framebuffer(&mut self, frame_buffer: &mut [Color]) {
-        self.device_manager.lcd_controller().fill_tile_framebuffer(frame_buffer);
+    /// Serializes the whole machine: CPU, every mapped device and the
+    /// cartridge's bank registers and RAM. The ROM itself is not included.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.write_bool(self.mode.is_cgb());
+        self.cpu.save_state(&mut w);
+        self.device_manager.save_state(&mut w);
+        w.write_u64(self.clocks);
+        w.into_inner()
+    }
+
+    /// Restores a state made by [`save_state`](Gameboy::save_state). The
+    /// machine must already be booted with the same cartridge and mode, and
+    /// is left unchanged when the state can't be loaded.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let snapshot = self.save_state();
+        let result = self.read_state(data);
+        if result.is_err() {
+            self.read_state(&snapshot).expect("restoring the machine's own state");
+        }
+        result
+    }
+
+    fn read_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let mut r = StateReader::new(data)?;
+        if r.read_bool()? != self.mode.is_cgb() {
+            return Err(SaveStateError::ModeMismatch);
+        }
+        self.cpu.load_state(&mut r)?;
+        self.device_manager.load_state(&mut r)?;
+        self.clocks = r.read_u64()?;
+        if !r.is_empty() {
+            return Err(SaveStateError::Invalid("trailing data"));
+        }
+        Ok(())
+    }
+
+    /// Starts or stops capturing the bytes the game sends over the link port,
+    /// which is where test ROMs print their results.
+    pub fn set_serial_capture(&mut self, capture: bool) {
+        self.device_manager.serial_controller().set_capture(capture);
+    }
+
+    /// Returns the bytes sent over the link port since the last call.
+    pub fn take_serial_output(&mut self) -> Vec<u8> {
+        self.device_manager.serial_controller().take_captured()
+    }
+
+    /// Makes `LD B,B` act as a breakpoint, see [`take_ld_b_b_hit`](Gameboy::take_ld_b_b_hit).
+    pub fn set_ld_b_b_breakpoint(&mut self, enabled: bool) {
+        self.cpu.set_ld_b_b_breakpoint(enabled);
+    }
+
+    /// Whether an `LD B,B` ran since the last call. Mooneye and acid2 test
+    /// ROMs execute one when they are done.
+    pub fn take_ld_b_b_hit(&mut self) -> bool {
+        self.cpu.take_ld_b_b_hit()
+    }
+
+    /// Starts or stops logging every instruction, see [`Tracer`].
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.cpu.set_tracer(tracer);
+    }
+
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.cpu.tracer()
+    }
+
+    /// The routines entered by CALL, RST or an interrupt and not yet
+    /// returned from, outermost first.
+    pub fn call_stack(&self) -> &[StackFrame] {
+        self.cpu.call_stack().frames()
+    }
+
+    /// The call stack with labels from the symbol file, one line per frame,
+    /// innermost first.
+    pub fn backtrace(&mut self) -> String {
+        let pc = self.cpu.registers().pc();
+        let bank = self.device_manager.bank(pc);
+        self.cpu.call_stack().backtrace((bank, pc), self.memory_map.symbols())
+    }
+
+    /// The RET or RETI made by the last step, if it didn't return to where
+    /// the innermost routine was entered from.
+    pub fn bad_return(&self) -> Option<&BadReturn> {
+        self.cpu.bad_return()
+    }
+
+    /// The cartridge's symbol file, if it has one.
+    pub fn symbols(&self) -> Option<&Symbols> {
+        self.memory_map.symbols()
+    }
+
+    /// Starts or stops counting the cycles spent in each routine, see
+    /// [`Profiler`].
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) {
+        self.profiler = profiler;
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Stops profiling, returning the profiler with its counts.
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    /// Starts or stops logging how each byte of memory is used, see
+    /// [`CodeDataLog`].
+    pub fn set_cdl(&mut self, cdl: Option<CodeDataLog>) {
+        self.cdl = cdl;
+    }
+
+    pub fn cdl(&self) -> Option<&CodeDataLog> {
+        self.cdl.as_ref()
+    }
+
+    /// Stops logging, returning the log.
+    pub fn take_cdl(&mut self) -> Option<CodeDataLog> {
+        self.cdl.take()
+    }
+
+    // The sizes of the ROM, WRAM and cartridge RAM.
+    pub(crate) fn memory_sizes(&mut self) -> (usize, usize, usize) {
+        let wram = self.device_manager.ram_bank0().memory().len() + self.device_manager.ram_bank1().memory().len();
+        let cartridge = self.device_manager.cartridge();
+        (cartridge.rom_len(), wram, cartridge.ram().len())
+    }
+
+    /// Starts or stops recording the memory accesses made by each
+    /// [`step`](Gameboy::step).
+    pub fn set_access_logging(&mut self, enabled: bool) {
+        self.access_log = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    /// The reads and writes made by the last step, oldest first. Empty
+    /// unless access logging is on.
+    pub fn accesses(&self) -> &[MemoryAccess] {
+        self.access_log.as_deref().unwrap_or(&[])
+    }
+
+    /// Reads a byte through the memory bus, as the CPU would.
+    pub fn read8(&mut self, addr: u16) -> u8 {
+        MemoryBus::new(&self.memory_map, &mut self.device_manager).get8(addr)
+    }
+
+    /// Writes a byte through the memory bus, as the CPU would.
+    pub fn write8(&mut self, addr: u16, byte: u8) {
+        MemoryBus::new(&self.memory_map, &mut self.device_manager).set8(addr, byte)
+    }
+
+    /// Reads `addr` in `bank` of WRAM, cartridge RAM or HRAM, whether or not
+    /// that bank is mapped.
+    pub fn read_banked(&mut self, bank: usize, addr: u16) -> Option<u8> {
+        self.device_manager.banked_ram(bank, addr).copied()
+    }
+
+    /// Writes `addr` in `bank` of WRAM, cartridge RAM or HRAM, whether or
+    /// not that bank is mapped. Other addresses are ignored.
+    pub fn write_banked(&mut self, bank: usize, addr: u16, byte: u8) {
+        if let Some(ram) = self.device_manager.banked_ram(bank, addr) {
+            *ram = byte;
+        }
+    }
+
+    /// Reads a byte of the ROM file, without any Game Genie patches.
+    pub fn read_rom(&mut self, offset: usize) -> Option<u8> {
+        self.device_manager.cartridge().rom().get(offset).copied()
+    }
+
+    /// Changes a byte of the ROM file. Offsets past its end are ignored.
+    pub fn write_rom(&mut self, offset: usize, byte: u8) {
+        let cartridge = self.device_manager.cartridge();
+        if offset < cartridge.rom_len() {
+            cartridge.patch_rom(offset, byte);
+        }
+    }
+
+    /// Reads `addr` in VRAM `bank`, whether or not that bank is mapped.
+    pub fn read_vram(&mut self, bank: usize, addr: u16) -> Option<u8> {
+        self.vram_banked(bank, addr).copied()
+    }
+
+    /// Writes `addr` in VRAM `bank`, whether or not that bank is mapped.
+    /// Other addresses are ignored.
+    pub fn write_vram(&mut self, bank: usize, addr: u16, byte: u8) {
+        if let Some(vram) = self.vram_banked(bank, addr) {
+            *vram = byte;
+        }
+    }
+
+    fn vram_banked(&mut self, bank: usize, addr: u16) -> Option<&mut u8> {
+        let banks = if self.mode.is_cgb() { 2 } else { 1 };
+        match addr {
+            0x8000 ..= 0x9FFF if bank < banks => Some(&mut self.device_manager.lcd_controller().vram_bank(bank)[addr as usize - 0x8000]),
+            _ => None
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub(crate) fn ram_banks(&mut self) -> Vec<(usize, u16, Vec<u8>)> {
+        self.device_manager.ram_banks()
+    }
+
+    /// Decodes the instruction at `addr`, returning its text and length.
+    pub fn disassemble(&mut self, addr: u16) -> (String, u16) {
+        let bytes = MemoryBus::new(&self.memory_map, &mut self.device_manager).get_arr3(addr);
+        let instr = Instr::disassemble(&bytes);
+        let text = disasm::format_opcode(instr.opcode, addr, &|target| Some(format!("${:04X}", target)));
+        (text, instr.n_bytes)
+    }
+
+    /// The ROM, SRAM or WRAM bank mapped at `addr`, numbered as in symbol
+    /// files. Unbanked areas are bank 0.
+    pub fn bank(&mut self, addr: u16) -> usize {
+        self.device_manager.bank(addr)
+    }
+
+    pub fn registers(&self) -> &Registers {
+        self.cpu.registers()
+    }
+
+    pub fn registers_mut(&mut self) -> &mut Registers {
+        self.cpu.registers_mut()
+    }
+
+    /// The number of M-cycles run since power on.
+    pub fn clocks(&self) -> u64 {
+        self.clocks
+    }
+
+    /// The color of `value` in `palette`.
+    pub fn palette_color(&mut self, palette: TilePalette, value: u8) -> Color {
+        self.device_manager.lcd_controller().palette_color(palette, value)
+    }
+
+    /// The RGB555 value of `value` in a CGB background or object palette,
+    /// or `None` for BGP, OBP0 and OBP1.
+    pub fn cgb_color(&mut self, palette: TilePalette, value: u8) -> Option<u16> {
+        self.device_manager.lcd_controller().cgb_color(palette, value)
+    }
+
+    /// Changes `value` in a CGB background or object palette to an RGB555
+    /// value. It shows from the next line drawn. Returns false for BGP,
+    /// OBP0 and OBP1, which hold shades rather than colors.
+    pub fn set_cgb_color(&mut self, palette: TilePalette, value: u8, raw: u16) -> bool {
+        self.device_manager.lcd_controller().set_cgb_color(palette, value, raw)
+    }
+
+    /// Draws the 384 tiles of VRAM `bank` into a 128x192 area of a frame
+    /// buffer `stride` pixels wide.
+    pub fn draw_tiles(&mut self, bank: usize, palette: TilePalette, frame_buffer: &mut [Color], stride: usize) {
+        self.device_manager.lcd_controller().draw_tiles(bank, palette, frame_buffer, stride);
+    }
+
+    /// The 40 sprites' 4 byte entries in OAM.
+    pub fn oam(&mut self) -> &[u8] {
+        self.device_manager.lcd_controller().oam()
+    }
+
+    /// The height of sprites, 8 or 16.
+    pub fn sprite_height(&mut self) -> usize {
+        self.device_manager.lcd_controller().sprite_height()
+    }
+
+    /// The sprites on line `ly` that aren't drawn because ten sprites
+    /// earlier in OAM already are.
+    pub fn dropped_sprites(&mut self, ly: u8) -> Vec<usize> {
+        self.device_manager.lcd_controller().dropped_sprites(ly)
+    }
+
+    /// Draws sprite `index` into an 8 pixel wide area of a frame buffer
+    /// `stride` pixels wide, leaving transparent pixels as they are.
+    pub fn draw_sprite(&mut self, index: usize, frame_buffer: &mut [Color], stride: usize) {
+        self.device_manager.lcd_controller().draw_sprite(index, frame_buffer, stride);
+    }
+
+    /// Draws the tile map at 0x9C00 if `high` or else 0x9800 into a 256x256
+    /// area of a frame buffer `stride` pixels wide.
+    pub fn draw_map(&mut self, high: bool, frame_buffer: &mut [Color], stride: usize) {
+        self.device_manager.lcd_controller().draw_map(high, frame_buffer, stride);
+    }
+}
+
+/// A machine past the boot ROM with `program` at the entry point, for
+/// tests. `name` keeps the ROM files of tests running at once apart.
+#[cfg(test)]
+pub(crate) fn booted_gameboy(name: &str, program: &[u8], mode: Mode) -> Gameboy {
+    let mut gameboy = Gameboy::new(false, mode);
+    gameboy.boot(crate::cartridge::test_cartridge(name, program), true);
+    gameboy
+}
+
+/// A CGB running a ROM that only loops, for tests that set up memory
+/// themselves.
+#[cfg(test)]
+pub(crate) fn idle_gameboy(name: &str) -> Gameboy {
+    // JR -2
+    booted_gameboy(name, &[0x18, 0xFE], Mode::CGB)
+}
+
+/// A program that calls a subroutine from a loop, for tests:
+///
+/// ```text
+/// main: INC A; CALL sub; LD ($C000), A; JR main
+/// sub:  INC B; RET
+/// ```
+#[cfg(test)]
+pub(crate) const CALL_PROGRAM: [u8; 11] = [0x3C, 0xCD, 0x09, 0x01, 0xEA, 0x00, 0xC0, 0x18, 0xF7, 0x04, 0xC9];
+
+/// A frame buffer for tests that step the machine.
+#[cfg(test)]
+pub(crate) fn test_frame_buffer() -> Vec<Color> {
+    vec![Color::new(0, 0, 0); GAME_WIDTH * GAME_HEIGHT]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::test_cartridge;
+    use crate::cpu::Register;
+
+    // INC A; LD (0xC000), A; JR -6
+    const COUNTER: [u8; 6] = [0x3C, 0xEA, 0x00, 0xC0, 0x18, 0xFA];
+
+    fn run(gameboy: &mut Gameboy, frames: usize) -> Vec<u8> {
+        let mut frame_buffer = test_frame_buffer();
+        let mut audio = Vec::new();
+        for _ in 0..frames {
+            gameboy.tick(&[JoypadInput::A], &mut frame_buffer, &mut audio);
+        }
+        gameboy.save_state()
+    }
+
+    #[test]
+    fn test_save_state_round_trip() {
+        for mode in &[Mode::DMG, Mode::CGB] {
+            let mut gameboy = Gameboy::new(false, *mode);
+            gameboy.boot(test_cartridge("round-trip", &COUNTER), false);
+            let state = run(&mut gameboy, 3);
+            let expected = run(&mut gameboy, 2);
+
+            gameboy.load_state(&state).unwrap();
+            assert_eq!(gameboy.save_state(), state);
+            assert_eq!(run(&mut gameboy, 2), expected);
+        }
+    }
+
+    #[test]
+    fn test_timer_read_mid_instruction() {
+        // TIMA counts every 4 M-cycles once TAC starts it. POP reads it on
+        // its second cycle, 3 after the write to TAC, so before it counts,
+        // and LDH on its third, 7 after, so after.
+        let program = [
+            0x31, 0x05, 0xFF, // LD SP,$FF05
+            0x3E, 0x05,       // LD A,$05
+            0xE0, 0x07,       // LDH ($07),A
+            0x00,             // NOP
+            0xC1,             // POP BC
+            0xF0, 0x05,       // LDH A,($05)
+            0x18, 0xFE        // JR -2
+        ];
+        let mut gameboy = booted_gameboy("timer-timing", &program, Mode::DMG);
+        let mut frame_buffer = test_frame_buffer();
+        for _ in 0..6 {
+            gameboy.step(&mut frame_buffer, &mut Vec::new());
+        }
+        assert_eq!(gameboy.registers().get8(Register::C), 0);
+        assert_eq!(gameboy.registers().get8(Register::A), 1);
+    }
+
+    #[test]
+    fn test_oam_dma() {
+        // Copies 0xC000-0xC09F into OAM from a routine in HRAM, reading
+        // 0xC000 both while the copy runs and once it's done.
+        let routine = [
+            0x3E, 0xC0,       // LD A,$C0
+            0xE0, 0x46,       // LDH ($46),A
+            0xFA, 0x00, 0xC0, // LD A,($C000)
+            0x47,             // LD B,A
+            0x3E, 0x28,       // LD A,$28
+            0x3D,             // DEC A
+            0x20, 0xFD,       // JR NZ,-3
+            0xFA, 0x00, 0xC0, // LD A,($C000)
+            0x18, 0xFE        // JR -2
+        ];
+        // JP $FF80
+        let mut gameboy = booted_gameboy("oam-dma", &[0xC3, 0x80, 0xFF], Mode::DMG);
+        for (i, &byte) in routine.iter().enumerate() {
+            gameboy.write8(0xFF80 + i as u16, byte);
+        }
+        for i in 0..0xA0 {
+            gameboy.write8(0xC000 + i, i as u8 ^ 0x5A);
+        }
+        let mut frame_buffer = test_frame_buffer();
+        for _ in 0..4 {
+            gameboy.step(&mut frame_buffer, &mut Vec::new());
+        }
+        assert_eq!(gameboy.read8(0xFE03), 0x03 ^ 0x5A);
+        assert_eq!(gameboy.read8(0xFE04), 0);
+
+        for _ in 0..100 {
+            gameboy.step(&mut frame_buffer, &mut Vec::new());
+        }
+        assert_eq!(gameboy.registers().get8(Register::B), 0xFF);
+        assert_eq!(gameboy.registers().get8(Register::A), 0x5A);
+        for i in 0..0xA0 {
+            assert_eq!(gameboy.read8(0xFE00 + i), i as u8 ^ 0x5A);
+        }
+    }
+
+    #[test]
+    fn test_load_state_mode_mismatch() {
+        let dmg = booted_gameboy("mismatch", &COUNTER, Mode::DMG);
+        let mut cgb = booted_gameboy("mismatch", &COUNTER, Mode::CGB);
+
+        assert_eq!(cgb.load_state(&dmg.save_state()), Err(SaveStateError::ModeMismatch));
+        assert_eq!(cgb.load_state(&[]), Err(SaveStateError::UnexpectedEof));
+
+        let mut other = test_cartridge("mismatch", &COUNTER);
+        other.patch_rom(0x14D, 0x42);
+        let mut dmg2 = Gameboy::new(false, Mode::DMG);
+        dmg2.boot(other, true);
+        assert_eq!(dmg2.load_state(&dmg.save_state()), Err(SaveStateError::RomMismatch));
+    }
+
+    #[test]
+    fn test_load_truncated_state() {
+        let mut gameboy = booted_gameboy("truncated", &COUNTER, Mode::DMG);
+        let state = run(&mut gameboy, 1);
+        let current = run(&mut gameboy, 1);
+        // Cut off inside the device state, after the CPU registers.
+        let truncated = &state[..state.len() / 2];
+        assert_eq!(gameboy.load_state(truncated), Err(SaveStateError::UnexpectedEof));
+        assert_eq!(gameboy.save_state(), current);
+    }
+
+    #[test]
+    fn test_serial_output() {
+        let program = [
+            0x3E, b'O', 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, // SB = 'O', start transfer
+            0x3E, 0x01, 0xE0, 0x02,                         // clear the transfer flag
+            0x3E, b'K', 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, // SB = 'K', start transfer
+            0x18, 0xFE
+        ];
+        let mut gameboy = booted_gameboy("serial", &program, Mode::DMG);
+        run(&mut gameboy, 1);
+        assert_eq!(gameboy.take_serial_output(), b"");
+
+        let mut gameboy = booted_gameboy("serial", &program, Mode::DMG);
+        gameboy.set_serial_capture(true);
+        run(&mut gameboy, 1);
+        assert_eq!(gameboy.take_serial_output(), b"OK");
+        assert_eq!(gameboy.take_serial_output(), b"");
+    }
+
+    #[test]
+    fn test_cheats() {
+        let mut gameboy = booted_gameboy("cheats", &COUNTER, Mode::DMG);
+        // $42 at $0100 when the ROM has $3C there, and $99 into $C000.
+        let cheats = Cheat::parse_file("421-00F-1EA\n421-00F-1E2\n!019900C0\n").unwrap();
+        gameboy.set_cheats(cheats);
+        assert_eq!(gameboy.read8(0x100), 0x42);
+        assert!(gameboy.set_cheat_enabled(0, false));
+        assert_eq!(gameboy.read8(0x100), 0x3C);
+        assert!(!gameboy.set_cheat_enabled(3, true));
+
+        run(&mut gameboy, 1);
+        assert_ne!(gameboy.read8(0xC000), 0x99);
+        gameboy.set_cheat_enabled(2, true);
+        run(&mut gameboy, 1);
+        assert_eq!(gameboy.read8(0xC000), 0x99);
+
+        gameboy.set_cheat_enabled(0, true);
+        gameboy.set_cheats_enabled(false);
+        assert_eq!(gameboy.read8(0x100), 0x3C);
+
+        // A DMG has a single WRAM bank, so banked codes write it directly.
+        gameboy.set_cheats(Cheat::parse_file("9355C0D0\n").unwrap());
+        gameboy.set_cheats_enabled(true);
+        run(&mut gameboy, 1);
+        assert_eq!(gameboy.read8(0xD0C0), 0x55);
     }
 }
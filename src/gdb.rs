@@ -0,0 +1,468 @@
+//! A GDB remote serial protocol server, so a remote debugger client can
+//! inspect and control the CPU over TCP.
+//!
+//! Registers are sent in the order A, F, B, C, D, E, H, L, SP, PC, the 16 bit
+//! ones little endian. Memory is read and written through the memory bus, so
+//! the debugger sees exactly what the CPU would.
+
+use std::collections::BTreeSet;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use crate::cpu::Register::{self, *};
+use crate::gameboy::{Color, Gameboy, JoypadInput};
+
+const REGISTERS: [Register; 10] = [A, F, B, C, D, E, H, L, SP, PC];
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.gnu.gdb.sm83.core">
+    <reg name="a" bitsize="8" regnum="0"/>
+    <reg name="f" bitsize="8"/>
+    <reg name="b" bitsize="8"/>
+    <reg name="c" bitsize="8"/>
+    <reg name="d" bitsize="8"/>
+    <reg name="e" bitsize="8"/>
+    <reg name="h" bitsize="8"/>
+    <reg name="l" bitsize="8"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+#[derive(Debug, PartialEq)]
+enum State {
+    Stopped,
+    Running,
+    Detached
+}
+
+pub struct GdbStub {
+    stream: TcpStream,
+    input: Vec<u8>,
+    breakpoints: BTreeSet<u16>,
+    state: State
+}
+
+impl GdbStub {
+    /// Waits for a debugger to connect on `addr`. The machine stays stopped
+    /// until the debugger continues it.
+    pub fn listen<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let (stream, _) = listener.accept()?;
+        Self::new(stream)
+    }
+
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        stream.set_nonblocking(true)?;
+        Ok(GdbStub {
+            stream,
+            input: Vec::new(),
+            breakpoints: BTreeSet::new(),
+            state: State::Stopped
+        })
+    }
+
+    /// Whether the debugger is still connected.
+    pub fn is_attached(&self) -> bool {
+        self.state != State::Detached
+    }
+
+    /// Whether the debugger has stopped the machine.
+    pub fn is_stopped(&self) -> bool {
+        self.state == State::Stopped
+    }
+
+    /// Handles the packets the debugger sent, then runs the machine until
+    /// the next VBlank or breakpoint unless it is stopped. Returns true when
+    /// a whole frame was run. Once the debugger detaches this just calls
+    /// [`Gameboy::tick`].
+    pub fn tick(&mut self, gameboy: &mut Gameboy, pressed_inputs: &[JoypadInput],
+                frame_buffer: &mut [Color], audio_queue: &mut Vec<f32>) -> io::Result<bool> {
+        if self.state != State::Detached {
+            self.process_input(gameboy, frame_buffer, audio_queue)?;
+        }
+
+        match self.state {
+            State::Stopped => Ok(false),
+            State::Detached => {
+                gameboy.tick(pressed_inputs, frame_buffer, audio_queue);
+                Ok(true)
+            }
+            State::Running => {
+                gameboy.set_pressed(pressed_inputs);
+                loop {
+                    let vblank = gameboy.step(frame_buffer, audio_queue);
+                    if self.breakpoints.contains(&gameboy.registers().pc()) {
+                        self.stop(SIGTRAP)?;
+                        return Ok(vblank);
+                    }
+                    if vblank {
+                        return Ok(true);
+                    }
+                }
+            }
+        }
+    }
+
+    fn stop(&mut self, signal: u8) -> io::Result<()> {
+        self.state = State::Stopped;
+        self.send(&format!("S{:02x}", signal))
+    }
+
+    fn process_input(&mut self, gameboy: &mut Gameboy, frame_buffer: &mut [Color],
+                     audio_queue: &mut Vec<f32>) -> io::Result<()> {
+        let mut buf = [0; 4096];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => {
+                    self.state = State::Detached;
+                    return Ok(());
+                }
+                Ok(n) => self.input.extend_from_slice(&buf[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e)
+            }
+        }
+
+        while !self.input.is_empty() && self.state != State::Detached {
+            match self.input[0] {
+                b'$' => {
+                    let end = match self.input.iter().position(|b| *b == b'#') {
+                        Some(end) if end + 2 < self.input.len() => end,
+                        _ => break
+                    };
+                    let packet: Vec<u8> = self.input.drain(..end + 3).collect();
+                    let data = &packet[1..end];
+                    let checksum = std::str::from_utf8(&packet[end + 1..])
+                        .ok()
+                        .and_then(|cs| u8::from_str_radix(cs, 16).ok());
+                    if checksum == Some(checksum_of(data)) {
+                        self.write(b"+")?;
+                        let data = String::from_utf8_lossy(data).into_owned();
+                        self.handle_packet(&data, gameboy, frame_buffer, audio_queue)?;
+                    } else {
+                        self.write(b"-")?;
+                    }
+                }
+                0x03 => {
+                    self.input.remove(0);
+                    if self.state == State::Running {
+                        self.stop(SIGINT)?;
+                    }
+                }
+                _ => {
+                    // Acks and noise between packets.
+                    self.input.remove(0);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_packet(&mut self, packet: &str, gameboy: &mut Gameboy, frame_buffer: &mut [Color],
+                     audio_queue: &mut Vec<f32>) -> io::Result<()> {
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        match command {
+            "?" => self.send(&format!("S{:02x}", SIGTRAP)),
+            "g" => {
+                let data: String = (0..REGISTERS.len()).map(|n| read_register(gameboy, n)).collect();
+                self.send(&data)
+            }
+            "G" => {
+                let mut rest = args;
+                for n in 0..REGISTERS.len() {
+                    let len = register_size(n) * 2;
+                    if rest.len() < len || !write_register(gameboy, n, &rest[..len]) {
+                        return self.send("E01");
+                    }
+                    rest = &rest[len..];
+                }
+                self.send("OK")
+            }
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(n) if n < REGISTERS.len() => self.send(&read_register(gameboy, n)),
+                _ => self.send("E01")
+            },
+            "P" => {
+                let ok = args.split_once('=').is_some_and(|(n, value)| {
+                    match usize::from_str_radix(n, 16) {
+                        Ok(n) if n < REGISTERS.len() => write_register(gameboy, n, value),
+                        _ => false
+                    }
+                });
+                self.send(if ok { "OK" } else { "E01" })
+            }
+            "m" => match parse_range(args) {
+                Some((addr, len)) => {
+                    let data: String = (addr..addr + len).map(|a| format!("{:02x}", gameboy.read8(a as u16))).collect();
+                    self.send(&data)
+                }
+                None => self.send("E01")
+            },
+            "M" => {
+                let write = args.split_once(':').and_then(|(range, data)| {
+                    let (addr, len) = parse_range(range)?;
+                    let bytes = parse_hex(data)?;
+                    if bytes.len() != len as usize { return None }
+                    Some((addr, bytes))
+                });
+                match write {
+                    Some((addr, bytes)) => {
+                        for (i, byte) in bytes.into_iter().enumerate() {
+                            gameboy.write8((addr as usize + i) as u16, byte);
+                        }
+                        self.send("OK")
+                    }
+                    None => self.send("E01")
+                }
+            }
+            "Z" | "z" => {
+                let mut parts = args.split(',');
+                let kind = parts.next();
+                let addr = parts.next().and_then(|a| u16::from_str_radix(a, 16).ok());
+                match (kind, addr) {
+                    // Software and hardware breakpoints are the same thing here.
+                    (Some("0"), Some(addr)) | (Some("1"), Some(addr)) => {
+                        if command == "Z" {
+                            self.breakpoints.insert(addr);
+                        } else {
+                            self.breakpoints.remove(&addr);
+                        }
+                        self.send("OK")
+                    }
+                    (Some("0"), None) | (Some("1"), None) => self.send("E01"),
+                    _ => self.send("")
+                }
+            }
+            "c" => {
+                if let Ok(addr) = u16::from_str_radix(args, 16) {
+                    gameboy.registers_mut().set16(PC, addr);
+                }
+                self.state = State::Running;
+                Ok(())
+            }
+            "s" => {
+                if let Ok(addr) = u16::from_str_radix(args, 16) {
+                    gameboy.registers_mut().set16(PC, addr);
+                }
+                gameboy.step(frame_buffer, audio_queue);
+                self.stop(SIGTRAP)
+            }
+            "D" => {
+                self.send("OK")?;
+                self.state = State::Detached;
+                Ok(())
+            }
+            "k" => {
+                self.state = State::Detached;
+                Ok(())
+            }
+            "H" => self.send("OK"),
+            "q" => {
+                if args.starts_with("Supported") {
+                    self.send("PacketSize=4000;qXfer:features:read+")
+                } else if args == "Attached" {
+                    self.send("1")
+                } else if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+                    match parse_range(range) {
+                        Some((offset, len)) => {
+                            let xml = TARGET_XML.as_bytes();
+                            let start = (offset as usize).min(xml.len());
+                            let end = (start + len as usize).min(xml.len());
+                            let more = if end < xml.len() { "m" } else { "l" };
+                            self.send(&format!("{}{}", more, String::from_utf8_lossy(&xml[start..end])))
+                        }
+                        None => self.send("E01")
+                    }
+                } else {
+                    self.send("")
+                }
+            }
+            _ => self.send("")
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.write(packet.as_bytes())
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.stream.set_nonblocking(false)?;
+        let result = self.stream.write_all(data);
+        self.stream.set_nonblocking(true)?;
+        result
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum: u8, b| sum.wrapping_add(*b))
+}
+
+fn register_size(n: usize) -> usize {
+    if n < 8 { 1 } else { 2 }
+}
+
+fn read_register(gameboy: &Gameboy, n: usize) -> String {
+    let registers = gameboy.registers();
+    match register_size(n) {
+        1 => format!("{:02x}", registers.get8(REGISTERS[n])),
+        _ => {
+            let [lo, hi] = registers.get16(REGISTERS[n]).to_le_bytes();
+            format!("{:02x}{:02x}", lo, hi)
+        }
+    }
+}
+
+fn write_register(gameboy: &mut Gameboy, n: usize, hex: &str) -> bool {
+    let bytes = match parse_hex(hex) {
+        Some(bytes) if bytes.len() == register_size(n) => bytes,
+        _ => return false
+    };
+    let registers = gameboy.registers_mut();
+    match bytes[..] {
+        [v] => registers.set8(REGISTERS[n], v),
+        [lo, hi] => registers.set16(REGISTERS[n], u16::from_le_bytes([lo, hi])),
+        _ => return false
+    }
+    true
+}
+
+// "addr,len" in hex, limited to the 16 bit address space.
+fn parse_range(range: &str) -> Option<(u32, u32)> {
+    let (addr, len) = range.split_once(',')?;
+    let addr = u32::from_str_radix(addr, 16).ok()?;
+    let len = u32::from_str_radix(len, 16).ok()?;
+    if addr + len > 0x10000 {
+        return None;
+    }
+    Some((addr, len))
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::gameboy::{booted_gameboy, test_frame_buffer, Mode};
+
+    struct Session {
+        stub: GdbStub,
+        client: TcpStream,
+        gameboy: Gameboy,
+        frame_buffer: Vec<Color>,
+        audio: Vec<f32>
+    }
+
+    impl Session {
+        fn new() -> Self {
+            // INC A; INC B; JR -4
+            let gameboy = booted_gameboy("gdb", &[0x3C, 0x04, 0x18, 0xFC], Mode::DMG);
+
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            client.set_nodelay(true).unwrap();
+            client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let stub = GdbStub::new(listener.accept().unwrap().0).unwrap();
+            Session {
+                stub, client, gameboy,
+                frame_buffer: test_frame_buffer(),
+                audio: Vec::new()
+            }
+        }
+
+        fn tick(&mut self) -> bool {
+            self.stub.tick(&mut self.gameboy, &[], &mut self.frame_buffer, &mut self.audio).unwrap()
+        }
+
+        fn send(&mut self, data: &str) {
+            let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+            self.client.write_all(packet.as_bytes()).unwrap();
+            // Poll until the stub has acked the packet.
+            for _ in 0..500 {
+                self.tick();
+                self.client.set_nonblocking(true).unwrap();
+                let acked = self.client.peek(&mut [0]).is_ok();
+                self.client.set_nonblocking(false).unwrap();
+                if acked {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            panic!("No reply to {}", data);
+        }
+
+        fn reply(&mut self) -> String {
+            let mut reply = Vec::new();
+            let mut byte = [0];
+            loop {
+                self.client.read_exact(&mut byte).unwrap();
+                reply.push(byte[0]);
+                if reply.len() > 3 && reply[reply.len() - 3] == b'#' {
+                    break;
+                }
+            }
+            let reply = String::from_utf8(reply).unwrap();
+            let start = reply.find('$').unwrap();
+            reply[start + 1..reply.len() - 3].to_string()
+        }
+
+        fn request(&mut self, data: &str) -> String {
+            self.send(data);
+            self.reply()
+        }
+    }
+
+    #[test]
+    fn test_registers_and_memory() {
+        let mut session = Session::new();
+        assert_eq!(session.request("?"), "S05");
+        assert_eq!(session.request("g"), "01b0001300d8014dfeff0001");
+        assert_eq!(session.request("p9"), "0001");
+        assert_eq!(session.request("P0=2a"), "OK");
+        assert_eq!(session.request("p0"), "2a");
+
+        assert_eq!(session.request("m100,4"), "3c0418fc");
+        assert_eq!(session.request("Mc000,2:beef"), "OK");
+        assert_eq!(session.request("mc000,2"), "beef");
+        assert_eq!(session.request("mfffe,4"), "E01");
+    }
+
+    #[test]
+    fn test_step_and_breakpoints() {
+        let mut session = Session::new();
+        assert_eq!(session.request("s"), "S05");
+        assert_eq!(session.request("p9"), "0101");
+
+        assert_eq!(session.request("Z0,102,1"), "OK");
+        session.send("c");
+        assert_eq!(session.reply(), "S05");
+        assert_eq!(session.request("p9"), "0201");
+        assert_eq!(session.request("p2"), "01");
+
+        // Continuing steps over the breakpoint it stopped at.
+        session.send("c");
+        assert_eq!(session.reply(), "S05");
+        assert_eq!(session.request("p2"), "02");
+
+        assert_eq!(session.request("z0,102,1"), "OK");
+        session.send("c");
+        assert!(session.tick());
+        assert!(!session.stub.is_stopped());
+        assert_eq!(session.request("D"), "OK");
+        assert!(!session.stub.is_attached());
+    }
+}
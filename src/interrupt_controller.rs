@@ -1,6 +1,7 @@
 use std::slice::Iter;
 use crate::memory::memory_map::MemoryMappedDevice;
 use crate::memory::memory_map::MappedArea;
+use crate::save_state::{self, SaveState, StateReader, StateWriter};
 
 const IE: u16 = 0xFFFF;
 const IF: u16 = 0xFF0F;
@@ -36,7 +37,7 @@ impl Interrupt {
 
     fn priority_iter() -> Iter<'static, Interrupt> {
         static PRIORITY_ORDER: [Interrupt; 4] = [VBlank, Stat, Timer, Serial];
-        PRIORITY_ORDER.into_iter()
+        PRIORITY_ORDER.iter()
     }
 }
 
@@ -72,7 +73,7 @@ impl InterruptController {
 
     fn fire(&mut self, interrupt: Interrupt, clear: bool) -> Option<u16> {
         let flag = interrupt.flag();
-        if clear { self.if_reg = self.if_reg ^ flag; }
+        if clear { self.if_reg ^= flag; }
         Some(interrupt.addr())
     }
 
@@ -82,7 +83,7 @@ impl InterruptController {
     }
 
     pub fn request(&mut self, interrupt: Interrupt) {
-        self.if_reg = self.if_reg | interrupt.flag();
+        self.if_reg |= interrupt.flag();
     }
 }
 
@@ -106,6 +107,19 @@ impl MemoryMappedDevice for InterruptController {
     }
 }
 
+impl SaveState for InterruptController {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.ie_reg);
+        w.write_u8(self.if_reg);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        self.ie_reg = r.read_u8()?;
+        self.if_reg = r.read_u8()?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,6 +1,7 @@
 use crate::gameboy::JoypadInput;
 use crate::memory::memory_map::MemoryMappedDevice;
 use crate::memory::memory_map::MappedArea;
+use crate::save_state::{self, SaveState, StateReader, StateWriter};
 
 const P1: u16 = 0xFF00;
 
@@ -88,3 +89,18 @@ impl MemoryMappedDevice for JoypadController {
     }
 
 }
+
+impl SaveState for JoypadController {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.p1);
+        w.write_u8(self.direction_nib);
+        w.write_u8(self.button_nib);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        self.p1 = r.read_u8()?;
+        self.direction_nib = r.read_u8()?;
+        self.button_nib = r.read_u8()?;
+        Ok(())
+    }
+}
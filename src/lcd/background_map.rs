@@ -28,6 +28,7 @@ impl TileAttributes {
 }
 
 #[derive(Clone, Copy, Default)]
+#[allow(dead_code)]
 pub struct BGPixel {
     pub value: u8,
     pub above_oam: bool,
@@ -69,13 +70,38 @@ impl<'a> BackgroundMap<'a> {
             VRamBank::Bank0 => self.tile_set0.tile(tile_idx),
             VRamBank::Bank1 => self.tile_set1.tile(tile_idx)
         };
-        let tile_row = tile.row(row % 8);
+        let tile_row = tile.row(if tile_attributes.y_flip { 7 - row % 8 } else { row % 8 });
         for (idx, p) in result.iter_mut().enumerate() {
             *p = BGPixel {
-                value: tile_row[idx],
+                value: tile_row[if tile_attributes.x_flip { 7 - idx } else { idx }],
                 above_oam: tile_attributes.above_oam,
                 palette_number: tile_attributes.palette_num
             };
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flipped_tiles() {
+        // Tile 0 has color 3 in its top left pixel and color 1 in its
+        // bottom left pixel.
+        let mut tiles = [0; 16];
+        tiles[0] = 0x80;
+        tiles[1] = 0x80;
+        tiles[14] = 0x80;
+        let map = [0; 32 * 32];
+        let values = |attribute: u8, row: usize| {
+            let attributes = [attribute; 32 * 32];
+            let map = BackgroundMap::new(&map, TileSet::new(&tiles, false), TileSet::new(&tiles, false), Some(&attributes));
+            map.row(row)[..8].iter().map(|p| p.value).collect::<Vec<_>>()
+        };
+        assert_eq!(values(0x00, 0), [3, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(values(0x20, 0), [0, 0, 0, 0, 0, 0, 0, 3]);
+        assert_eq!(values(0x40, 0), [1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(values(0x60, 7), [0, 0, 0, 0, 0, 0, 0, 3]);
+    }
+}
@@ -1,11 +1,12 @@
 use crate::memory::memory_bus::{MemoryBus};
 use crate::memory::memory_map::{MemoryMappedDevice, MappedArea};
 use crate::gameboy::{Color, Mode, GAME_WIDTH};
-use super::tiles::TileSet;
-use super::palette::{Palette, PaletteManager};
+use super::tiles::{Tile, TileSet};
+use super::palette::{Palette, PaletteManager, TilePalette};
 use super::background_map::{BackgroundMap, BGPixel};
 use super::oam::{OamEntries, OamPixel, SpriteSize};
 use crate::interrupt_controller::Interrupt;
+use crate::save_state::{self, SaveState, SaveStateError, StateReader, StateWriter};
 
 pub const VRAM_START: u16 = 0x8000;
 pub const VRAM_SIZE: usize = 0x2000;
@@ -61,8 +62,8 @@ impl DmaExecutor {
 
     pub fn execute(&self, mb: &mut MemoryBus) {
         for i in 0..16 {
-            let d = mb.get8(self.src_start_addr + i as u16);
-            mb.set8(self.dst_start_addr + i as u16 + 0x8000, d);
+            let d = mb.dma_get8(self.src_start_addr + i as u16);
+            mb.dma_set8(self.dst_start_addr + i as u16 + 0x8000, d);
         }
     }
 }
@@ -90,6 +91,35 @@ impl DmaTransfer {
     }
 }
 
+impl SaveState for Option<DmaTransfer> {
+    fn save_state(&self, w: &mut StateWriter) {
+        match self {
+            None => w.write_bool(false),
+            Some(dma_transfer) => {
+                w.write_bool(true);
+                w.write_u16(dma_transfer.src_start);
+                w.write_u16(dma_transfer.dst_start);
+                w.write_u16(dma_transfer.len);
+                w.write_u16(dma_transfer.transferred);
+            }
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        *self = if r.read_bool()? {
+            let mut dma_transfer = DmaTransfer::new(r.read_u16()?, r.read_u16()?, r.read_u16()?);
+            dma_transfer.transferred = r.read_u16()?;
+            if dma_transfer.transferred >= dma_transfer.len {
+                return Err(SaveStateError::Invalid("HDMA transfer"))
+            }
+            Some(dma_transfer)
+        } else {
+            None
+        };
+        Ok(())
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 enum Period {
     OAMSearch,
@@ -141,6 +171,27 @@ impl Period {
 
 use Period::*;
 
+impl SaveState for State {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.period.mode());
+        w.write_u32(self.clocks_left);
+        w.write_u8(self.ly);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        self.period = match r.read_u8()? {
+            0 => HBlank,
+            1 => VBlank,
+            2 => OAMSearch,
+            3 => PixelTransfer,
+            _ => return Err(SaveStateError::Invalid("LCD period"))
+        };
+        self.clocks_left = r.read_u32()?;
+        self.ly = r.read_u8()?;
+        Ok(())
+    }
+}
+
 struct State {
     period: Period,
     clocks_left: u32,
@@ -204,7 +255,9 @@ pub struct LcdController {
     mode: Mode,
     vram_dma_src: u16,
     vram_dma_dst: u16,
-    dma_transfer: Option<DmaTransfer>
+    dma_transfer: Option<DmaTransfer>,
+    // The source of the running OAM DMA and how many bytes it has copied.
+    oam_dma: Option<(u16, u8)>
 }
 
 impl LcdController {
@@ -234,7 +287,8 @@ impl LcdController {
             mode: Mode::CGB,
             vram_dma_src: 0,
             vram_dma_dst: 0,
-            dma_transfer: None
+            dma_transfer: None,
+            oam_dma: None
         }
     }
 
@@ -251,6 +305,7 @@ impl LcdController {
         ]
     }
 
+    #[allow(dead_code)]
     pub fn set_mode(&mut self, mode: Mode) {
         self.mode = mode;
     }
@@ -300,11 +355,30 @@ impl LcdController {
                 }
             }
         }
-        return None
+        None
+    }
+
+    /// Starts copying the 0xA0 bytes at `source` into OAM, one per M-cycle.
+    pub fn oam_dma(&mut self, source: u16) {
+        self.oam_dma = Some((source, 0));
+    }
+
+    /// Whether an OAM DMA is running.
+    pub fn oam_dma_running(&self) -> bool {
+        self.oam_dma.is_some()
+    }
+
+    /// The address the OAM DMA copies from next.
+    pub fn oam_dma_source(&self) -> Option<u16> {
+        self.oam_dma.map(|(source, copied)| source + copied as u16)
     }
 
-    pub fn dma(&mut self, data: &[u8]) {
-        self.oam.copy_from_slice(data);
+    /// Stores the next byte of the OAM DMA, ending it after the last one.
+    pub fn oam_dma_copy(&mut self, byte: u8) {
+        if let Some((source, copied)) = self.oam_dma {
+            self.oam[copied as usize] = byte;
+            self.oam_dma = if copied as usize + 1 < OAM_SIZE { Some((source, copied + 1)) } else { None };
+        }
     }
 
     pub fn vram_dma(&mut self, data: &[u8]) {
@@ -332,7 +406,7 @@ impl LcdController {
         }
     }
 
-    fn bg_tile_sets(&self) -> (TileSet, TileSet) {
+    fn bg_tile_sets(&self) -> (TileSet<'_>, TileSet<'_>) {
         let (start, shift) = if b4!(self.lcdc) == 0 {
             (0x0800, true)
         } else {
@@ -344,7 +418,7 @@ impl LcdController {
          TileSet::new(&self.vram1[range], shift))
     }
 
-    fn bg_map(&self, flag: u8) -> BackgroundMap {
+    fn bg_map(&self, flag: u8) -> BackgroundMap<'_> {
         let (tile_set0, tile_set1) = self.bg_tile_sets();
         let start = if flag == 1 {
             TILE_MAP_1_OFFSET
@@ -416,15 +490,18 @@ impl LcdController {
         }
     }
 
+    fn with_oam_entries<T>(&self, f: impl FnOnce(&OamEntries) -> T) -> T {
+        let oam_tile_set0 = TileSet::new(&self.vram0[0x0..0x1000], false);
+        let oam_tile_set1 = TileSet::new(&self.vram1[0x0..0x1000], false);
+        f(&OamEntries::new(self.mode, &self.oam, &oam_tile_set0, &oam_tile_set1, self.sprite_size()))
+    }
+
     fn oam_row(&self) -> [Option<OamPixel>; GAME_WIDTH] {
         if !self.oam_enabled() {
             return [None; GAME_WIDTH];
         }
 
-        let oam_tile_set0 = TileSet::new(&self.vram0[0x0..0x1000], false);
-        let oam_tile_set1 = TileSet::new(&self.vram1[0x0..0x1000], false);
-        let oam_entries = OamEntries::new(self.mode, &self.oam, &oam_tile_set0, &oam_tile_set1, self.sprite_size());
-        oam_entries.row(self.ly)
+        self.with_oam_entries(|oam_entries| oam_entries.row(self.ly))
     }
 
     fn fill_framebuffer(&self, frame_buffer: &mut [Color]) {
@@ -480,56 +557,174 @@ impl LcdController {
         }
     }
 
+    /// VRAM bank 0 or 1, whether or not it is mapped.
+    pub fn vram_bank(&mut self, bank: usize) -> &mut [u8] {
+        match bank {
+            0 => &mut self.vram0,
+            _ => &mut self.vram1
+        }
+    }
+
     pub fn vram_dma_source(&self) -> u16 {
         self.vram_dma_src
     }
 
 
-    pub fn fill_tile_framebuffer(&self, tile_frame_buffer: &mut [Color]) {
-        let tile_set = TileSet::new(&self.vram0[0..0x1000], false);
+    /// The color of `value` in `palette`.
+    pub fn palette_color(&self, palette: TilePalette, value: u8) -> Color {
+        match palette {
+            TilePalette::Bgp => self.bg_palette.color(value),
+            TilePalette::Obp0 => self.ob0_palette.color(value),
+            TilePalette::Obp1 => self.ob1_palette.color(value),
+            TilePalette::Bg(n) => self.bg_palette_manager.color(n, value),
+            TilePalette::Obj(n) => self.ob_palette_manager.color(n, value)
+        }
+    }
+
+    /// The RGB555 value of `value` in one of the CGB palettes, or `None`
+    /// for the DMG ones and out of range palettes or values.
+    pub fn cgb_color(&self, palette: TilePalette, value: u8) -> Option<u16> {
+        match palette {
+            TilePalette::Bg(n) if n < 8 && value < 4 => Some(self.bg_palette_manager.raw(n, value)),
+            TilePalette::Obj(n) if n < 8 && value < 4 => Some(self.ob_palette_manager.raw(n, value)),
+            _ => None
+        }
+    }
+
+    /// Changes `value` in one of the CGB palettes to an RGB555 value, as
+    /// writing it through BCPD or OCPD would. Returns false, changing
+    /// nothing, when `cgb_color` would return `None`.
+    pub fn set_cgb_color(&mut self, palette: TilePalette, value: u8, raw: u16) -> bool {
+        match palette {
+            TilePalette::Bg(n) if n < 8 && value < 4 => self.bg_palette_manager.set_raw(n, value, raw),
+            TilePalette::Obj(n) if n < 8 && value < 4 => self.ob_palette_manager.set_raw(n, value, raw),
+            _ => return false
+        }
+        true
+    }
 
-        for i in 0usize..256 {
-            let tile = tile_set.tile(i as u8);
-            let origin = (i / 16)*128*8 + (i % 16)*8;
+    /// Draws the 384 tiles of a VRAM bank, 16 to a row, into a 128x192
+    /// area of a frame buffer `stride` pixels wide.
+    pub fn draw_tiles(&self, bank: usize, palette: TilePalette, frame_buffer: &mut [Color], stride: usize) {
+        let vram = if bank == 0 { &self.vram0 } else { &self.vram1 };
+        for (i, data) in vram[..0x1800].chunks(16).enumerate() {
+            let tile = Tile::new(data);
+            let origin = (i / 16) * 8 * stride + (i % 16) * 8;
             for j in 0..8 {
-                let row = tile.row(j);
-                let row_start = origin + 128 * j;
-                for (k, p) in row.iter().enumerate() {
-                    let color = self.bg_palette.color(*p);
-                    tile_frame_buffer[row_start + k] = color;
+                for (k, p) in tile.row(j).iter().enumerate() {
+                    frame_buffer[origin + j * stride + k] = self.palette_color(palette, *p);
                 }
             }
         }
+    }
 
-        let tile_set = TileSet::new(&self.vram0[0x01000..0x1800], false);
-        let start = 16*128*8;
+    pub fn oam(&self) -> &[u8] {
+        &self.oam
+    }
 
-        for i in 0usize..128 {
-            let tile = tile_set.tile(i as u8);
-            let origin = start + (i / 16)*128*8 + (i % 16)*8;
-            for j in 0..8 {
-                let row = tile.row(j);
-                let row_start = origin + 128 * j;
-                for (k, p) in row.iter().enumerate() {
-                    let color = self.bg_palette.color(*p);
-                    tile_frame_buffer[row_start + k] = color;
-                }
+    /// 8 or 16, as LCDC selects.
+    pub fn sprite_height(&self) -> usize {
+        match self.sprite_size() {
+            SpriteSize::EightByEight => 8,
+            SpriteSize::EightBySixteen => 16
+        }
+    }
+
+    /// The sprites on line `ly` that the ten sprite limit drops.
+    pub fn dropped_sprites(&self, ly: u8) -> Vec<usize> {
+        self.with_oam_entries(|oam_entries| oam_entries.dropped(ly))
+    }
+
+    /// Draws sprite `index` into an 8 pixel wide area of a frame buffer
+    /// `stride` pixels wide, as tall as the sprite size. Transparent pixels
+    /// are left as they are.
+    pub fn draw_sprite(&self, index: usize, frame_buffer: &mut [Color], stride: usize) {
+        let pixels = self.with_oam_entries(|oam_entries| oam_entries.pixels(index));
+        for (y, row) in pixels.iter().enumerate() {
+            for (x, pixel) in row.iter().enumerate().filter(|(_, pixel)| pixel.value != 0) {
+                frame_buffer[y * stride + x] = self.oam_color(*pixel);
+            }
+        }
+    }
+
+    /// Draws the 32x32 tile map at 0x9C00 if `high` or else 0x9800 into a
+    /// 256x256 area of a frame buffer `stride` pixels wide, using the tile
+    /// data LCDC selects for the background.
+    pub fn draw_map(&self, high: bool, frame_buffer: &mut [Color], stride: usize) {
+        let map = self.bg_map(high as u8);
+        for y in 0..256 {
+            for (x, pixel) in map.row(y).iter().enumerate() {
+                frame_buffer[y * stride + x] = self.bg_color(*pixel);
             }
         }
+    }
+}
 
+impl SaveState for LcdController {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.vram0);
+        w.write_bytes(&self.vram1);
+        w.write_bool(match self.vram_bank { Bank0 => false, Bank1 => true });
+        w.write_bytes(&self.oam);
+        for v in &[self.lcdc, self.stat, self.scy, self.scx, self.ly, self.lyc,
+                   self.bgp, self.obp0, self.obp1, self.wy, self.wx] {
+            w.write_u8(*v);
+        }
+        self.bg_palette_manager.save_state(w);
+        self.ob_palette_manager.save_state(w);
+        self.state.save_state(w);
+        w.write_u16(self.vram_dma_src);
+        w.write_u16(self.vram_dma_dst);
+        self.dma_transfer.save_state(w);
+        w.write_bool(self.oam_dma.is_some());
+        if let Some((source, copied)) = self.oam_dma {
+            w.write_u16(source);
+            w.write_u8(copied);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        r.read_bytes_into(&mut self.vram0)?;
+        r.read_bytes_into(&mut self.vram1)?;
+        self.vram_bank = if r.read_bool()? { Bank1 } else { Bank0 };
+        r.read_bytes_into(&mut self.oam)?;
+        for v in &mut [&mut self.lcdc, &mut self.stat, &mut self.scy, &mut self.scx,
+                       &mut self.ly, &mut self.lyc, &mut self.bgp, &mut self.obp0,
+                       &mut self.obp1, &mut self.wy, &mut self.wx] {
+            **v = r.read_u8()?;
+        }
+        self.bg_palette = Palette::new(self.bgp);
+        self.ob0_palette = Palette::new(self.obp0);
+        self.ob1_palette = Palette::new(self.obp1);
+        self.bg_palette_manager.load_state(r)?;
+        self.ob_palette_manager.load_state(r)?;
+        self.state.load_state(r)?;
+        self.vram_dma_src = r.read_u16()?;
+        self.vram_dma_dst = r.read_u16()?;
+        self.dma_transfer.load_state(r)?;
+        self.oam_dma = if r.read_bool()? {
+            let (source, copied) = (r.read_u16()?, r.read_u8()?);
+            if copied as usize >= OAM_SIZE {
+                return Err(SaveStateError::Invalid("OAM DMA"))
+            }
+            Some((source, copied))
+        } else {
+            None
+        };
+        Ok(())
     }
 }
 
 impl MemoryMappedDevice for LcdController {
     fn set8(&mut self, addr: u16, byte: u8) {
         match addr {
-            VRAM_START ... VRAM_END => {
+            VRAM_START ..= VRAM_END => {
                 self.vram_mut()[(addr - VRAM_START) as usize] = byte;
             }
             VBK => {
                 self.vram_bank = if b0!(byte) == 0 { Bank0 } else { Bank1 };
             }
-            OAM_START ... OAM_END => {
+            OAM_START ..= OAM_END => {
                 self.oam[(addr - OAM_START) as usize] = byte;
             }
             LCDC => {
@@ -601,7 +796,7 @@ impl MemoryMappedDevice for LcdController {
 
     fn get8(&self, addr: u16) -> u8 {
         match addr {
-            VRAM_START ... VRAM_END => {
+            VRAM_START ..= VRAM_END => {
                 self.vram()[(addr - VRAM_START) as usize]
             }
             VBK => {
@@ -610,7 +805,7 @@ impl MemoryMappedDevice for LcdController {
                     VRamBank::Bank1 => 0x01
                 }
             }
-            OAM_START ... OAM_END => {
+            OAM_START ..= OAM_END => {
                 self.oam[(addr - OAM_START) as usize]
             }
             LCDC => self.lcdc,
@@ -5,3 +5,4 @@ mod oam;
 mod controller;
 
 pub use self::controller::LcdController;
+pub use self::palette::TilePalette;
@@ -53,7 +53,11 @@ impl OamEntry {
     }
 
     fn row(&self, ly: u8, tile_set0: &TileSet, tile_set1: &TileSet) -> [u8; 8] {
-        let mut tile_y = (ly + 16 - self.y) as usize;
+        self.sprite_row((ly + 16 - self.y) as usize, tile_set0, tile_set1)
+    }
+
+    // Row `tile_y` of the sprite, counting from its top.
+    fn sprite_row(&self, mut tile_y: usize, tile_set0: &TileSet, tile_set1: &TileSet) -> [u8; 8] {
         let tile_set = match self.vram_bank {
             VRamBank::Bank0 => tile_set0,
             VRamBank::Bank1 => tile_set1
@@ -92,6 +96,29 @@ impl<'a> OamEntries<'a> {
         OamEntries { entries, tile_set0, tile_set1 }
     }
 
+    /// The sprites on line `ly` after the first ten, which aren't drawn.
+    pub fn dropped(&self, ly: u8) -> Vec<usize> {
+        self.entries.iter().enumerate()
+            .filter(|(_, e)| e.is_on_row(ly))
+            .skip(10)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// The pixels of sprite `index`, a row at a time and flipped as they are
+    /// drawn. Transparent pixels have a value of 0.
+    pub fn pixels(&self, index: usize) -> Vec<[OamPixel; 8]> {
+        let entry = &self.entries[index];
+        (0..entry.height as usize).map(|tile_y| {
+            let row = entry.sprite_row(tile_y, self.tile_set0, self.tile_set1);
+            let mut pixels = [OamPixel { value: 0, above_background: entry.above_background, palette_number: entry.palette_number }; 8];
+            for (pixel, value) in pixels.iter_mut().zip(row.iter()) {
+                pixel.value = *value;
+            }
+            pixels
+        }).collect()
+    }
+
     pub fn row(&self, ly: u8) -> [Option<OamPixel>; GAME_WIDTH] {
         let mut result = [None; GAME_WIDTH];
 
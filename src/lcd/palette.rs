@@ -1,11 +1,35 @@
+use std::fmt;
 use crate::gameboy::{Color};
-use super::background_map::BGPixel;
+use crate::save_state::{self, SaveState, StateReader, StateWriter};
 
 const WHITE: Color = Color { r: 31, g: 31, b: 31 };
 const LIGHT_GRAY: Color = Color { r: 20, g: 20, b: 20 };
 const DARK_GRAY: Color = Color { r: 10, g: 10, b: 10 };
 const BLACK: Color = Color { r: 0, g: 0, b: 0 };
 
+/// A palette to color tiles with outside of rendering: one of the DMG
+/// palette registers or one of the eight CGB background or object palettes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TilePalette {
+    Bgp,
+    Obp0,
+    Obp1,
+    Bg(u8),
+    Obj(u8)
+}
+
+impl fmt::Display for TilePalette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TilePalette::Bgp => write!(f, "BGP"),
+            TilePalette::Obp0 => write!(f, "OBP0"),
+            TilePalette::Obp1 => write!(f, "OBP1"),
+            TilePalette::Bg(n) => write!(f, "BG{}", n),
+            TilePalette::Obj(n) => write!(f, "OBJ{}", n)
+        }
+    }
+}
+
 pub struct Palette {
     palette: u8
 }
@@ -69,14 +93,41 @@ impl PaletteManager {
         self.data[self.index]
     }
 
-    pub fn color(&self, palette_number: u8, value: u8) -> Color {
+    /// The RGB555 value of color `value` in a palette.
+    pub fn raw(&self, palette_number: u8, value: u8) -> u16 {
+        let offset = (palette_number * 8 + value * 2) as usize;
+        u16::from_le_bytes([self.data[offset], self.data[offset + 1]])
+    }
+
+    /// Sets color `value` in a palette to an RGB555 value without moving
+    /// the index.
+    pub fn set_raw(&mut self, palette_number: u8, value: u8, raw: u16) {
         let offset = (palette_number * 8 + value * 2) as usize;
-        let b0 = self.data[offset] as u16;
-        let b1 = self.data[offset + 1] as u16;
-        let b10 = (b1 << 8) | b0;
+        self.data[offset..offset + 2].copy_from_slice(&raw.to_le_bytes());
+    }
+
+    pub fn color(&self, palette_number: u8, value: u8) -> Color {
+        let b10 = self.raw(palette_number, value);
         let r = b10 & 0x1F;
         let g = (b10 >> 5) & 0x1F;
         let b = (b10 >> 10) & 0x1F;
         Color::new(r as u8, g as u8, b as u8)
     }
 }
+
+impl SaveState for PaletteManager {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_usize(self.index);
+        w.write_bool(self.auto_increment);
+        w.write_bytes(&self.data);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        self.index = r.read_usize()?;
+        if self.index >= self.data.len() {
+            return Err(save_state::SaveStateError::Invalid("palette index"))
+        }
+        self.auto_increment = r.read_bool()?;
+        r.read_bytes_into(&mut self.data)
+    }
+}
@@ -10,9 +10,9 @@ impl <'a> Tile<'a> {
         let lb = self.data[row * 2];
         let ub = self.data[row * 2 + 1];
         let mut result = [0; 8];
-        for i in 0..8 {
+        for (i, p) in result.iter_mut().enumerate() {
             let shift = 7 - i;
-            result[i] = ((ub >> shift << 1) & 0b10) | ((lb >> shift) & 0b01);
+            *p = ((ub >> shift << 1) & 0b10) | ((lb >> shift) & 0b01);
         }
         result
     }
@@ -28,11 +28,11 @@ impl<'a> TileSet<'a> {
         TileSet { data, shift }
     }
 
-    pub fn tile(&self, idx: u8) -> Tile {
+    pub fn tile(&self, idx: u8) -> Tile<'_> {
         let start = if self.shift {
-            ((idx as i8 as i16 + 128) as usize)
+            (idx as i8 as i16 + 128) as usize
         } else {
-            (idx as usize)
+            idx as usize
         } * 16;
         Tile::new(&self.data[start..start+16])
     }
@@ -0,0 +1,83 @@
+//! A Game Boy and Game Boy Color emulator core.
+//!
+//! The core has no opinion about how frames are displayed or how audio is
+//! played. A frontend owns a frame buffer and an audio buffer and hands them
+//! to [`Gameboy::tick`], which runs the machine until the next VBlank:
+//!
+//! ```no_run
+//! use gameboy::{Cartridge, Color, Gameboy, Mode, GAME_WIDTH, GAME_HEIGHT};
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let cartridge = Cartridge::new("game.gb".into(), None)?;
+//! let mut gameboy = Gameboy::new(false, Mode::CGB);
+//! gameboy.boot(cartridge, true);
+//!
+//! let mut frame_buffer = [Color::new(31, 31, 31); GAME_WIDTH * GAME_HEIGHT];
+//! let mut audio_queue = Vec::new();
+//! gameboy.tick(&[], &mut frame_buffer, &mut audio_queue);
+//! # Ok(())
+//! # }
+//! ```
+
+#![allow(clippy::upper_case_acronyms, clippy::module_inception)]
+
+#[cfg(feature = "debug")]
+macro_rules! debug {
+    ($( $args:expr ),*) => { println!( $( $args ),* ); }
+}
+
+#[cfg(not(feature = "debug"))]
+macro_rules! debug {
+    ($( $args:expr ),*) => {}
+}
+
+mod bitops;
+mod cartridge;
+pub mod clocks;
+mod memory;
+mod cpu;
+mod ram_device;
+mod gameboy;
+mod interrupt_controller;
+mod timer_controller;
+mod joypad_controller;
+mod lcd;
+mod sound;
+mod serial;
+mod save_state;
+mod rewind;
+mod movie;
+mod trace;
+mod profiler;
+mod cheats;
+mod ram_search;
+mod cdl;
+mod draw;
+mod memory_viewer;
+mod tile_viewer;
+mod map_viewer;
+mod oam_viewer;
+mod palette_viewer;
+pub mod gdb;
+pub mod debugger;
+pub mod disasm;
+pub mod screenshot;
+
+pub use crate::gameboy::{Gameboy, Color, JoypadInput, Mode, GAME_WIDTH, GAME_HEIGHT};
+pub use crate::cartridge::{Cartridge, Symbols};
+pub use crate::cpu::{BadReturn, FrameKind, Register, Registers, StackFrame};
+pub use crate::memory::memory_bus::MemoryAccess;
+pub use crate::save_state::SaveStateError;
+pub use crate::rewind::RewindBuffer;
+pub use crate::movie::{Movie, MovieError, frame_hash};
+pub use crate::trace::Tracer;
+pub use crate::profiler::Profiler;
+pub use crate::cheats::Cheat;
+pub use crate::cdl::CodeDataLog;
+pub use crate::memory_viewer::{MemoryRegion, MemoryViewer};
+pub use crate::tile_viewer::TileViewer;
+pub use crate::map_viewer::MapViewer;
+pub use crate::oam_viewer::OamViewer;
+pub use crate::palette_viewer::{Channel, PaletteViewer};
+pub use crate::lcd::TilePalette;
+pub use crate::ram_search::{RamSearch, SearchFilter, SearchFormat, SearchResult};
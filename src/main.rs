@@ -1,6 +1,10 @@
+use std::process;
+use std::path::PathBuf;
 use structopt::StructOpt;
 use sdl2::audio::AudioSpecDesired;
-use crate::clocks::AUDIO_SAMPLE_RATE;
+use gameboy::{Cartridge, Movie};
+use gameboy::gdb::GdbStub;
+use gameboy::clocks::AUDIO_SAMPLE_RATE;
 
 #[cfg(feature = "debug")]
 macro_rules! debug {
@@ -13,19 +17,6 @@ macro_rules! debug {
 }
 
 
-mod bitops;
-mod cartridge;
-mod clocks;
-mod memory;
-mod cpu;
-mod ram_device;
-mod gameboy;
-mod interrupt_controller;
-mod timer_controller;
-mod joypad_controller;
-mod lcd;
-mod sound;
-mod serial;
 mod renderer;
 
 #[derive(StructOpt)]
@@ -38,13 +29,58 @@ struct Cli {
     skip_boot_rom: bool,
     #[structopt(short, long, parse(from_os_str))]
     save_path: Option<std::path::PathBuf>,
+    /// Record the inputs to a movie file, written on exit.
+    #[structopt(long, parse(from_os_str))]
+    record: Option<std::path::PathBuf>,
+    /// Play back a movie file, using its mode and boot options.
+    #[structopt(long, parse(from_os_str))]
+    play: Option<std::path::PathBuf>,
+    /// Wait for a GDB remote protocol client on this local port before
+    /// starting.
+    #[structopt(long)]
+    gdb: Option<u16>,
+    /// Open the debugger console when this address or label is reached.
+    #[structopt(long)]
+    break_at: Option<String>,
+    /// Profile the cycles spent in each routine, writing the call stacks to
+    /// this file in the folded flamegraph format on exit.
+    #[structopt(long, parse(from_os_str))]
+    profile: Option<PathBuf>,
+    /// Log which ROM and RAM bytes are run as code or read as data, adding
+    /// to this BizHawk CDL file if it exists and saving it on exit.
+    #[structopt(long, parse(from_os_str))]
+    cdl: Option<PathBuf>,
     #[structopt(parse(from_os_str))]
-    cartridge_path: std::path::PathBuf,
+    cartridge_path: PathBuf,
+}
+
+fn fail(msg: String) -> ! {
+    eprintln!("{}", msg);
+    process::exit(1)
 }
 
 fn main() {
     let args = Cli::from_args();
-    let cartridge = cartridge::Cartridge::new(args.cartridge_path, args.save_path);
+    let mut cartridge = Cartridge::new(args.cartridge_path, args.save_path)
+        .unwrap_or_else(|e| fail(format!("Can't load cartridge: {}", e)));
+    let playback = args.play.map(|path| {
+        Movie::read(&path).unwrap_or_else(|e| fail(format!("Can't read {}: {}", path.display(), e)))
+    });
+    let (dmg, skip_boot_rom) = match &playback {
+        Some(movie) => {
+            movie.prepare(&mut cartridge).unwrap_or_else(|e| fail(format!("Can't play movie: {}", e)));
+            (!movie.mode().is_cgb(), movie.skip_boot_rom())
+        }
+        None => (args.dmg, args.skip_boot_rom)
+    };
+    if args.gdb.is_some() && (args.record.is_some() || playback.is_some()) {
+        fail("--gdb can't be combined with movies".to_string());
+    }
+    let gdb = args.gdb.map(|port| {
+        println!("Waiting for a debugger on port {}", port);
+        GdbStub::listen(("127.0.0.1", port))
+            .unwrap_or_else(|e| fail(format!("Can't listen on port {}: {}", port, e)))
+    });
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
@@ -74,5 +110,15 @@ fn main() {
 
     let mut renderer = renderer::Renderer::new(canvas, audio_queue, event_pump);
 
-    renderer.run(cartridge, args.debug, args.skip_boot_rom, args.dmg);
+    renderer.run(cartridge, renderer::RunOptions {
+        debug: args.debug,
+        skip_boot_rom,
+        dmg,
+        record: args.record,
+        playback,
+        gdb,
+        break_at: args.break_at,
+        profile: args.profile,
+        cdl: args.cdl
+    });
 }
@@ -0,0 +1,194 @@
+use crate::draw::{self, BACKGROUND, GLYPH_HEIGHT, MARGIN, TEXT};
+use crate::gameboy::{Color, Gameboy, GAME_WIDTH, GAME_HEIGHT};
+
+const MAP_SIZE: usize = 256;
+const MAP_Y: usize = MARGIN + GLYPH_HEIGHT + 2;
+const STATUS_Y: usize = MAP_Y + MAP_SIZE + 2;
+
+const LCDC: u16 = 0xFF40;
+const SCY: u16 = 0xFF42;
+const SCX: u16 = 0xFF43;
+const WY: u16 = 0xFF4A;
+const WX: u16 = 0xFF4B;
+
+const VIEWPORT: Color = Color { r: 31, g: 10, b: 8 };
+const WINDOW: Color = Color { r: 6, g: 28, b: 10 };
+const HOVER: Color = Color { r: 8, g: 16, b: 31 };
+
+/// Shows one of the two 32x32 tile maps as the background would draw it,
+/// with the part on screen outlined. When the window is on, its visible
+/// part is outlined on the window's map and its top left corner marked on
+/// the background's.
+pub struct MapViewer {
+    // Whether the map at 0x9C00 is shown rather than 0x9800.
+    high: bool,
+    // The column and row of the tile under the mouse.
+    hover: Option<(usize, usize)>
+}
+
+impl MapViewer {
+    pub const WIDTH: usize = 2 * MARGIN + MAP_SIZE;
+    pub const HEIGHT: usize = STATUS_Y + 2 * GLYPH_HEIGHT + MARGIN;
+
+    /// A viewer showing the map the background is using.
+    pub fn new(gameboy: &mut Gameboy) -> Self {
+        MapViewer { high: gameboy.read8(LCDC) & 0x08 != 0, hover: None }
+    }
+
+    /// The address of the map shown.
+    pub fn map_addr(&self) -> u16 {
+        if self.high { 0x9C00 } else { 0x9800 }
+    }
+
+    /// Switches to the other map.
+    pub fn toggle_map(&mut self) {
+        self.high = !self.high;
+    }
+
+    /// The column and row of the tile drawn at (`x`, `y`).
+    pub fn tile_at(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        let x = x.checked_sub(MARGIN).filter(|x| *x < MAP_SIZE)?;
+        let y = y.checked_sub(MAP_Y).filter(|y| *y < MAP_SIZE)?;
+        Some((x / 8, y / 8))
+    }
+
+    /// Tracks the mouse at (`x`, `y`), or off the view with None.
+    pub fn set_hover(&mut self, position: Option<(usize, usize)>) {
+        self.hover = position.and_then(|(x, y)| self.tile_at(x, y));
+    }
+
+    /// The map entry at `col` and `row`: its address, tile index, the tile
+    /// it refers to with the addressing mode LCDC selects and, on CGB, its
+    /// attributes.
+    pub fn describe(&self, gameboy: &mut Gameboy, col: usize, row: usize) -> Vec<String> {
+        let addr = self.map_addr() + (row * 32 + col) as u16;
+        let index = gameboy.read_vram(0, addr).unwrap();
+        let tile_addr = if gameboy.read8(LCDC) & 0x10 != 0 {
+            0x8000 + index as u16 * 16
+        } else {
+            (0x9000 + index as i8 as i32 * 16) as u16
+        };
+        let attributes = gameboy.read_vram(1, addr);
+        let bank = attributes.map_or(0, |attributes| (attributes >> 3) & 1);
+        let mut lines = vec![format!("X {:02} Y {:02}  {:04X}  INDEX {:02X}  TILE {}:{:04X}", col, row, addr, index, bank, tile_addr)];
+        if let Some(attributes) = attributes {
+            let mut line = format!("ATTR {:02X}  PAL {}  BANK {}", attributes, attributes & 0x7, bank);
+            for (bit, flag) in &[(5, "XFLIP"), (6, "YFLIP"), (7, "PRIORITY")] {
+                if attributes & (1 << bit) != 0 {
+                    line.push_str("  ");
+                    line.push_str(flag);
+                }
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    pub fn draw(&self, gameboy: &mut Gameboy, frame_buffer: &mut [Color]) {
+        let width = Self::WIDTH;
+        draw::fill_rect(frame_buffer, width, 0, 0, width, Self::HEIGHT, BACKGROUND);
+        let lcdc = gameboy.read8(LCDC);
+        let (scx, scy) = (gameboy.read8(SCX) as usize, gameboy.read8(SCY) as usize);
+        let (wx, wy) = (gameboy.read8(WX) as usize, gameboy.read8(WY) as usize);
+        let bg_map = (lcdc & 0x08 != 0) == self.high;
+        let window_map = (lcdc & 0x40 != 0) == self.high;
+        let mut header = format!("MAP {:04X}", self.map_addr());
+        if bg_map {
+            header.push_str(" BG");
+        }
+        if window_map {
+            header.push_str(" WIN");
+        }
+        header.push_str(&format!("  SCX {:02X} SCY {:02X}  WX {:02X} WY {:02X}", scx, scy, wx, wy));
+        draw::draw_text(frame_buffer, width, MARGIN, MARGIN, &header, TEXT);
+
+        gameboy.draw_map(self.high, &mut frame_buffer[MAP_Y * width + MARGIN..], width);
+
+        let mut plot = |x: usize, y: usize, color: Color| {
+            frame_buffer[(MAP_Y + y % MAP_SIZE) * width + MARGIN + x % MAP_SIZE] = color;
+        };
+        let window_on = lcdc & 0x20 != 0 && wx < GAME_WIDTH + 7 && wy < GAME_HEIGHT;
+        if window_on && window_map {
+            let (w, h) = (GAME_WIDTH + 7 - wx.max(7), GAME_HEIGHT - wy);
+            outline(&mut plot, 0, 0, w, h, WINDOW);
+        }
+        if bg_map {
+            outline(&mut plot, scx, scy, GAME_WIDTH, GAME_HEIGHT, VIEWPORT);
+            if window_on {
+                // Where the window's top left corner is on screen.
+                let (x, y) = (scx + wx + MAP_SIZE - 7, scy + wy);
+                for i in 0..5 {
+                    plot(x + i + MAP_SIZE - 2, y, WINDOW);
+                    plot(x, y + i + MAP_SIZE - 2, WINDOW);
+                }
+            }
+        }
+
+        if let Some((col, row)) = self.hover {
+            outline(&mut plot, col * 8, row * 8, 8, 8, HOVER);
+            for (i, line) in self.describe(gameboy, col, row).iter().enumerate() {
+                draw::draw_text(frame_buffer, width, MARGIN, STATUS_Y + i * GLYPH_HEIGHT, line, TEXT);
+            }
+        }
+    }
+}
+
+// Outlines a rectangle in map coordinates, wrapping around the edges.
+fn outline(plot: &mut impl FnMut(usize, usize, Color), x: usize, y: usize, w: usize, h: usize, color: Color) {
+    for i in 0..w {
+        plot(x + i, y, color);
+        plot(x + i, y + h - 1, color);
+    }
+    for j in 0..h {
+        plot(x, y + j, color);
+        plot(x + w - 1, y + j, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::idle_gameboy;
+
+    #[test]
+    fn test_map_viewer() {
+        let mut gameboy = idle_gameboy("map-viewer");
+        // Display on, tiles at 0x8000, background map at 0x9800.
+        gameboy.write8(LCDC, 0x91);
+        gameboy.write8(SCY, 4);
+        gameboy.write8(SCX, 250);
+        // Tile 1 has color 3 in its top left pixel and is drawn with
+        // palette 2, whose color 3 is red.
+        gameboy.write_vram(0, 0x8010, 0x80);
+        gameboy.write_vram(0, 0x8011, 0x80);
+        gameboy.write_vram(0, 0x9801, 0x01);
+        gameboy.write_vram(1, 0x9801, 0x02);
+        gameboy.write8(0xFF68, 0x80 | (2 * 8 + 3 * 2));
+        gameboy.write8(0xFF69, 0x1F);
+        gameboy.write8(0xFF69, 0x00);
+
+        let mut viewer = MapViewer::new(&mut gameboy);
+        assert_eq!(viewer.map_addr(), 0x9800);
+        assert_eq!(viewer.tile_at(MARGIN + 12, MAP_Y + 3), Some((1, 0)));
+        assert_eq!(viewer.describe(&mut gameboy, 1, 0), vec![
+            "X 01 Y 00  9801  INDEX 01  TILE 0:8010".to_string(),
+            "ATTR 02  PAL 2  BANK 0".to_string()
+        ]);
+
+        let mut frame_buffer = vec![Color::new(0, 0, 0); MapViewer::WIDTH * MapViewer::HEIGHT];
+        viewer.draw(&mut gameboy, &mut frame_buffer);
+        let pixel = |x: usize, y: usize| frame_buffer[(MAP_Y + y) * MapViewer::WIDTH + MARGIN + x];
+        let red = Color::new(31, 0, 0);
+        assert_eq!(pixel(8, 0), red);
+        assert_ne!(pixel(15, 0), red);
+        // The viewport wraps around from x 250 to 153.
+        assert_eq!(pixel(252, 4), VIEWPORT);
+        assert_eq!(pixel(100, 4), VIEWPORT);
+        assert_eq!(pixel(153, 50), VIEWPORT);
+        assert_ne!(pixel(154, 4), VIEWPORT);
+
+        viewer.toggle_map();
+        assert_eq!(viewer.map_addr(), 0x9C00);
+        assert_eq!(viewer.describe(&mut gameboy, 1, 0)[0], "X 01 Y 00  9C01  INDEX 00  TILE 0:8000");
+    }
+}
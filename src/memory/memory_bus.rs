@@ -1,39 +1,165 @@
 use super::memory_map::{MemoryMap, MemoryMappedDeviceManager, MemoryMappedDevice};
+use crate::cartridge::Symbols;
+use crate::cdl::CodeDataLog;
+use crate::gameboy::Color;
+use crate::interrupt_controller::Interrupt;
 
 const DMA: u16 = 0xFF46;
 const HDMA5: u16 = 0xFF55;
 
+/// A read or write made through the memory bus, see
+/// [`Gameboy::set_access_logging`](crate::Gameboy::set_access_logging).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MemoryAccess {
+    Read(u16),
+    Write(u16, u8)
+}
+
+// Where the devices ticked by the bus draw and play.
+struct Clock<'a> {
+    frame_buffer: &'a mut [Color],
+    audio_queue: &'a mut Vec<f32>,
+    vblank: bool
+}
+
+/// The CPU's view of memory. Each read and write the CPU makes through
+/// [`get8`](Self::get8) and [`set8`](Self::set8) takes an M-cycle, which
+/// first advances the other devices by one M-cycle when the bus has a
+/// clock, so they see the access at the end of its cycle.
 pub struct MemoryBus<'a> {
     memory_map: &'a MemoryMap,
-    devices: &'a mut MemoryMappedDeviceManager
+    devices: &'a mut MemoryMappedDeviceManager,
+    access_log: Option<&'a mut Vec<MemoryAccess>>,
+    cdl: Option<&'a mut CodeDataLog>,
+    clock: Option<Clock<'a>>,
+    cycles: u32
 }
 
 impl<'a> MemoryBus<'a> {
     pub fn new(memory_map: &'a MemoryMap, devices: &'a mut MemoryMappedDeviceManager) -> MemoryBus<'a> {
-        MemoryBus { memory_map, devices }
+        MemoryBus { memory_map, devices, access_log: None, cdl: None, clock: None, cycles: 0 }
+    }
+
+    /// A bus that records every read and write into `access_log`.
+    /// Instruction fetches aren't recorded.
+    pub fn with_access_log(memory_map: &'a MemoryMap, devices: &'a mut MemoryMappedDeviceManager,
+                           access_log: &'a mut Vec<MemoryAccess>) -> MemoryBus<'a> {
+        MemoryBus { memory_map, devices, access_log: Some(access_log), cdl: None, clock: None, cycles: 0 }
+    }
+
+    /// Makes every cycle advance the timer, LCD, serial port and sound,
+    /// drawing into `frame_buffer` and pushing samples into `audio_queue`.
+    /// Without a clock, accesses have no effect on the other devices.
+    pub fn set_clock(&mut self, frame_buffer: &'a mut [Color], audio_queue: &'a mut Vec<f32>) {
+        self.clock = Some(Clock { frame_buffer, audio_queue, vblank: false });
+    }
+
+    /// Whether the LCD entered VBlank since the clock was set.
+    pub fn vblank(&self) -> bool {
+        self.clock.as_ref().is_some_and(|clock| clock.vblank)
+    }
+
+    /// The M-cycles that have passed on this bus.
+    pub fn cycles(&self) -> u32 {
+        self.cycles
+    }
+
+    /// Takes an M-cycle, advancing the devices by it if the bus has a clock.
+    /// Interrupts they raise are requested straight away.
+    pub fn tick(&mut self) {
+        self.cycles += 1;
+        if self.clock.is_none() {
+            return;
+        }
+
+        if let Some(source) = self.devices.lcd_controller().oam_dma_source() {
+            let byte = self.dma_get8(source);
+            self.devices.lcd_controller().oam_dma_copy(byte);
+        }
+
+        let clock = self.clock.as_mut().unwrap();
+        let mut interrupts = Vec::new();
+        let mut fire_interrupt = |interrupt| interrupts.push(interrupt);
+        self.devices.timer().tick(1, &mut fire_interrupt);
+        let dma_executor = self.devices.lcd_controller().tick(1, clock.frame_buffer, &mut fire_interrupt);
+        self.devices.serial_controller().tick(1, &mut fire_interrupt);
+        self.devices.sound_controller().tick(1, clock.audio_queue);
+
+        clock.vblank |= interrupts.contains(&Interrupt::VBlank);
+        for interrupt in interrupts {
+            self.devices.interrupt_controller().request(interrupt);
+        }
+        if let Some(dma_executor) = dma_executor {
+            dma_executor.execute(self);
+        }
+    }
+
+    /// Records the reads and instruction fetches made through the bus in `cdl`.
+    pub fn set_cdl(&mut self, cdl: &'a mut CodeDataLog) {
+        self.cdl = Some(cdl);
+    }
+
+    fn mark(&mut self, addr: u16, flag: u8) {
+        if let Some(cdl) = self.cdl.as_mut() {
+            cdl.mark(self.devices, addr, flag);
+        }
+    }
+
+    /// Marks the `len` bytes of the instruction at `addr` as executed.
+    pub fn mark_executed(&mut self, addr: u16, len: u16) {
+        if self.cdl.is_some() {
+            self.mark(addr, CodeDataLog::EXEC_FIRST);
+            for i in 1..len {
+                self.mark(addr.wrapping_add(i), CodeDataLog::EXEC_OPERAND);
+            }
+        }
+    }
+
+    fn log(&mut self, access: MemoryAccess) {
+        if let Some(access_log) = self.access_log.as_mut() {
+            access_log.push(access);
+        }
     }
 
     pub fn devices(&mut self) -> &mut MemoryMappedDeviceManager {
         self.devices
     }
 
-    fn get_device(&mut self, addr: u16) -> &mut MemoryMappedDevice {
+    fn get_device(&mut self, addr: u16) -> &mut dyn MemoryMappedDevice {
         self.devices.get(self.memory_map.get_id(addr))
     }
 
-    pub fn get_sym(&self, addr: u16) -> Option<&String> {
-        self.memory_map.get_sym(addr)
+    pub fn symbols(&self) -> Option<&'a Symbols> {
+        self.memory_map.symbols()
     }
 
+    pub fn get_sym(&mut self, addr: u16) -> Option<&'a str> {
+        let bank = self.devices.bank(addr);
+        self.memory_map.get_sym(bank, addr)
+    }
+
+    // While an OAM DMA runs, the CPU can only reach HRAM. Buses without a
+    // clock aren't the CPU's, so they can still reach everything.
+    fn blocked(&mut self, addr: u16) -> bool {
+        self.clock.is_some() && !matches!(addr, 0xFF80 ..= 0xFFFE) &&
+            self.devices.lcd_controller().oam_dma_running()
+    }
+
+    /// Writes a byte at the end of an M-cycle. Writes outside HRAM are
+    /// dropped while an OAM DMA runs.
     pub fn set8(&mut self, addr: u16, byte: u8) {
+        let blocked = self.blocked(addr);
+        self.tick();
+        if !blocked {
+            self.write(addr, byte);
+        }
+    }
+
+    fn write(&mut self, addr: u16, byte: u8) {
+        self.log(MemoryAccess::Write(addr, byte));
         match addr {
             DMA => {
-                let source = (byte as u16) << 8;
-                let mut data = [0; 0xA0];
-                for i in 0..0xA0 {
-                    data[i] = self.get8(source + i as u16);
-                }
-                self.devices.lcd_controller().dma(&data);
+                self.devices.lcd_controller().oam_dma((byte as u16) << 8);
             }
             HDMA5 => {
                 let len = (((byte & 0x7F) as u16) + 1) << 4;
@@ -43,7 +169,7 @@ impl<'a> MemoryBus<'a> {
                     let source = self.devices.lcd_controller().vram_dma_source();
                     let mut data = Vec::with_capacity(len as usize);
                     for i in 0..len {
-                        data.push(self.get8(source + i as u16));
+                        data.push(self.dma_get8(source + i));
                     }
                     self.devices.lcd_controller().vram_dma(&data);
                 }
@@ -54,17 +180,41 @@ impl<'a> MemoryBus<'a> {
         }
     }
 
+    /// Writes the high byte of `v` at `addr` and then the low byte after it.
     pub fn set16(&mut self, addr: u16, v: u16) {
-        let device = self.get_device(addr);
-        device.set8(addr, (v >> 8) as u8);
-        device.set8(addr + 1, (v & 0xFF) as u8);
+        self.set8(addr, (v >> 8) as u8);
+        self.set8(addr.wrapping_add(1), (v & 0xFF) as u8);
     }
 
+    /// Reads a byte at the end of an M-cycle. Reads outside HRAM give 0xFF
+    /// while an OAM DMA runs.
     pub fn get8(&mut self, addr: u16) -> u8 {
+        let blocked = self.blocked(addr);
+        self.tick();
+        if blocked {
+            return 0xFF;
+        }
+        self.log(MemoryAccess::Read(addr));
+        self.mark(addr, CodeDataLog::DATA);
         self.get_device(addr).get8(addr)
     }
 
+    /// Reads a byte for a DMA transfer, which takes none of the CPU's cycles.
+    pub fn dma_get8(&mut self, addr: u16) -> u8 {
+        self.log(MemoryAccess::Read(addr));
+        self.mark(addr, CodeDataLog::DMA);
+        self.get_device(addr).get8(addr)
+    }
+
+    /// Writes a byte for a DMA transfer.
+    pub fn dma_set8(&mut self, addr: u16, byte: u8) {
+        self.write(addr, byte);
+    }
+
+    /// Peeks at three bytes without taking any cycles, for decoding an
+    /// instruction.
     pub fn get_arr3(&mut self, addr: u16) -> [u8; 3] {
-        [self.get8(addr), self.get8(addr + 1), self.get8(addr + 2)]
+        let mut fetch = |addr: u16| if self.blocked(addr) { 0xFF } else { self.get_device(addr).get8(addr) };
+        [fetch(addr), fetch(addr.wrapping_add(1)), fetch(addr.wrapping_add(2))]
     }
 }
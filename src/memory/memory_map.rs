@@ -1,4 +1,3 @@
-use crate::gameboy::Mode;
 use crate::cartridge::Cartridge;
 use crate::ram_device::RamDevice;
 use crate::joypad_controller::JoypadController;
@@ -8,6 +7,7 @@ use crate::serial::SerialController;
 use crate::lcd::LcdController;
 use crate::sound::SoundController;
 use crate::cartridge::Symbols;
+use crate::save_state::{self, SaveState, SaveStateError, StateReader, StateWriter};
 
 const MEMORY_SIZE: usize = 0x10000;
 
@@ -52,17 +52,21 @@ impl MemoryMap {
         self.symbols = symbols;
     }
 
-    pub fn get_sym(&self, addr: u16) -> Option<&String> {
+    pub fn symbols(&self) -> Option<&Symbols> {
+        self.symbols.as_ref()
+    }
+
+    pub fn get_sym(&self, bank: usize, addr: u16) -> Option<&str> {
         match &self.symbols {
             None => None,
-            Some(c) => c.get(addr as usize)
+            Some(c) => c.get(bank, addr)
         }
     }
 
     pub fn register(&mut self, id: MemoryMappedDeviceId, mapped_areas: &[MappedArea]) {
         for area in mapped_areas {
             let start = area.0 as usize;
-            for i in start..(start as usize)+area.1 {
+            for i in start..start+area.1 {
                 self.memory_map[i] = Some(id);
             }
         }
@@ -239,7 +243,44 @@ impl MemoryMappedDeviceManager {
         }
     }
 
-    pub fn get(&mut self, id: MemoryMappedDeviceId) -> &mut MemoryMappedDevice {
+    /// The bank currently mapped at `addr`, as numbered in symbol files.
+    pub fn bank(&mut self, addr: u16) -> usize {
+        match addr {
+            0x4000 ..= 0x7FFF => self.cartridge().rom_bank(),
+            0xA000 ..= 0xBFFF => self.cartridge().ram_bank(),
+            0xD000 ..= 0xDFFF => self.ram_bank1().bank(),
+            _ => 0
+        }
+    }
+
+    /// A copy of every WRAM, cartridge RAM and HRAM bank, as `(bank, start
+    /// address, bytes)`.
+    pub fn ram_banks(&mut self) -> Vec<(usize, u16, Vec<u8>)> {
+        let mut banks = vec![(0, 0xC000, self.ram_bank0().memory().to_vec())];
+        for (i, chunk) in self.ram_bank1().memory().chunks(0x1000).enumerate() {
+            banks.push((i + 1, 0xD000, chunk.to_vec()));
+        }
+        for (i, chunk) in self.cartridge().ram().chunks(0x2000).enumerate() {
+            banks.push((i, 0xA000, chunk.to_vec()));
+        }
+        banks.push((0, 0xFF80, self.hram().memory()[..0x7F].to_vec()));
+        banks
+    }
+
+    /// The byte at `addr` in `bank` of WRAM, cartridge RAM or HRAM, whether
+    /// or not that bank is mapped.
+    pub fn banked_ram(&mut self, bank: usize, addr: u16) -> Option<&mut u8> {
+        let (memory, index) = match addr {
+            0xA000 ..= 0xBFFF => (self.cartridge().ram(), bank * 0x2000 + addr as usize - 0xA000),
+            0xC000 ..= 0xCFFF => (self.ram_bank0().memory(), addr as usize - 0xC000),
+            0xD000 ..= 0xDFFF => (self.ram_bank1().memory(), (bank.max(1) - 1) * 0x1000 + addr as usize - 0xD000),
+            0xFF80 ..= 0xFFFE => (self.hram().memory(), addr as usize - 0xFF80),
+            _ => return None
+        };
+        memory.get_mut(index)
+    }
+
+    pub fn get(&mut self, id: MemoryMappedDeviceId) -> &mut dyn MemoryMappedDevice {
         match id {
             Cartridge => self.cartridge(),
             RAMBank0 => self.ram_bank0(),
@@ -255,3 +296,47 @@ impl MemoryMappedDeviceManager {
         }
     }
 }
+
+fn save_device<T: SaveState>(device: &Option<T>, w: &mut StateWriter) {
+    w.write_bool(device.is_some());
+    if let Some(device) = device {
+        device.save_state(w);
+    }
+}
+
+fn load_device<T: SaveState>(device: &mut Option<T>, r: &mut StateReader) -> save_state::Result<()> {
+    let present = r.read_bool()?;
+    match device {
+        Some(device) if present => device.load_state(r),
+        None if !present => Ok(()),
+        _ => Err(SaveStateError::Invalid("registered devices"))
+    }
+}
+
+impl SaveState for MemoryMappedDeviceManager {
+    fn save_state(&self, w: &mut StateWriter) {
+        save_device(&self.cartridge, w);
+        save_device(&self.ram_bank0, w);
+        save_device(&self.ram_bank1, w);
+        save_device(&self.interrupt_controller, w);
+        save_device(&self.joypad_controller, w);
+        save_device(&self.timer, w);
+        save_device(&self.lcd_controller, w);
+        save_device(&self.sound_controller, w);
+        save_device(&self.serial_controller, w);
+        save_device(&self.hram, w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        load_device(&mut self.cartridge, r)?;
+        load_device(&mut self.ram_bank0, r)?;
+        load_device(&mut self.ram_bank1, r)?;
+        load_device(&mut self.interrupt_controller, r)?;
+        load_device(&mut self.joypad_controller, r)?;
+        load_device(&mut self.timer, r)?;
+        load_device(&mut self.lcd_controller, r)?;
+        load_device(&mut self.sound_controller, r)?;
+        load_device(&mut self.serial_controller, r)?;
+        load_device(&mut self.hram, r)
+    }
+}
@@ -0,0 +1,347 @@
+use crate::draw::{self, BACKGROUND, GLYPH_HEIGHT, GLYPH_WIDTH, MARGIN, TEXT};
+use crate::gameboy::{Color, Gameboy};
+
+const COLUMNS: usize = 16;
+const ROWS: usize = 16;
+// Address, a space and three characters a byte, less the last space.
+const LINE_CHARS: usize = 4 + 1 + COLUMNS * 3 - 1;
+// Where the first row of bytes starts, below the header.
+const ROWS_Y: usize = MARGIN + GLYPH_HEIGHT + 2;
+
+// Colors have 5 bits a channel, like the LCD's.
+const ADDRESS: Color = Color { r: 14, g: 16, b: 20 };
+const CHANGED: Color = Color { r: 31, g: 10, b: 8 };
+const CURSOR: Color = Color { r: 8, g: 12, b: 24 };
+
+/// An area of memory shown by the [`MemoryViewer`]. Every bank but the
+/// bus can be shown whether or not it is mapped.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MemoryRegion {
+    /// All 64K, as read and written by the CPU.
+    Bus,
+    Rom(usize),
+    Vram(usize),
+    Wram(usize),
+    Sram(usize)
+}
+
+impl MemoryRegion {
+    // The CPU address of the first byte.
+    fn base(&self) -> u16 {
+        match self {
+            MemoryRegion::Bus | MemoryRegion::Rom(0) => 0x0000,
+            MemoryRegion::Rom(_) => 0x4000,
+            MemoryRegion::Vram(_) => 0x8000,
+            MemoryRegion::Sram(_) => 0xA000,
+            MemoryRegion::Wram(0) => 0xC000,
+            MemoryRegion::Wram(_) => 0xD000
+        }
+    }
+
+    fn len(&self, gameboy: &mut Gameboy) -> usize {
+        match *self {
+            MemoryRegion::Bus => 0x10000,
+            MemoryRegion::Rom(_) => 0x4000,
+            MemoryRegion::Vram(_) => 0x2000,
+            MemoryRegion::Wram(_) => 0x1000,
+            MemoryRegion::Sram(bank) => gameboy.memory_sizes().2.saturating_sub(bank * 0x2000).min(0x2000)
+        }
+    }
+
+    // How many banks of this kind there are.
+    fn banks(&self, gameboy: &mut Gameboy) -> usize {
+        let (rom, wram, sram) = gameboy.memory_sizes();
+        match self {
+            MemoryRegion::Bus => 1,
+            MemoryRegion::Rom(_) => rom.div_ceil(0x4000),
+            MemoryRegion::Vram(_) => if gameboy.mode().is_cgb() { 2 } else { 1 },
+            MemoryRegion::Wram(_) => wram / 0x1000,
+            MemoryRegion::Sram(_) => sram.div_ceil(0x2000)
+        }
+    }
+
+    fn with_bank(&self, bank: usize) -> MemoryRegion {
+        match self {
+            MemoryRegion::Bus => MemoryRegion::Bus,
+            MemoryRegion::Rom(_) => MemoryRegion::Rom(bank),
+            MemoryRegion::Vram(_) => MemoryRegion::Vram(bank),
+            MemoryRegion::Wram(_) => MemoryRegion::Wram(bank),
+            MemoryRegion::Sram(_) => MemoryRegion::Sram(bank)
+        }
+    }
+
+    fn bank(&self) -> usize {
+        match *self {
+            MemoryRegion::Bus => 0,
+            MemoryRegion::Rom(bank) | MemoryRegion::Vram(bank) | MemoryRegion::Wram(bank) | MemoryRegion::Sram(bank) => bank
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            MemoryRegion::Bus => "BUS".to_string(),
+            MemoryRegion::Rom(bank) => format!("ROM {:02X}", bank),
+            MemoryRegion::Vram(bank) => format!("VRAM {}", bank),
+            MemoryRegion::Wram(bank) => format!("WRAM {}", bank),
+            MemoryRegion::Sram(bank) => format!("SRAM {:02X}", bank)
+        }
+    }
+
+    fn read(&self, gameboy: &mut Gameboy, offset: usize) -> Option<u8> {
+        let addr = self.base().wrapping_add(offset as u16);
+        match *self {
+            MemoryRegion::Bus => Some(gameboy.read8(addr)),
+            MemoryRegion::Rom(bank) => gameboy.read_rom(bank * 0x4000 + offset),
+            MemoryRegion::Vram(bank) => gameboy.read_vram(bank, addr),
+            MemoryRegion::Wram(bank) | MemoryRegion::Sram(bank) => gameboy.read_banked(bank, addr)
+        }
+    }
+
+    fn write(&self, gameboy: &mut Gameboy, offset: usize, byte: u8) {
+        let addr = self.base().wrapping_add(offset as u16);
+        match *self {
+            MemoryRegion::Bus => gameboy.write8(addr, byte),
+            MemoryRegion::Rom(bank) => gameboy.write_rom(bank * 0x4000 + offset, byte),
+            MemoryRegion::Vram(bank) => gameboy.write_vram(bank, addr, byte),
+            MemoryRegion::Wram(bank) | MemoryRegion::Sram(bank) => gameboy.write_banked(bank, addr, byte)
+        }
+    }
+}
+
+/// A hex view of memory that follows the running game, highlighting the
+/// bytes that changed since the last frame, and lets the byte under the
+/// cursor be typed over.
+pub struct MemoryViewer {
+    region: MemoryRegion,
+    cursor: usize,
+    // The offset of the first byte shown.
+    top: usize,
+    // The high nibble typed so far.
+    pending: Option<u8>,
+    // The region and top that `bytes` were read from.
+    shown: Option<(MemoryRegion, usize)>,
+    bytes: Vec<Option<u8>>,
+    changed: Vec<bool>
+}
+
+impl Default for MemoryViewer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryViewer {
+    pub const WIDTH: usize = LINE_CHARS * GLYPH_WIDTH + 2 * MARGIN;
+    pub const HEIGHT: usize = ROWS_Y + ROWS * GLYPH_HEIGHT + MARGIN;
+
+    pub fn new() -> Self {
+        MemoryViewer {
+            region: MemoryRegion::Bus,
+            cursor: 0,
+            top: 0,
+            pending: None,
+            shown: None,
+            bytes: Vec::new(),
+            changed: Vec::new()
+        }
+    }
+
+    pub fn region(&self) -> MemoryRegion {
+        self.region
+    }
+
+    /// The offset of the cursor in the region.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Shows `region`, or its last bank when there is no such bank.
+    pub fn set_region(&mut self, gameboy: &mut Gameboy, region: MemoryRegion) {
+        let last = region.banks(gameboy).saturating_sub(1);
+        self.region = region.with_bank(region.bank().min(last));
+        self.move_cursor(gameboy, 0);
+    }
+
+    /// Switches between the bus, ROM, VRAM, WRAM and SRAM, skipping SRAM
+    /// when the cartridge has none.
+    pub fn next_region(&mut self, gameboy: &mut Gameboy) {
+        let next = match self.region {
+            MemoryRegion::Bus => MemoryRegion::Rom(0),
+            MemoryRegion::Rom(_) => MemoryRegion::Vram(0),
+            MemoryRegion::Vram(_) => MemoryRegion::Wram(0),
+            MemoryRegion::Wram(_) if MemoryRegion::Sram(0).banks(gameboy) > 0 => MemoryRegion::Sram(0),
+            MemoryRegion::Wram(_) | MemoryRegion::Sram(_) => MemoryRegion::Bus
+        };
+        self.set_region(gameboy, next);
+    }
+
+    /// Shows the bank `delta` away from the current one, wrapping around.
+    pub fn change_bank(&mut self, gameboy: &mut Gameboy, delta: isize) {
+        let banks = self.region.banks(gameboy).max(1) as isize;
+        let bank = (self.region.bank() as isize + delta).rem_euclid(banks);
+        self.set_region(gameboy, self.region.with_bank(bank as usize));
+    }
+
+    /// Moves the cursor by `delta` bytes, stopping at either end, and
+    /// scrolls to keep it in view. A half typed byte is dropped.
+    pub fn move_cursor(&mut self, gameboy: &mut Gameboy, delta: isize) {
+        let last = self.region.len(gameboy) as isize - 1;
+        self.cursor = (self.cursor as isize + delta).clamp(0, last.max(0)) as usize;
+        self.pending = None;
+        let row = self.cursor / COLUMNS * COLUMNS;
+        if row < self.top {
+            self.top = row;
+        } else if row >= self.top + ROWS * COLUMNS {
+            self.top = row + COLUMNS - ROWS * COLUMNS;
+        }
+        self.top = self.top.min((last.max(0) as usize / COLUMNS).saturating_sub(ROWS - 1) * COLUMNS);
+        self.reload(gameboy);
+    }
+
+    /// Types a hex digit over the byte under the cursor. The byte is written
+    /// once both digits are in, and the cursor moves on to the next.
+    pub fn type_digit(&mut self, gameboy: &mut Gameboy, digit: u8) {
+        match self.pending.take() {
+            None => self.pending = Some(digit & 0xF),
+            Some(high) => {
+                self.region.write(gameboy, self.cursor, (high << 4) | (digit & 0xF));
+                self.move_cursor(gameboy, 1);
+            }
+        }
+    }
+
+    /// Drops a half typed byte.
+    pub fn cancel_edit(&mut self) {
+        self.pending = None;
+    }
+
+    fn read(&self, gameboy: &mut Gameboy) -> Vec<Option<u8>> {
+        let len = self.region.len(gameboy);
+        (self.top..(self.top + ROWS * COLUMNS).min(len))
+            .map(|offset| self.region.read(gameboy, offset))
+            .collect()
+    }
+
+    /// Reads the bytes on screen, flagging the ones that changed since the
+    /// last refresh. Call once a frame.
+    pub fn refresh(&mut self, gameboy: &mut Gameboy) {
+        let bytes = self.read(gameboy);
+        self.changed = if self.shown == Some((self.region, self.top)) {
+            bytes.iter().zip(&self.bytes).map(|(now, before)| now != before).collect()
+        } else {
+            vec![false; bytes.len()]
+        };
+        self.bytes = bytes;
+        self.shown = Some((self.region, self.top));
+    }
+
+    // Rereads the bytes on screen after an edit or a move, keeping the
+    // highlights unless the view scrolled.
+    fn reload(&mut self, gameboy: &mut Gameboy) {
+        if self.shown == Some((self.region, self.top)) {
+            self.bytes = self.read(gameboy);
+        } else {
+            self.refresh(gameboy);
+        }
+    }
+
+    /// Draws the view into a frame buffer `WIDTH` by `HEIGHT` pixels.
+    pub fn draw(&self, frame_buffer: &mut [Color]) {
+        let width = Self::WIDTH;
+        draw::fill_rect(frame_buffer, width, 0, 0, width, Self::HEIGHT, BACKGROUND);
+        let cursor_addr = self.region.base().wrapping_add(self.cursor as u16);
+        let header = format!("{}  {:04X}", self.region.name(), cursor_addr);
+        draw::draw_text(frame_buffer, width, MARGIN, MARGIN, &header, TEXT);
+
+        for (row, line) in self.bytes.chunks(COLUMNS).enumerate() {
+            let y = ROWS_Y + row * GLYPH_HEIGHT;
+            let addr = self.region.base().wrapping_add((self.top + row * COLUMNS) as u16);
+            draw::draw_text(frame_buffer, width, MARGIN, y, &format!("{:04X}", addr), ADDRESS);
+            for (col, byte) in line.iter().enumerate() {
+                let i = row * COLUMNS + col;
+                let x = MARGIN + (5 + col * 3) * GLYPH_WIDTH;
+                let text = match (self.top + i == self.cursor, self.pending, byte) {
+                    (true, Some(high), _) => format!("{:X}_", high),
+                    (_, _, Some(byte)) => format!("{:02X}", byte),
+                    (_, _, None) => "--".to_string()
+                };
+                if self.top + i == self.cursor {
+                    draw::fill_rect(frame_buffer, width, x - 1, y - 1, 2 * GLYPH_WIDTH + 1, GLYPH_HEIGHT, CURSOR);
+                }
+                let color = if self.changed.get(i).copied().unwrap_or(false) { CHANGED } else { TEXT };
+                draw::draw_text(frame_buffer, width, x, y, &text, color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::idle_gameboy;
+
+    #[test]
+    fn test_edit_and_highlight() {
+        let mut gameboy = idle_gameboy("memory-viewer");
+        let mut viewer = MemoryViewer::new();
+        viewer.move_cursor(&mut gameboy, 0xC000);
+        viewer.refresh(&mut gameboy);
+        viewer.type_digit(&mut gameboy, 0x1);
+        assert_eq!(gameboy.read8(0xC000), 0);
+        viewer.type_digit(&mut gameboy, 0xA);
+        assert_eq!(gameboy.read8(0xC000), 0x1A);
+        assert_eq!(viewer.cursor(), 0xC001);
+
+        // Only the game's writes are highlighted, not the ones typed in.
+        gameboy.write8(0xC008, 0x55);
+        viewer.refresh(&mut gameboy);
+        let changed: Vec<usize> = (0..viewer.changed.len()).filter(|i| viewer.changed[*i]).collect();
+        assert_eq!(changed, vec![0xC008 - viewer.top]);
+        let mut frame_buffer = vec![Color::new(0, 0, 0); MemoryViewer::WIDTH * MemoryViewer::HEIGHT];
+        viewer.draw(&mut frame_buffer);
+        assert!(frame_buffer.contains(&CHANGED) && frame_buffer.contains(&CURSOR));
+        viewer.refresh(&mut gameboy);
+        assert!(viewer.changed.iter().all(|changed| !changed));
+    }
+
+    #[test]
+    fn test_banks() {
+        let mut gameboy = idle_gameboy("memory-viewer");
+        let mut viewer = MemoryViewer::new();
+        viewer.next_region(&mut gameboy);
+        assert_eq!(viewer.region(), MemoryRegion::Rom(0));
+        viewer.move_cursor(&mut gameboy, 0x150);
+        viewer.type_digit(&mut gameboy, 0xC);
+        viewer.type_digit(&mut gameboy, 0x9);
+        assert_eq!(gameboy.read8(0x150), 0xC9);
+
+        viewer.next_region(&mut gameboy);
+        viewer.change_bank(&mut gameboy, -1);
+        assert_eq!(viewer.region(), MemoryRegion::Vram(1));
+        viewer.type_digit(&mut gameboy, 0x4);
+        viewer.type_digit(&mut gameboy, 0x2);
+        assert_eq!(gameboy.read_vram(1, 0x8151), Some(0x42));
+        assert_eq!(gameboy.read8(0x8151), 0);
+
+        viewer.next_region(&mut gameboy);
+        viewer.change_bank(&mut gameboy, 3);
+        assert_eq!(viewer.region(), MemoryRegion::Wram(3));
+        assert_eq!(viewer.cursor(), 0x152);
+        // The cursor stops at the end of the bank.
+        viewer.move_cursor(&mut gameboy, 0x10000);
+        assert_eq!(viewer.cursor(), 0xFFF);
+        viewer.type_digit(&mut gameboy, 0x7);
+        viewer.type_digit(&mut gameboy, 0x7);
+        assert_eq!(gameboy.read_banked(3, 0xDFFF), Some(0x77));
+
+        // The test cartridge has no SRAM.
+        viewer.next_region(&mut gameboy);
+        assert_eq!(viewer.region(), MemoryRegion::Bus);
+        viewer.set_region(&mut gameboy, MemoryRegion::Sram(1));
+        assert_eq!(viewer.region(), MemoryRegion::Sram(0));
+        viewer.change_bank(&mut gameboy, 1);
+        assert_eq!(viewer.cursor(), 0);
+        viewer.refresh(&mut gameboy);
+        viewer.draw(&mut vec![Color::new(0, 0, 0); MemoryViewer::WIDTH * MemoryViewer::HEIGHT]);
+    }
+}
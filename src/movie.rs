@@ -0,0 +1,233 @@
+use std::convert::TryInto;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::cartridge::Cartridge;
+use crate::gameboy::{Color, JoypadInput, Mode};
+
+const MAGIC: &[u8; 4] = b"GBMV";
+const VERSION: u8 = 1;
+
+const BUTTONS: [JoypadInput; 8] = [
+    JoypadInput::Up, JoypadInput::Down, JoypadInput::Left, JoypadInput::Right,
+    JoypadInput::Start, JoypadInput::Select, JoypadInput::A, JoypadInput::B
+];
+
+#[derive(Debug)]
+pub enum MovieError {
+    Io(io::Error),
+    Format(&'static str),
+    CartridgeMismatch
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::Io(e) => write!(f, "{}", e),
+            MovieError::Format(what) => write!(f, "bad movie file: {}", what),
+            MovieError::CartridgeMismatch => write!(f, "movie was recorded with a different cartridge")
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<io::Error> for MovieError {
+    fn from(e: io::Error) -> Self {
+        MovieError::Io(e)
+    }
+}
+
+/// The inputs for every frame of a run, along with everything needed to
+/// start the run the same way again: the cartridge checksums, the mode, the
+/// boot options and the starting cartridge RAM.
+///
+/// Frame `n` holds the buttons passed to the `n`th [`Gameboy::tick`](crate::Gameboy::tick).
+#[derive(Debug, PartialEq)]
+pub struct Movie {
+    header_checksum: u8,
+    global_checksum: u16,
+    cgb: bool,
+    skip_boot_rom: bool,
+    sram: Vec<u8>,
+    final_frame_hash: Option<u64>,
+    frames: Vec<u8>
+}
+
+impl Movie {
+    /// Starts an empty movie for `cartridge`. Call before booting, so the
+    /// cartridge RAM is the one the run starts with.
+    pub fn new(cartridge: &Cartridge, mode: Mode, skip_boot_rom: bool) -> Self {
+        Movie {
+            header_checksum: cartridge.header_checksum(),
+            global_checksum: cartridge.global_checksum(),
+            cgb: mode.is_cgb(),
+            skip_boot_rom,
+            sram: cartridge.dump_ram(),
+            final_frame_hash: None,
+            frames: Vec::new()
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        if self.cgb { Mode::CGB } else { Mode::DMG }
+    }
+
+    pub fn skip_boot_rom(&self) -> bool {
+        self.skip_boot_rom
+    }
+
+    /// Checks that `cartridge` is the one the movie was recorded with and
+    /// restores the cartridge RAM the recording started with.
+    pub fn prepare(&self, cartridge: &mut Cartridge) -> Result<(), MovieError> {
+        if cartridge.header_checksum() != self.header_checksum ||
+            cartridge.global_checksum() != self.global_checksum {
+            return Err(MovieError::CartridgeMismatch);
+        }
+        cartridge.load_ram(&self.sram);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn record_frame(&mut self, pressed_inputs: &[JoypadInput]) {
+        let mask = BUTTONS.iter().enumerate()
+            .filter(|(_, b)| pressed_inputs.contains(b))
+            .fold(0, |mask, (i, _)| mask | (1 << i));
+        self.frames.push(mask);
+    }
+
+    /// The buttons held during `frame`, or `None` past the end of the movie.
+    pub fn frame(&self, frame: usize) -> Option<Vec<JoypadInput>> {
+        self.frames.get(frame).map(|mask| {
+            BUTTONS.iter().enumerate()
+                .filter(|(i, _)| mask & (1 << i) != 0)
+                .map(|(_, b)| b.clone())
+                .collect()
+        })
+    }
+
+    /// The hash of the last frame, if it was stored when recording ended.
+    pub fn final_frame_hash(&self) -> Option<u64> {
+        self.final_frame_hash
+    }
+
+    pub fn set_final_frame_hash(&mut self, hash: u64) {
+        self.final_frame_hash = Some(hash);
+    }
+
+    pub fn read(path: &Path) -> Result<Self, MovieError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(32 + self.sram.len() + self.frames.len());
+        data.extend_from_slice(MAGIC);
+        data.push(VERSION);
+        data.push(self.header_checksum);
+        data.extend_from_slice(&self.global_checksum.to_le_bytes());
+        data.push(self.cgb as u8);
+        data.push(self.skip_boot_rom as u8);
+        data.extend_from_slice(&(self.sram.len() as u32).to_le_bytes());
+        data.extend_from_slice(&self.sram);
+        data.push(self.final_frame_hash.is_some() as u8);
+        data.extend_from_slice(&self.final_frame_hash.unwrap_or(0).to_le_bytes());
+        data.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        data.extend_from_slice(&self.frames);
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, MovieError> {
+        let mut pos = 0;
+        let mut take = |n: usize| -> Result<&[u8], MovieError> {
+            let bytes = data.get(pos..pos + n).ok_or(MovieError::Format("truncated"))?;
+            pos += n;
+            Ok(bytes)
+        };
+
+        if take(4)? != MAGIC {
+            return Err(MovieError::Format("not a movie"));
+        }
+        if take(1)?[0] != VERSION {
+            return Err(MovieError::Format("unsupported version"));
+        }
+        let header_checksum = take(1)?[0];
+        let global_checksum = u16::from_le_bytes(take(2)?.try_into().unwrap());
+        let cgb = take(1)?[0] != 0;
+        let skip_boot_rom = take(1)?[0] != 0;
+        let sram_len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+        let sram = take(sram_len)?.to_vec();
+        let has_hash = take(1)?[0] != 0;
+        let hash = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let frame_count = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+        let frames = take(frame_count)?.to_vec();
+
+        Ok(Movie {
+            header_checksum, global_checksum, cgb, skip_boot_rom, sram,
+            final_frame_hash: if has_hash { Some(hash) } else { None },
+            frames
+        })
+    }
+}
+
+/// A 64 bit FNV-1a hash of a frame, stable across platforms and builds.
+pub fn frame_hash(frame_buffer: &[Color]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for color in frame_buffer {
+        for v in &[color.r, color.g, color.b] {
+            hash ^= *v as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn movie() -> Movie {
+        Movie {
+            header_checksum: 0x3A,
+            global_checksum: 0xBEEF,
+            cgb: true,
+            skip_boot_rom: false,
+            sram: vec![1, 2, 3],
+            final_frame_hash: None,
+            frames: Vec::new()
+        }
+    }
+
+    #[test]
+    fn test_record_frame() {
+        let mut movie = movie();
+        movie.record_frame(&[]);
+        movie.record_frame(&[JoypadInput::B, JoypadInput::Up]);
+        assert_eq!(movie.len(), 2);
+        assert_eq!(movie.frame(0), Some(vec![]));
+        assert_eq!(movie.frame(1), Some(vec![JoypadInput::Up, JoypadInput::B]));
+        assert_eq!(movie.frame(2), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut movie = movie();
+        movie.record_frame(&[JoypadInput::Start]);
+        movie.set_final_frame_hash(0x1234_5678_9ABC_DEF0);
+        let data = movie.to_bytes();
+        assert_eq!(Movie::from_bytes(&data).unwrap(), movie);
+        assert!(Movie::from_bytes(&data[..data.len() - 1]).is_err());
+        assert!(Movie::from_bytes(b"GBSS").is_err());
+    }
+}
@@ -0,0 +1,178 @@
+use crate::draw::{self, BACKGROUND, GLYPH_HEIGHT, GLYPH_WIDTH, MARGIN, TEXT};
+use crate::gameboy::{Color, Gameboy, GAME_HEIGHT};
+
+const COLUMNS: usize = 4;
+const ROWS: usize = 10;
+const GRID_Y: usize = MARGIN + GLYPH_HEIGHT + 2;
+// Each cell is the thumbnail and two lines of text beside it.
+const TEXT_X: usize = 8 + 3;
+const CELL_WIDTH: usize = TEXT_X + 22 * GLYPH_WIDTH + 4;
+const CELL_HEIGHT: usize = 16 + 2;
+
+const LY: u16 = 0xFF44;
+
+const HIDDEN: Color = Color { r: 12, g: 12, b: 12 };
+const THUMBNAIL: Color = Color { r: 6, g: 6, b: 8 };
+const ON_LINE: Color = Color { r: 3, g: 5, b: 12 };
+const DROPPED: Color = Color { r: 31, g: 10, b: 8 };
+
+/// Lists the 40 sprites in OAM with their attributes and how they look at
+/// the current sprite size. Sprites the ten per line limit keeps from
+/// being drawn are shown in red, either on one chosen line or on any line
+/// of the screen. Sprites on the chosen line that are drawn are shown in
+/// blue, and sprites off the screen are greyed out.
+pub struct OamViewer {
+    // The line to check, or None for all of them.
+    line: Option<u8>
+}
+
+impl OamViewer {
+    pub const WIDTH: usize = 2 * MARGIN + COLUMNS * CELL_WIDTH;
+    pub const HEIGHT: usize = GRID_Y + ROWS * CELL_HEIGHT + MARGIN;
+
+    pub fn new() -> Self {
+        OamViewer { line: None }
+    }
+
+    pub fn line(&self) -> Option<u8> {
+        self.line
+    }
+
+    pub fn set_line(&mut self, line: Option<u8>) {
+        self.line = line;
+    }
+
+    /// Steps through all lines and then each visible line in turn.
+    pub fn step_line(&mut self, delta: isize) {
+        let count = GAME_HEIGHT as isize + 1;
+        let i = self.line.map_or(0, |line| line as isize + 1);
+        self.line = match (i + delta).rem_euclid(count) {
+            0 => None,
+            i => Some(i as u8 - 1)
+        };
+    }
+
+    /// The sprites that aren't drawn on the chosen line, or on at least one
+    /// visible line, because of the ten sprite limit.
+    pub fn dropped(&self, gameboy: &mut Gameboy) -> Vec<usize> {
+        let lines = match self.line {
+            Some(line) => line..=line,
+            None => 0..=GAME_HEIGHT as u8 - 1
+        };
+        let mut dropped: Vec<usize> = lines.flat_map(|ly| gameboy.dropped_sprites(ly)).collect();
+        dropped.sort_unstable();
+        dropped.dedup();
+        dropped
+    }
+
+    /// Sprite `index`'s number, position, tile and flags, then its DMG
+    /// palette, CGB palette, VRAM bank, flips and priority.
+    pub fn describe(&self, gameboy: &mut Gameboy, index: usize) -> [String; 2] {
+        let entry = &gameboy.oam()[index * 4..index * 4 + 4];
+        let (y, x, tile, flags) = (entry[0], entry[1], entry[2], entry[3]);
+        let mut attributes = format!("OBP{}  P{} B{}", (flags >> 4) & 1, flags & 7, (flags >> 3) & 1);
+        for (bit, flag) in &[(5, "XF"), (6, "YF"), (7, "BG")] {
+            if flags & (1 << bit) != 0 {
+                attributes.push(' ');
+                attributes.push_str(flag);
+            }
+        }
+        [format!("{:02} X {:02X} Y {:02X} T {:02X} F {:02X}", index, x, y, tile, flags), attributes]
+    }
+
+    pub fn draw(&self, gameboy: &mut Gameboy, frame_buffer: &mut [Color]) {
+        let width = Self::WIDTH;
+        draw::fill_rect(frame_buffer, width, 0, 0, width, Self::HEIGHT, BACKGROUND);
+        let height = gameboy.sprite_height();
+        let dropped = self.dropped(gameboy);
+        let line = self.line.map_or("ALL".to_string(), |line| format!("{:02X}", line));
+        let header = format!("LINE {}  LY {:02X}  8X{}  DROPPED {}", line, gameboy.read8(LY), height, dropped.len());
+        draw::draw_text(frame_buffer, width, MARGIN, MARGIN, &header, TEXT);
+
+        for index in 0..40 {
+            let (x, y) = (MARGIN + index / ROWS * CELL_WIDTH, GRID_Y + index % ROWS * CELL_HEIGHT);
+            let (sprite_y, sprite_x) = (gameboy.oam()[index * 4] as usize, gameboy.oam()[index * 4 + 1] as usize);
+            let on_line = self.line.is_some_and(|line| {
+                let line = line as usize + 16;
+                line >= sprite_y && line < sprite_y + height
+            });
+            let text = if dropped.contains(&index) {
+                DROPPED
+            } else if sprite_y == 0 || sprite_y >= GAME_HEIGHT + 16 || sprite_x == 0 || sprite_x >= 168 {
+                HIDDEN
+            } else {
+                TEXT
+            };
+            if on_line {
+                draw::fill_rect(frame_buffer, width, x, y, CELL_WIDTH - 2, CELL_HEIGHT - 1, ON_LINE);
+            }
+            draw::fill_rect(frame_buffer, width, x, y, 8, height, THUMBNAIL);
+            gameboy.draw_sprite(index, &mut frame_buffer[y * width + x..], width);
+            for (i, line) in self.describe(gameboy, index).iter().enumerate() {
+                draw::draw_text(frame_buffer, width, x + TEXT_X, y + 2 + i * GLYPH_HEIGHT, line, text);
+            }
+        }
+    }
+}
+
+impl Default for OamViewer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::idle_gameboy;
+
+    #[test]
+    fn test_oam_viewer() {
+        let mut gameboy = idle_gameboy("oam-viewer");
+        // Display and 8x16 sprites on.
+        gameboy.write8(0xFF40, 0x86);
+        // Twelve sprites on lines 0 to 15 and one, flipped vertically with
+        // palette 5 from bank 1, on lines 8 to 23.
+        for i in 0..12 {
+            gameboy.write8(0xFE00 + i * 4, 16);
+            gameboy.write8(0xFE01 + i * 4, 8 + i as u8 * 8);
+        }
+        gameboy.write8(0xFE00 + 12 * 4, 24);
+        gameboy.write8(0xFE01 + 12 * 4, 8);
+        gameboy.write8(0xFE02 + 12 * 4, 0x02);
+        gameboy.write8(0xFE03 + 12 * 4, 0x4D);
+        // Tile 2 in bank 1 has color 1 in the top left pixel and tile 3 is
+        // blank, so flipped the pixel is at the bottom.
+        gameboy.write_vram(1, 0x8020, 0x80);
+        gameboy.write8(0xFF6A, 0x80 | (5 * 8 + 2));
+        gameboy.write8(0xFF6B, 0x1F);
+        gameboy.write8(0xFF6B, 0x00);
+
+        let mut viewer = OamViewer::new();
+        assert_eq!(viewer.dropped(&mut gameboy), vec![10, 11, 12]);
+        viewer.step_line(10);
+        assert_eq!(viewer.line(), Some(9));
+        assert_eq!(viewer.dropped(&mut gameboy), vec![10, 11, 12]);
+        viewer.step_line(-10);
+        viewer.step_line(-1);
+        assert_eq!(viewer.line(), Some(143));
+        assert_eq!(viewer.dropped(&mut gameboy), vec![]);
+        viewer.set_line(Some(3));
+        assert_eq!(viewer.dropped(&mut gameboy), vec![10, 11]);
+        assert_eq!(viewer.describe(&mut gameboy, 12), [
+            "12 X 08 Y 18 T 02 F 4D".to_string(),
+            "OBP0  P5 B1 YF".to_string()
+        ]);
+
+        let mut frame_buffer = vec![Color::new(0, 0, 0); OamViewer::WIDTH * OamViewer::HEIGHT];
+        viewer.draw(&mut gameboy, &mut frame_buffer);
+        // Sprites 11 and 12 are in the second column.
+        let pixel = |row: usize, dx: usize, dy: usize| {
+            frame_buffer[(GRID_Y + row * CELL_HEIGHT + dy) * OamViewer::WIDTH + MARGIN + CELL_WIDTH + dx]
+        };
+        assert_eq!(pixel(2, 0, 15), Color::new(31, 0, 0));
+        assert_eq!(pixel(2, 0, 0), THUMBNAIL);
+        assert_eq!(pixel(2, TEXT_X + 1, 2), TEXT);
+        assert_eq!(pixel(1, TEXT_X + 1, 2), DROPPED);
+    }
+}
@@ -0,0 +1,278 @@
+use crate::draw::{self, BACKGROUND, GLYPH_HEIGHT, GLYPH_WIDTH, MARGIN, TEXT};
+use crate::gameboy::{Color, Gameboy, Mode};
+use crate::lcd::TilePalette;
+
+const GRID_Y: usize = MARGIN + GLYPH_HEIGHT + 2;
+const LABEL_WIDTH: usize = 5 * GLYPH_WIDTH;
+const SWATCH_WIDTH: usize = 16;
+const SWATCH_HEIGHT: usize = 10;
+const SWATCH_PITCH: usize = SWATCH_WIDTH + 2;
+const SIDE_WIDTH: usize = LABEL_WIDTH + 4 * SWATCH_PITCH;
+const SIDE_GAP: usize = 8;
+const ROW_HEIGHT: usize = SWATCH_HEIGHT + 1 + GLYPH_HEIGHT + 3;
+// Between the DMG registers and the CGB palettes.
+const SECTION_GAP: usize = 4;
+const ROWS: usize = 10;
+
+const BGP: u16 = 0xFF47;
+const OBP0: u16 = 0xFF48;
+const OBP1: u16 = 0xFF49;
+
+const SELECTED: Color = Color { r: 31, g: 10, b: 8 };
+
+/// One of the three 5 bit parts of an RGB555 color.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue
+}
+
+impl Channel {
+    fn shift(self) -> u16 {
+        match self {
+            Channel::Red => 0,
+            Channel::Green => 5,
+            Channel::Blue => 10
+        }
+    }
+}
+
+/// Shows the DMG palette registers with the shade each color maps to and,
+/// on CGB, the eight background and eight object palettes with their
+/// RGB555 values. The selected color can be changed while the game runs:
+/// one channel at a time for CGB colors, which is written back to palette
+/// RAM, and the shade it maps to for the DMG registers.
+pub struct PaletteViewer {
+    selected: (TilePalette, u8),
+    channel: Channel
+}
+
+impl PaletteViewer {
+    pub const WIDTH: usize = 2 * MARGIN + 2 * SIDE_WIDTH + SIDE_GAP;
+    pub const HEIGHT: usize = GRID_Y + ROWS * ROW_HEIGHT + SECTION_GAP + MARGIN;
+
+    pub fn new(mode: Mode) -> Self {
+        let palette = if mode.is_cgb() { TilePalette::Bg(0) } else { TilePalette::Bgp };
+        PaletteViewer { selected: (palette, 0), channel: Channel::Red }
+    }
+
+    /// The selected palette and color within it.
+    pub fn selected(&self) -> (TilePalette, u8) {
+        self.selected
+    }
+
+    pub fn select(&mut self, palette: TilePalette, value: u8) {
+        self.selected = (palette, value);
+    }
+
+    pub fn channel(&self) -> Channel {
+        self.channel
+    }
+
+    pub fn set_channel(&mut self, channel: Channel) {
+        self.channel = channel;
+    }
+
+    /// Moves the selection across colors and down palettes, skipping
+    /// places with no palette and staying put at the edges.
+    pub fn move_cursor(&mut self, mode: Mode, dx: isize, dy: isize) {
+        let (side, row) = position(self.selected.0);
+        let mut col = (side * 4 + self.selected.1 as usize) as isize;
+        let mut row = row as isize;
+        loop {
+            col += dx;
+            row += dy;
+            if !(0..8).contains(&col) || !(0..ROWS as isize).contains(&row) {
+                return;
+            }
+            if let Some(palette) = palette_at(mode, col as usize / 4, row as usize) {
+                self.selected = (palette, col as u8 % 4);
+                return;
+            }
+        }
+    }
+
+    /// The palette and color of the swatch drawn at (`x`, `y`).
+    pub fn swatch_at(&self, mode: Mode, x: usize, y: usize) -> Option<(TilePalette, u8)> {
+        (0..ROWS).flat_map(|row| (0..2).map(move |side| (side, row)))
+            .filter_map(|(side, row)| palette_at(mode, side, row))
+            .flat_map(|palette| (0..4).map(move |value| (palette, value)))
+            .find(|(palette, value)| {
+                let (sx, sy) = swatch_position(*palette, *value);
+                (sx..sx + SWATCH_WIDTH).contains(&x) && (sy..sy + SWATCH_HEIGHT).contains(&y)
+            })
+    }
+
+    /// Changes the selected color's channel by `delta`, or for the DMG
+    /// registers the shade it maps to.
+    pub fn adjust(&self, gameboy: &mut Gameboy, delta: i8) {
+        let (palette, value) = self.selected;
+        match register(palette) {
+            Some(addr) => {
+                let shift = value * 2;
+                let register = gameboy.read8(addr);
+                let shade = ((register >> shift) & 3).wrapping_add(delta as u8) & 3;
+                gameboy.write8(addr, (register & !(3 << shift)) | (shade << shift));
+            }
+            None => {
+                let raw = gameboy.cgb_color(palette, value).unwrap_or(0);
+                let shift = self.channel.shift();
+                let level = ((raw >> shift) & 0x1F) as i16 + delta as i16;
+                let level = level.clamp(0, 0x1F) as u16;
+                gameboy.set_cgb_color(palette, value, (raw & !(0x1F << shift)) | (level << shift));
+            }
+        }
+    }
+
+    /// The selected color: its raw value and channels for CGB palettes,
+    /// or the register and the shade it maps to for DMG ones.
+    pub fn describe(&self, gameboy: &mut Gameboy) -> String {
+        let (palette, value) = self.selected;
+        match register(palette) {
+            Some(addr) => {
+                let register = gameboy.read8(addr);
+                format!("{} {}  {:04X} {:02X}  SHADE {}", palette, value, addr, register, (register >> (value * 2)) & 3)
+            }
+            None => {
+                let raw = gameboy.cgb_color(palette, value).unwrap_or(0);
+                let channel = match self.channel {
+                    Channel::Red => "R",
+                    Channel::Green => "G",
+                    Channel::Blue => "B"
+                };
+                format!("{} {}  RGB {:04X}  R {:02} G {:02} B {:02}  EDIT {}",
+                        palette, value, raw, raw & 0x1F, (raw >> 5) & 0x1F, (raw >> 10) & 0x1F, channel)
+            }
+        }
+    }
+
+    pub fn draw(&self, gameboy: &mut Gameboy, frame_buffer: &mut [Color]) {
+        let width = Self::WIDTH;
+        let mode = gameboy.mode();
+        draw::fill_rect(frame_buffer, width, 0, 0, width, Self::HEIGHT, BACKGROUND);
+        let header = self.describe(gameboy);
+        draw::draw_text(frame_buffer, width, MARGIN, MARGIN, &header, TEXT);
+
+        for (side, row) in (0..ROWS).flat_map(|row| (0..2).map(move |side| (side, row))) {
+            let palette = match palette_at(mode, side, row) {
+                Some(palette) => palette,
+                None => continue
+            };
+            let (x, y) = swatch_position(palette, 0);
+            draw::draw_text(frame_buffer, width, x - LABEL_WIDTH, y + 2, &palette.to_string(), TEXT);
+            for value in 0..4 {
+                let (x, y) = swatch_position(palette, value);
+                let color = gameboy.palette_color(palette, value);
+                draw::fill_rect(frame_buffer, width, x, y, SWATCH_WIDTH, SWATCH_HEIGHT, color);
+                let text = match register(palette) {
+                    Some(addr) => format!("{}", (gameboy.read8(addr) >> (value * 2)) & 3),
+                    None => format!("{:04X}", gameboy.cgb_color(palette, value).unwrap_or(0))
+                };
+                draw::draw_text(frame_buffer, width, x, y + SWATCH_HEIGHT + 1, &text, TEXT);
+                if self.selected == (palette, value) {
+                    draw::outline_rect(frame_buffer, width, x - 1, y - 1, SWATCH_WIDTH + 2, SWATCH_HEIGHT + 2, SELECTED);
+                }
+            }
+        }
+    }
+}
+
+// The DMG registers are on the first two rows, background on the left and
+// objects on the right, with the CGB palettes below them.
+fn palette_at(mode: Mode, side: usize, row: usize) -> Option<TilePalette> {
+    match (side, row) {
+        (0, 0) => Some(TilePalette::Bgp),
+        (1, 0) => Some(TilePalette::Obp0),
+        (1, 1) => Some(TilePalette::Obp1),
+        (0, 2 ..= 9) if mode.is_cgb() => Some(TilePalette::Bg(row as u8 - 2)),
+        (1, 2 ..= 9) if mode.is_cgb() => Some(TilePalette::Obj(row as u8 - 2)),
+        _ => None
+    }
+}
+
+fn position(palette: TilePalette) -> (usize, usize) {
+    match palette {
+        TilePalette::Bgp => (0, 0),
+        TilePalette::Obp0 => (1, 0),
+        TilePalette::Obp1 => (1, 1),
+        TilePalette::Bg(n) => (0, n as usize + 2),
+        TilePalette::Obj(n) => (1, n as usize + 2)
+    }
+}
+
+fn swatch_position(palette: TilePalette, value: u8) -> (usize, usize) {
+    let (side, row) = position(palette);
+    let x = MARGIN + side * (SIDE_WIDTH + SIDE_GAP) + LABEL_WIDTH + value as usize * SWATCH_PITCH;
+    let y = GRID_Y + row * ROW_HEIGHT + if row >= 2 { SECTION_GAP } else { 0 };
+    (x, y + 1)
+}
+
+fn register(palette: TilePalette) -> Option<u16> {
+    match palette {
+        TilePalette::Bgp => Some(BGP),
+        TilePalette::Obp0 => Some(OBP0),
+        TilePalette::Obp1 => Some(OBP1),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::idle_gameboy;
+
+    #[test]
+    fn test_palette_viewer() {
+        let mut gameboy = idle_gameboy("palette-viewer");
+        // Object palette 2, color 1 is 0x7C1F: full red and blue.
+        gameboy.write8(0xFF6A, 0x80 | (2 * 8 + 2));
+        gameboy.write8(0xFF6B, 0x1F);
+        gameboy.write8(0xFF6B, 0x7C);
+        gameboy.write8(BGP, 0xE4);
+
+        let mut viewer = PaletteViewer::new(Mode::CGB);
+        assert_eq!(viewer.selected(), (TilePalette::Bg(0), 0));
+        viewer.move_cursor(Mode::CGB, 5, 2);
+        assert_eq!(viewer.selected(), (TilePalette::Obj(2), 1));
+        assert_eq!(viewer.describe(&mut gameboy), "OBJ2 1  RGB 7C1F  R 31 G 00 B 31  EDIT R");
+
+        viewer.adjust(&mut gameboy, 1);
+        viewer.set_channel(Channel::Green);
+        viewer.adjust(&mut gameboy, 3);
+        viewer.set_channel(Channel::Blue);
+        viewer.adjust(&mut gameboy, -4);
+        assert_eq!(gameboy.cgb_color(TilePalette::Obj(2), 1), Some(0x6C7F));
+        assert_eq!(gameboy.cgb_color(TilePalette::Bgp, 1), None);
+        assert!(!gameboy.set_cgb_color(TilePalette::Obp0, 1, 0));
+        // The change is in palette RAM, where the game reads it back.
+        gameboy.write8(0xFF6A, 2 * 8 + 2);
+        assert_eq!(gameboy.read8(0xFF6B), 0x7F);
+        assert_eq!(gameboy.palette_color(TilePalette::Obj(2), 1), Color::new(31, 3, 27));
+
+        // Up from OBJ0 skips the empty place under BGP on the left but not
+        // OBP1 on the right.
+        viewer.move_cursor(Mode::CGB, 0, -2);
+        assert_eq!(viewer.selected(), (TilePalette::Obj(0), 1));
+        viewer.move_cursor(Mode::CGB, 0, -1);
+        assert_eq!(viewer.selected(), (TilePalette::Obp1, 1));
+        viewer.select(TilePalette::Bg(1), 0);
+        viewer.move_cursor(Mode::CGB, 0, -1);
+        viewer.move_cursor(Mode::CGB, 0, -1);
+        assert_eq!(viewer.selected(), (TilePalette::Bgp, 0));
+        viewer.move_cursor(Mode::CGB, 0, -1);
+        assert_eq!(viewer.selected(), (TilePalette::Bgp, 0));
+
+        viewer.select(TilePalette::Bgp, 1);
+        viewer.adjust(&mut gameboy, 1);
+        assert_eq!(gameboy.read8(BGP), 0xE8);
+        assert_eq!(viewer.describe(&mut gameboy), "BGP 1  FF47 E8  SHADE 2");
+
+        let (x, y) = swatch_position(TilePalette::Obj(2), 1);
+        assert_eq!(viewer.swatch_at(Mode::CGB, x + 3, y + 3), Some((TilePalette::Obj(2), 1)));
+        assert_eq!(viewer.swatch_at(Mode::DMG, x + 3, y + 3), None);
+        let mut frame_buffer = vec![Color::new(0, 0, 0); PaletteViewer::WIDTH * PaletteViewer::HEIGHT];
+        viewer.draw(&mut gameboy, &mut frame_buffer);
+        assert_eq!(frame_buffer[(y + 3) * PaletteViewer::WIDTH + x + 3], Color::new(31, 3, 27));
+    }
+}
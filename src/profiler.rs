@@ -0,0 +1,187 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use crate::cartridge::Symbols;
+use crate::cpu::StackFrame;
+
+// A bank and address.
+type Loc = (usize, u16);
+
+// Code outside any known routine.
+const ROOT: Loc = (usize::MAX, 0);
+
+// How many of the busiest addresses the report lists.
+const TOP_PCS: usize = 20;
+
+/// Counts the M-cycles spent at every PC and in every call stack.
+///
+/// Cycles are rolled up by routine: the enclosing label from the symbol
+/// file or, without one, the address the current routine was entered at.
+/// Stacks come from the CPU's [call stack](crate::Gameboy::call_stack).
+pub struct Profiler {
+    symbols: Option<Symbols>,
+    by_pc: HashMap<Loc, u64>,
+    by_stack: HashMap<Vec<Loc>, u64>,
+    pc: Loc,
+    key: Vec<Loc>,
+    cycles: u64,
+    vblanks: u64
+}
+
+impl Profiler {
+    pub fn new(symbols: Option<Symbols>) -> Self {
+        Profiler {
+            symbols,
+            by_pc: HashMap::new(),
+            by_stack: HashMap::new(),
+            pc: ROOT,
+            key: Vec::new(),
+            cycles: 0,
+            vblanks: 0
+        }
+    }
+
+    // The routine `loc` is part of, when running inside `frames`.
+    fn routine(&self, loc: Loc, frames: &[StackFrame]) -> Loc {
+        let enclosing = self.symbols.as_ref().and_then(|s| s.enclosing(loc.0, loc.1));
+        match enclosing {
+            Some((addr, _)) => (loc.0, addr),
+            None => frames.last().map_or(ROOT, |frame| frame.to)
+        }
+    }
+
+    /// The next instruction runs at `pc` inside `frames`.
+    pub(crate) fn start(&mut self, pc: Loc, frames: &[StackFrame]) {
+        let mut key = std::mem::take(&mut self.key);
+        key.clear();
+        key.extend(frames.iter().enumerate().map(|(i, frame)| self.routine(frame.from, &frames[..i])));
+        key.push(self.routine(pc, frames));
+        self.key = key;
+        self.pc = pc;
+    }
+
+    /// The instruction took `cycles`.
+    pub(crate) fn finish(&mut self, cycles: u32, vblank: bool) {
+        let cycles = cycles as u64;
+        self.cycles += cycles;
+        *self.by_pc.entry(self.pc).or_insert(0) += cycles;
+        match self.by_stack.get_mut(self.key.as_slice()) {
+            Some(total) => *total += cycles,
+            None => { self.by_stack.insert(self.key.clone(), cycles); }
+        }
+        if vblank {
+            self.vblanks += 1;
+        }
+    }
+
+    fn name(&self, loc: Loc) -> String {
+        if loc == ROOT {
+            return "(root)".to_string();
+        }
+        let (bank, addr) = loc;
+        self.symbols.as_ref().and_then(|symbols| symbols.describe(bank, addr))
+            .unwrap_or_else(|| format!("{:02X}:{:04X}", bank, addr))
+    }
+
+    /// Writes every call stack with the cycles spent in it, in the folded
+    /// format read by flamegraph.pl and speedscope.
+    pub fn write_folded(&self, out: &mut dyn Write) -> io::Result<()> {
+        let mut lines: Vec<(String, u64)> = self.by_stack.iter()
+            .map(|(stack, cycles)| {
+                let names: Vec<String> = stack.iter().map(|loc| self.name(*loc)).collect();
+                (names.join(";"), *cycles)
+            })
+            .collect();
+        lines.sort();
+        for (stack, cycles) in lines {
+            writeln!(out, "{} {}", stack, cycles)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the cycles spent in each routine, by itself and including
+    /// what it called, followed by the busiest addresses.
+    pub fn write_report(&self, out: &mut dyn Write) -> io::Result<()> {
+        let mut self_cycles: HashMap<Loc, u64> = HashMap::new();
+        let mut total_cycles: HashMap<Loc, u64> = HashMap::new();
+        for (stack, cycles) in &self.by_stack {
+            *self_cycles.entry(*stack.last().unwrap()).or_insert(0) += cycles;
+            // Recursion mustn't count a routine twice.
+            for loc in stack.iter().collect::<HashSet<_>>() {
+                *total_cycles.entry(*loc).or_insert(0) += cycles;
+            }
+        }
+
+        let frames = self.vblanks.max(1);
+        writeln!(out, "{} cycles over {} frames, {} per frame", self.cycles, self.vblanks, self.cycles / frames)?;
+        let percent = |cycles: u64| 100.0 * cycles as f64 / self.cycles.max(1) as f64;
+
+        writeln!(out, "\n{:>12} {:>6} {:>12} {:>6} {:>10}  routine", "self", "%", "total", "%", "self/frame")?;
+        let mut routines: Vec<_> = total_cycles.into_iter().collect();
+        routines.sort_by_key(|(loc, total)| (std::cmp::Reverse(self_cycles.get(loc).copied().unwrap_or(0)), std::cmp::Reverse(*total), *loc));
+        for (loc, total) in routines {
+            let own = self_cycles.get(&loc).copied().unwrap_or(0);
+            writeln!(out, "{:>12} {:>5.1}% {:>12} {:>5.1}% {:>10}  {}",
+                     own, percent(own), total, percent(total), own / frames, self.name(loc))?;
+        }
+
+        writeln!(out, "\n{:>12} {:>6}  address", "cycles", "%")?;
+        let mut pcs: Vec<_> = self.by_pc.iter().collect();
+        pcs.sort_by_key(|(loc, cycles)| (std::cmp::Reverse(**cycles), **loc));
+        for (loc, cycles) in pcs.into_iter().take(TOP_PCS) {
+            let address = format!("{:02X}:{:04X}", loc.0, loc.1);
+            let name = self.name(*loc);
+            if name == address {
+                writeln!(out, "{:>12} {:>5.1}%  {}", cycles, percent(*cycles), address)?;
+            } else {
+                writeln!(out, "{:>12} {:>5.1}%  {} {}", cycles, percent(*cycles), address, name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::{booted_gameboy, test_frame_buffer, Mode, CALL_PROGRAM};
+
+    fn profile(symbols: Option<Symbols>) -> Profiler {
+        let mut gameboy = booted_gameboy("profiler", &CALL_PROGRAM, Mode::DMG);
+        gameboy.set_profiler(Some(Profiler::new(symbols)));
+        let mut frame_buffer = test_frame_buffer();
+        // Four times round the loop.
+        for _ in 0..24 {
+            gameboy.step(&mut frame_buffer, &mut Vec::new());
+        }
+        gameboy.take_profiler().unwrap()
+    }
+
+    fn folded(profiler: &Profiler) -> String {
+        let mut out = Vec::new();
+        profiler.write_folded(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_folded_stacks() {
+        // INC A + CALL + LD + JR is 1 + 6 + 4 + 3 cycles, INC B + RET 1 + 4.
+        let profiler = profile(Some(Symbols::parse("00:0100 main\n00:0109 sub\n")));
+        assert_eq!(folded(&profiler), "main 56\nmain;sub 20\n");
+
+        let profiler = profile(None);
+        assert_eq!(folded(&profiler), "(root) 56\n(root);00:0109 20\n");
+    }
+
+    #[test]
+    fn test_report() {
+        let profiler = profile(Some(Symbols::parse("00:0100 main\n00:0109 sub\n")));
+        let mut out = Vec::new();
+        profiler.write_report(&mut out).unwrap();
+        let report = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "76 cycles over 0 frames, 76 per frame");
+        assert!(lines[3].ends_with("  main") && lines[3].contains("56"));
+        assert!(lines[4].ends_with("  sub") && lines[4].contains("20"));
+        assert!(report.contains("00:0101 main+$1"));
+    }
+}
@@ -1,4 +1,5 @@
 use crate::memory::memory_map::{MemoryMappedDevice};
+use crate::save_state::{self, SaveState, StateReader, StateWriter};
 
 pub struct RamDevice {
     offset: usize,
@@ -9,8 +10,7 @@ pub struct RamDevice {
 
 impl RamDevice {
     pub fn new(offset: usize, size: usize, banks: usize) -> RamDevice {
-        let mut v = Vec::with_capacity(size * banks);
-        v.resize(size * banks, 0);
+        let v = vec![0; size * banks];
         RamDevice {
             offset,
             size,
@@ -19,6 +19,16 @@ impl RamDevice {
         }
     }
 
+    /// The bank selected through SVBK.
+    pub fn bank(&self) -> usize {
+        self.bank
+    }
+
+    /// Every bank, one after another.
+    pub fn memory(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
     fn bank_offset(&self) -> usize {
         (self.bank - 1) * self.size
     }
@@ -28,7 +38,7 @@ impl MemoryMappedDevice for RamDevice {
     fn set8(&mut self, addr: u16, byte: u8) {
         match addr {
             0xFF70 => {
-                self.bank = match byte & 0x3 {
+                self.bank = match byte & 0x7 {
                     0 | 1 => 1,
                     b => b as usize
                 };
@@ -61,3 +71,42 @@ impl MemoryMappedDevice for RamDevice {
         }
     }
 }
+
+impl SaveState for RamDevice {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.memory);
+        w.write_usize(self.bank);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        r.read_bytes_into(&mut self.memory)?;
+        self.bank = r.read_usize()?;
+        if self.bank == 0 || self.bank * self.size > self.memory.len() {
+            return Err(save_state::SaveStateError::Invalid("RAM bank"))
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_svbk_selects_every_bank() {
+        let mut ram = RamDevice::new(0xD000, 0x1000, 7);
+        for bank in 1..=7 {
+            ram.set8(0xFF70, bank);
+            ram.set8(0xD000, bank * 0x11);
+        }
+        for bank in 4..=7 {
+            ram.set8(0xFF70, bank);
+            assert_eq!(ram.get8(0xFF70), bank);
+            assert_eq!(ram.get8(0xD000), bank * 0x11);
+        }
+        // Bank 0 selects bank 1 and the upper bits are ignored.
+        ram.set8(0xFF70, 0xF8);
+        assert_eq!(ram.get8(0xD000), 0x11);
+        assert_eq!(ram.memory()[3 * 0x1000], 0x44);
+    }
+}
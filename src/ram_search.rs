@@ -0,0 +1,231 @@
+use std::str::FromStr;
+use crate::cheats::Cheat;
+use crate::gameboy::Gameboy;
+
+/// How the bytes at a candidate address are read. Multi-byte values are
+/// little endian.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SearchFormat {
+    U8,
+    U16,
+    Bcd8,
+    Bcd16
+}
+
+impl SearchFormat {
+    pub fn size(&self) -> usize {
+        match self {
+            SearchFormat::U8 | SearchFormat::Bcd8 => 1,
+            SearchFormat::U16 | SearchFormat::Bcd16 => 2
+        }
+    }
+
+    /// The value of `bytes`, or None if they aren't valid BCD.
+    fn decode(&self, bytes: &[u8]) -> Option<u32> {
+        let bcd = |byte: u8| if byte >> 4 <= 9 && byte & 0xF <= 9 { Some((byte >> 4) as u32 * 10 + (byte & 0xF) as u32) } else { None };
+        match self {
+            SearchFormat::U8 => Some(bytes[0] as u32),
+            SearchFormat::U16 => Some(bytes[0] as u32 | (bytes[1] as u32) << 8),
+            SearchFormat::Bcd8 => bcd(bytes[0]),
+            SearchFormat::Bcd16 => Some(bcd(bytes[0])? + bcd(bytes[1])? * 100)
+        }
+    }
+}
+
+impl FromStr for SearchFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "u8" => Ok(SearchFormat::U8),
+            "u16" => Ok(SearchFormat::U16),
+            "bcd8" => Ok(SearchFormat::Bcd8),
+            "bcd16" => Ok(SearchFormat::Bcd16),
+            _ => Err(format!("Unknown format {}, try u8, u16, bcd8 or bcd16", s))
+        }
+    }
+}
+
+/// Which candidates a search step keeps, by comparing the value now with
+/// the value at the previous step.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SearchFilter {
+    Unchanged,
+    Changed,
+    Increased,
+    Decreased,
+    EqualTo(u32)
+}
+
+impl SearchFilter {
+    fn keep(&self, previous: u32, value: u32) -> bool {
+        match self {
+            SearchFilter::Unchanged => value == previous,
+            SearchFilter::Changed => value != previous,
+            SearchFilter::Increased => value > previous,
+            SearchFilter::Decreased => value < previous,
+            SearchFilter::EqualTo(n) => value == *n
+        }
+    }
+}
+
+/// An address still matching every filter.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SearchResult {
+    pub bank: usize,
+    pub addr: u16,
+    pub value: u32,
+    pub previous: u32
+}
+
+struct Snapshot {
+    banks: Vec<(usize, u16, Vec<u8>)>
+}
+
+impl Snapshot {
+    fn take(gameboy: &mut Gameboy) -> Self {
+        Snapshot { banks: gameboy.ram_banks() }
+    }
+
+    fn value(&self, format: SearchFormat, (bank, offset): (usize, usize)) -> Option<u32> {
+        format.decode(&self.banks[bank].2[offset..offset + format.size()])
+    }
+}
+
+/// Narrows down where a game keeps a value by snapshotting RAM and
+/// filtering the addresses on how their values change between steps.
+///
+/// Every WRAM bank, cartridge RAM bank and HRAM is searched, whether or not
+/// it is mapped at the time.
+pub struct RamSearch {
+    format: SearchFormat,
+    previous: Snapshot,
+    current: Snapshot,
+    // Indexes into the snapshots' banks, and offsets into those.
+    candidates: Vec<(usize, usize)>
+}
+
+impl RamSearch {
+    /// Starts a search with every address as a candidate.
+    pub fn new(gameboy: &mut Gameboy, format: SearchFormat) -> Self {
+        let current = Snapshot::take(gameboy);
+        let candidates = current.banks.iter().enumerate()
+            .flat_map(|(i, (_, _, bytes))| (0..=bytes.len().saturating_sub(format.size())).map(move |offset| (i, offset)))
+            .filter(|candidate| current.value(format, *candidate).is_some())
+            .collect();
+        RamSearch { format, previous: Snapshot { banks: current.banks.clone() }, current, candidates }
+    }
+
+    pub fn format(&self) -> SearchFormat {
+        self.format
+    }
+
+    /// Snapshots RAM again and keeps the candidates that pass `filter`.
+    pub fn filter(&mut self, gameboy: &mut Gameboy, filter: SearchFilter) {
+        self.previous = std::mem::replace(&mut self.current, Snapshot::take(gameboy));
+        let (format, previous, current) = (self.format, &self.previous, &self.current);
+        self.candidates.retain(|candidate| {
+            match (previous.value(format, *candidate), current.value(format, *candidate)) {
+                (Some(before), Some(now)) => filter.keep(before, now),
+                _ => false
+            }
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    /// The remaining candidates with their values at the last two steps.
+    pub fn results(&self) -> impl Iterator<Item = SearchResult> + '_ {
+        self.candidates.iter().map(move |candidate| {
+            let (bank, start, _) = self.current.banks[candidate.0];
+            let value = self.current.value(self.format, *candidate).unwrap();
+            SearchResult {
+                bank,
+                addr: start + candidate.1 as u16,
+                value,
+                previous: self.previous.value(self.format, *candidate).unwrap()
+            }
+        })
+    }
+
+    /// Cheats that hold each byte of `result` at its current value.
+    pub fn freeze(&self, gameboy: &mut Gameboy, result: &SearchResult) -> Vec<Cheat> {
+        (0..self.format.size() as u16)
+            .map(|i| {
+                let addr = result.addr + i;
+                Cheat::freeze(result.bank, addr, gameboy.read_banked(result.bank, addr).unwrap())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::{booted_gameboy, idle_gameboy, test_frame_buffer, Mode};
+
+    #[test]
+    fn test_search() {
+        let mut gameboy = idle_gameboy("ram-search");
+        let mut search = RamSearch::new(&mut gameboy, SearchFormat::U8);
+        // WRAM bank 0, banks 1-7 and HRAM.
+        assert_eq!(search.len(), 0x1000 * 8 + 0x7F);
+
+        gameboy.write_banked(5, 0xD123, 3);
+        gameboy.write_banked(0, 0xFF90, 1);
+        search.filter(&mut gameboy, SearchFilter::Increased);
+        assert_eq!(search.len(), 2);
+        gameboy.write_banked(5, 0xD123, 2);
+        search.filter(&mut gameboy, SearchFilter::Changed);
+        let results: Vec<_> = search.results().collect();
+        assert_eq!(results, vec![SearchResult { bank: 5, addr: 0xD123, value: 2, previous: 3 }]);
+
+        let freeze = search.freeze(&mut gameboy, &results[0]);
+        gameboy.add_cheat(freeze[0].clone());
+        gameboy.write_banked(5, 0xD123, 9);
+        gameboy.tick(&[], &mut test_frame_buffer(), &mut Vec::new());
+        assert_eq!(gameboy.read_banked(5, 0xD123), Some(2));
+    }
+
+    #[test]
+    fn test_freeze_dmg_cartridge_ram() {
+        // JR -2 on an MBC1 cartridge, which maps RAM bank 0.
+        let mut program = [0; 0x48];
+        program[..2].copy_from_slice(&[0x18, 0xFE]);
+        program[0x47] = 0x01;
+        let mut gameboy = booted_gameboy("ram-search-dmg", &program, Mode::DMG);
+        let search = RamSearch::new(&mut gameboy, SearchFormat::U8);
+        gameboy.write_banked(2, 0xA000, 0x42);
+        let result = SearchResult { bank: 2, addr: 0xA000, value: 0x42, previous: 0 };
+        let freeze = search.freeze(&mut gameboy, &result);
+        gameboy.add_cheat(freeze[0].clone());
+        gameboy.write_banked(2, 0xA000, 9);
+        gameboy.tick(&[], &mut test_frame_buffer(), &mut Vec::new());
+        assert_eq!(gameboy.read_banked(2, 0xA000), Some(0x42));
+        assert_eq!(gameboy.read_banked(0, 0xA000), Some(0));
+    }
+
+    #[test]
+    fn test_formats() {
+        let mut gameboy = idle_gameboy("ram-search");
+        gameboy.write_banked(0, 0xC010, 0x34);
+        gameboy.write_banked(0, 0xC011, 0x12);
+        let mut search = RamSearch::new(&mut gameboy, SearchFormat::U16);
+        search.filter(&mut gameboy, SearchFilter::EqualTo(0x1234));
+        assert_eq!(search.results().map(|r| r.addr).collect::<Vec<_>>(), vec![0xC010]);
+
+        let mut search = RamSearch::new(&mut gameboy, SearchFormat::Bcd16);
+        search.filter(&mut gameboy, SearchFilter::EqualTo(1234));
+        assert_eq!(search.results().map(|r| r.addr).collect::<Vec<_>>(), vec![0xC010]);
+
+        gameboy.write_banked(0, 0xC020, 0x1A);
+        let search = RamSearch::new(&mut gameboy, SearchFormat::Bcd8);
+        assert!(search.results().all(|r| r.addr != 0xC020));
+    }
+}
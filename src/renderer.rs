@@ -1,21 +1,30 @@
-use std::{fs, thread, time};
+use std::{fs, io, thread, time};
+use std::convert::TryFrom;
+use std::path::PathBuf;
 use sdl2::pixels::{PixelFormatEnum, Color as PColor};
 use sdl2::render::{WindowCanvas, Texture};
+use sdl2::VideoSubsystem;
 use sdl2::audio::AudioQueue;
 use sdl2::EventPump;
 use sdl2::event::{Event, WindowEvent};
-use sdl2::keyboard::{KeyboardState, Scancode, Keycode};
-use sdl2::rect::Rect;
+use sdl2::keyboard::{KeyboardState, Scancode, Keycode, Mod};
+use sdl2::sys::SDL_WindowFlags;
 use samplerate::{Samplerate, ConverterType};
-use crate::clocks::{CLOCK_FREQ, AUDIO_SAMPLE_RATE, NS_PER_SCREEN_REFRESH, NS_PER_SAMPLE};
-use crate::cartridge::Cartridge;
-use crate::gameboy::{Gameboy, Color, JoypadInput, Mode, GAME_WIDTH, GAME_HEIGHT};
+use gameboy::clocks::{CLOCK_FREQ, AUDIO_SAMPLE_RATE, NS_PER_SCREEN_REFRESH, NS_PER_SAMPLE};
+use gameboy::{Cartridge, Channel, CodeDataLog, Gameboy, Color, JoypadInput, MapViewer, MemoryViewer, Mode, Movie, OamViewer, PaletteViewer, Profiler, RewindBuffer, TileViewer, GAME_WIDTH, GAME_HEIGHT};
+use gameboy::gdb::GdbStub;
+use gameboy::debugger::Debugger;
 
 const SCANCODES: [Scancode; 8] = [
     Scancode::W, Scancode::S, Scancode::A, Scancode::D,
     Scancode::X, Scancode::Z, Scancode::L, Scancode::K
 ];
 
+const REWIND_KEY: Scancode = Scancode::Backspace;
+// A snapshot every other frame keeps about ten seconds of history.
+const REWIND_INTERVAL: u64 = 2;
+const REWIND_CAPACITY: usize = 300;
+
 const RATIO: u8 = 8;
 fn to_pcolor(color: Color) -> PColor {
     PColor { r: color.r * RATIO, g: color.g * RATIO, b: color.b * RATIO, a: 255 }
@@ -39,10 +48,309 @@ fn scancode_to_joypad_input(scancode: &Scancode) -> JoypadInput {
 
 fn collect_pressed(keyboard_state: &KeyboardState) -> Vec<JoypadInput> {
     SCANCODES.iter().filter(|sc| keyboard_state.is_scancode_pressed(**sc)).
-        map(|sc| scancode_to_joypad_input(sc)).
+        map(scancode_to_joypad_input).
         collect()
 }
 
+fn keycode_to_slot(keycode: Keycode) -> Option<u8> {
+    match keycode {
+        Keycode::Num0 => Some(0),
+        Keycode::Num1 => Some(1),
+        Keycode::Num2 => Some(2),
+        Keycode::Num3 => Some(3),
+        Keycode::Num4 => Some(4),
+        Keycode::Num5 => Some(5),
+        Keycode::Num6 => Some(6),
+        Keycode::Num7 => Some(7),
+        Keycode::Num8 => Some(8),
+        Keycode::Num9 => Some(9),
+        _ => None
+    }
+}
+
+fn keycode_to_hex_digit(keycode: Keycode) -> Option<u8> {
+    let name = keycode.name();
+    if name.len() == 1 { u8::from_str_radix(&name, 16).ok() } else { None }
+}
+
+fn report_playback(movie: &Movie, frame_buffer: &[Color]) {
+    let hash = gameboy::frame_hash(frame_buffer);
+    match movie.final_frame_hash() {
+        Some(expected) if expected == hash => println!("Movie ended, final frame hash {:016x} matches", hash),
+        Some(expected) => println!("Movie ended, final frame hash {:016x} differs from the recorded {:016x}", hash, expected),
+        None => println!("Movie ended, final frame hash {:016x}", hash)
+    }
+}
+
+fn save_state_path(cartridge: &Cartridge, slot: u8) -> PathBuf {
+    cartridge.path().with_extension(format!("ss{}", slot))
+}
+
+/// How [`Renderer::run`] starts and drives the machine.
+pub struct RunOptions {
+    pub debug: bool,
+    pub skip_boot_rom: bool,
+    pub dmg: bool,
+    /// Write the inputs to a movie at this path on exit.
+    pub record: Option<PathBuf>,
+    /// Take the inputs from this movie until it ends.
+    pub playback: Option<Movie>,
+    /// Let a remote debugger control the machine.
+    pub gdb: Option<GdbStub>,
+    /// Open the debugger console when this address or label is reached.
+    pub break_at: Option<String>,
+    /// Write the profiled call stacks to this path on exit.
+    pub profile: Option<PathBuf>,
+    /// Log code and data accesses, adding to the CDL file at this path and
+    /// saving it on exit.
+    pub cdl: Option<PathBuf>
+}
+
+// One of the debug views. Each draws into a frame buffer its own size,
+// shown in a window of its own.
+trait View {
+    fn handle_key(&mut self, _gameboy: &mut Gameboy, _keycode: Keycode, _keymod: Mod) {}
+
+    // The mouse moved to a position in view pixels, or None when it left.
+    fn handle_hover(&mut self, _gameboy: &mut Gameboy, _position: Option<(usize, usize)>) {}
+
+    fn handle_click(&mut self, _gameboy: &mut Gameboy, _x: usize, _y: usize) {}
+
+    // Called after every emulated frame.
+    fn refresh(&mut self, _gameboy: &mut Gameboy) {}
+
+    fn render(&self, gameboy: &mut Gameboy, frame_buffer: &mut [Color]);
+}
+
+// Arrows and page up/down move the cursor, by 4K with shift. Tab switches
+// region, [ and ] switch bank and hex digits edit.
+impl View for MemoryViewer {
+    fn handle_key(&mut self, gameboy: &mut Gameboy, keycode: Keycode, keymod: Mod) {
+        let page = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) { 0x1000 } else { 0x100 };
+        match keycode {
+            Keycode::Left => self.move_cursor(gameboy, -1),
+            Keycode::Right => self.move_cursor(gameboy, 1),
+            Keycode::Up => self.move_cursor(gameboy, -16),
+            Keycode::Down => self.move_cursor(gameboy, 16),
+            Keycode::PageUp => self.move_cursor(gameboy, -page),
+            Keycode::PageDown => self.move_cursor(gameboy, page),
+            Keycode::Home => self.move_cursor(gameboy, isize::MIN / 2),
+            Keycode::End => self.move_cursor(gameboy, isize::MAX / 2),
+            Keycode::Tab => self.next_region(gameboy),
+            Keycode::LeftBracket => self.change_bank(gameboy, -1),
+            Keycode::RightBracket => self.change_bank(gameboy, 1),
+            Keycode::Backspace => self.cancel_edit(),
+            _ => {
+                if let Some(digit) = keycode_to_hex_digit(keycode) {
+                    self.type_digit(gameboy, digit);
+                }
+            }
+        }
+    }
+
+    fn refresh(&mut self, gameboy: &mut Gameboy) {
+        MemoryViewer::refresh(self, gameboy);
+    }
+
+    fn render(&self, _gameboy: &mut Gameboy, frame_buffer: &mut [Color]) {
+        self.draw(frame_buffer);
+    }
+}
+
+// The arrows and tab step through the palettes.
+impl View for TileViewer {
+    fn handle_key(&mut self, gameboy: &mut Gameboy, keycode: Keycode, _keymod: Mod) {
+        match keycode {
+            Keycode::Left | Keycode::Up => self.next_palette(gameboy.mode(), -1),
+            Keycode::Right | Keycode::Down | Keycode::Tab => self.next_palette(gameboy.mode(), 1),
+            _ => {}
+        }
+    }
+
+    fn handle_hover(&mut self, gameboy: &mut Gameboy, position: Option<(usize, usize)>) {
+        self.set_hover(gameboy.mode(), position);
+    }
+
+    fn render(&self, gameboy: &mut Gameboy, frame_buffer: &mut [Color]) {
+        self.draw(gameboy, frame_buffer);
+    }
+}
+
+// Tab switches between the maps at 0x9800 and 0x9C00.
+impl View for MapViewer {
+    fn handle_key(&mut self, _gameboy: &mut Gameboy, keycode: Keycode, _keymod: Mod) {
+        if keycode == Keycode::Tab {
+            self.toggle_map();
+        }
+    }
+
+    fn handle_hover(&mut self, _gameboy: &mut Gameboy, position: Option<(usize, usize)>) {
+        self.set_hover(position);
+    }
+
+    fn render(&self, gameboy: &mut Gameboy, frame_buffer: &mut [Color]) {
+        self.draw(gameboy, frame_buffer);
+    }
+}
+
+// Up and down choose the line checked for dropped sprites, a line at a
+// time or eight with shift, and Home goes back to checking all of them.
+impl View for OamViewer {
+    fn handle_key(&mut self, _gameboy: &mut Gameboy, keycode: Keycode, keymod: Mod) {
+        let step = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) { 8 } else { 1 };
+        match keycode {
+            Keycode::Up => self.step_line(-step),
+            Keycode::Down => self.step_line(step),
+            Keycode::Home => self.set_line(None),
+            _ => {}
+        }
+    }
+
+    fn render(&self, gameboy: &mut Gameboy, frame_buffer: &mut [Color]) {
+        self.draw(gameboy, frame_buffer);
+    }
+}
+
+// The arrow keys or a click select a color, R, G and B the channel to
+// change and plus and minus change it, by four with shift.
+impl View for PaletteViewer {
+    fn handle_key(&mut self, gameboy: &mut Gameboy, keycode: Keycode, keymod: Mod) {
+        let step = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) { 4 } else { 1 };
+        let mode = gameboy.mode();
+        match keycode {
+            Keycode::Left => self.move_cursor(mode, -1, 0),
+            Keycode::Right => self.move_cursor(mode, 1, 0),
+            Keycode::Up => self.move_cursor(mode, 0, -1),
+            Keycode::Down => self.move_cursor(mode, 0, 1),
+            Keycode::R => self.set_channel(Channel::Red),
+            Keycode::G => self.set_channel(Channel::Green),
+            Keycode::B => self.set_channel(Channel::Blue),
+            Keycode::Plus | Keycode::Equals | Keycode::KpPlus => self.adjust(gameboy, step),
+            Keycode::Minus | Keycode::KpMinus => self.adjust(gameboy, -step),
+            _ => {}
+        }
+    }
+
+    fn handle_click(&mut self, gameboy: &mut Gameboy, x: usize, y: usize) {
+        if let Some((palette, value)) = self.swatch_at(gameboy.mode(), x, y) {
+            self.select(palette, value);
+        }
+    }
+
+    fn render(&self, gameboy: &mut Gameboy, frame_buffer: &mut [Color]) {
+        self.draw(gameboy, frame_buffer);
+    }
+}
+
+// The keys that open and close the debug views: memory, VRAM tiles, tile
+// maps, sprites and palettes.
+const VIEW_KEYS: [Keycode; 5] = [Keycode::F2, Keycode::F3, Keycode::F4, Keycode::F7, Keycode::F10];
+
+fn open_view(keycode: Keycode, gameboy: &mut Gameboy) -> (&'static str, usize, usize, Box<dyn View>) {
+    let mode = gameboy.mode();
+    match keycode {
+        Keycode::F2 => {
+            let mut viewer = MemoryViewer::new();
+            viewer.refresh(gameboy);
+            ("Memory", MemoryViewer::WIDTH, MemoryViewer::HEIGHT, Box::new(viewer))
+        }
+        Keycode::F3 => ("Tiles", TileViewer::WIDTH, TileViewer::HEIGHT, Box::new(TileViewer::new(mode))),
+        Keycode::F4 => ("Tile map", MapViewer::WIDTH, MapViewer::HEIGHT, Box::new(MapViewer::new(gameboy))),
+        Keycode::F7 => ("Sprites", OamViewer::WIDTH, OamViewer::HEIGHT, Box::new(OamViewer::new())),
+        Keycode::F10 => ("Palettes", PaletteViewer::WIDTH, PaletteViewer::HEIGHT, Box::new(PaletteViewer::new(mode))),
+        _ => panic!("No debug view on {}", keycode)
+    }
+}
+
+// The window an event happened in, for the events the debug windows take.
+fn event_window_id(event: &Event) -> Option<u32> {
+    match *event {
+        Event::Window { window_id, .. } |
+        Event::KeyDown { window_id, .. } |
+        Event::KeyUp { window_id, .. } |
+        Event::MouseMotion { window_id, .. } |
+        Event::MouseButtonDown { window_id, .. } |
+        Event::MouseButtonUp { window_id, .. } |
+        Event::MouseWheel { window_id, .. } => Some(window_id),
+        _ => None
+    }
+}
+
+// A second window showing one of the debug views, closed with escape or
+// the key that opened it.
+struct DebugWindow {
+    key: Keycode,
+    view: Box<dyn View>,
+    canvas: WindowCanvas,
+    // Freed along with the canvas's renderer.
+    texture: Texture,
+    width: usize,
+    frame_buffer: Vec<Color>
+}
+
+impl DebugWindow {
+    fn open(video: &VideoSubsystem, key: Keycode, gameboy: &mut Gameboy) -> Self {
+        let (title, width, height, view) = open_view(key, gameboy);
+        let window = video
+            .window(title, width as u32 * 3, height as u32 * 3)
+            .resizable()
+            .build()
+            .unwrap();
+        let mut canvas = window.into_canvas().build().unwrap();
+        // Mouse events then come in view pixels.
+        canvas.set_logical_size(width as u32, height as u32).unwrap();
+        let texture = canvas.texture_creator().create_texture_streaming(
+            PixelFormatEnum::RGB24, width as u32, height as u32
+        ).unwrap();
+        DebugWindow { key, view, canvas, texture, width, frame_buffer: vec![COLOR_OFF; width * height] }
+    }
+
+    fn id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    fn has_focus(&self) -> bool {
+        self.canvas.window().window_flags() & SDL_WindowFlags::SDL_WINDOW_INPUT_FOCUS as u32 != 0
+    }
+
+    // Passes an event on to the view. Returns false when the window should
+    // close.
+    fn handle(&mut self, event: &Event, gameboy: &mut Gameboy) -> bool {
+        let position = |x: i32, y: i32| usize::try_from(x).ok().zip(usize::try_from(y).ok());
+        match *event {
+            Event::Window { win_event: WindowEvent::Close, .. } => return false,
+            Event::KeyDown { keycode: Some(keycode), .. } if keycode == Keycode::Escape || keycode == self.key => return false,
+            Event::KeyDown { keycode: Some(keycode), keymod, .. } => self.view.handle_key(gameboy, keycode, keymod),
+            Event::MouseMotion { x, y, .. } => self.view.handle_hover(gameboy, position(x, y)),
+            Event::Window { win_event: WindowEvent::Leave, .. } => self.view.handle_hover(gameboy, None),
+            Event::MouseButtonDown { x, y, .. } => {
+                if let Some((x, y)) = position(x, y) {
+                    self.view.handle_click(gameboy, x, y);
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn present(&mut self, gameboy: &mut Gameboy) {
+        self.view.render(gameboy, &mut self.frame_buffer);
+        let (width, frame_buffer) = (self.width, &self.frame_buffer);
+        self.texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
+            for (y, line) in frame_buffer.chunks(width).enumerate() {
+                for (x, color) in line.iter().enumerate() {
+                    let offset = y*pitch + x*3;
+                    let color = to_pcolor(*color);
+                    buffer[offset] = color.r;
+                    buffer[offset + 1] = color.g;
+                    buffer[offset + 2] = color.b;
+                }
+            }
+        }).unwrap();
+        self.canvas.copy(&self.texture, None, None).unwrap();
+        self.canvas.present();
+    }
+}
 
 pub struct Renderer {
     canvas: WindowCanvas,
@@ -50,7 +358,7 @@ pub struct Renderer {
     audio_queue: AudioQueue<f32>,
     event_pump: EventPump,
     frame_buffer: [Color; GAME_WIDTH * GAME_HEIGHT],
-    bg_tile_map_frame_buffer: [Color; 128 * 192]
+    debug_windows: Vec<DebugWindow>
 }
 
 impl Renderer {
@@ -62,47 +370,146 @@ impl Renderer {
             audio_queue,
             event_pump,
             frame_buffer: [COLOR_OFF; GAME_WIDTH * GAME_HEIGHT],
-            bg_tile_map_frame_buffer: [COLOR_OFF; 128 * 192]
+            debug_windows: Vec::new()
         }
     }
 
-    pub fn run(&mut self, cartridge: Cartridge, debug: bool, skip_boot_rom: bool, dmg: bool) {
+    /// Runs until the window is closed. Rewinding, resetting and loading
+    /// states are disabled while a movie is recording or playing. F9 opens
+    /// the debugger console on stdin. F6 turns the codes from the ROM's
+    /// `.cht` file on and off. F2 opens a window with a live, editable hex
+    /// view of memory, F3 one of the tiles in VRAM, F4 one of the tile
+    /// maps, F7 one of the sprites in OAM and F10 one of the palettes,
+    /// where colors can be changed. They take the keyboard while they have focus.
+    pub fn run(&mut self, cartridge: Cartridge, options: RunOptions) {
+        let RunOptions { debug, skip_boot_rom, dmg, record, playback, mut gdb, break_at, profile, cdl } = options;
         self.canvas.window_mut().set_size(GAME_WIDTH as u32 * 4, GAME_HEIGHT as u32 * 4).unwrap();
 
         let texture_creator = self.canvas.texture_creator();
         let mut game_texture = texture_creator.create_texture_streaming(
             PixelFormatEnum::RGB24, GAME_WIDTH as u32, GAME_HEIGHT as u32
         ).unwrap();
-        let mut bg_tile_map_texture = texture_creator.create_texture_streaming(
-            PixelFormatEnum::RGB24, 256, 256
-        ).unwrap();
 
         let mut audio_data = Vec::with_capacity(40_000);
 
         let mode = if dmg { Mode::DMG } else { Mode::CGB };
         let mut gameboy = Gameboy::new(debug, mode);
         gameboy.boot(cartridge.clone(), skip_boot_rom);
+        if profile.is_some() {
+            gameboy.set_profiler(Some(Profiler::new(cartridge.symbols())));
+        }
+        match cartridge.cheats() {
+            Ok(cheats) => gameboy.set_cheats(cheats),
+            Err(e) => println!("Failed to load cheats: {}", e)
+        }
+        if let Some(path) = &cdl {
+            let mut log = CodeDataLog::new(&mut gameboy);
+            if path.exists() {
+                if let Err(e) = fs::read(path).map_err(|e| e.to_string()).and_then(|data| log.load(&data)) {
+                    println!("Failed to load {}: {}", path.display(), e);
+                }
+            }
+            gameboy.set_cdl(Some(log));
+        }
 
         let mut saves = 0;
+        let mut slot = 0;
         let mut paused = false;
+        let mut rewind = RewindBuffer::new(REWIND_CAPACITY);
+        let mut frames: u64 = 0;
+        let mut recording = record.as_ref().map(|_| Movie::new(&cartridge, mode, skip_boot_rom));
+        let mut movie_frame = 0;
+        let mut debugger = Debugger::new(cartridge.symbols());
+        if let Some(location) = break_at {
+            if let Err(e) = debugger.break_at(&location) {
+                println!("Can't break at {}: {}", location, e);
+            }
+        }
         'running: loop {
-            let pressed = collect_pressed(&self.event_pump.keyboard_state());
+            let movie_running = recording.is_some() ||
+                playback.as_ref().is_some_and(|movie| movie_frame < movie.len());
+            let debug_focused = self.debug_windows.iter().any(DebugWindow::has_focus);
+            let keyboard_state = self.event_pump.keyboard_state();
+            let mut pressed = if debug_focused { Vec::new() } else { collect_pressed(&keyboard_state) };
+            let rewinding = !movie_running && !debug_focused && keyboard_state.is_scancode_pressed(REWIND_KEY);
 
             if !paused {
-                gameboy.tick(&pressed, &mut self.frame_buffer, &mut audio_data);
-                self.flush_audio(&audio_data);
+                let ran_frame = if rewinding {
+                    if let Some(state) = rewind.pop() {
+                        gameboy.load_state(&state).unwrap();
+                        if rewind.is_empty() {
+                            rewind.push(state);
+                        }
+                    }
+                    gameboy.tick(&[], &mut self.frame_buffer, &mut audio_data);
+                    true
+                } else if let Some(stub) = gdb.as_mut() {
+                    let (ran_frame, attached) = match stub.tick(&mut gameboy, &pressed, &mut self.frame_buffer, &mut audio_data) {
+                        Ok(ran_frame) => (ran_frame, stub.is_attached()),
+                        Err(e) => {
+                            println!("Lost the debugger connection: {}", e);
+                            (false, false)
+                        }
+                    };
+                    if !attached {
+                        println!("Debugger detached");
+                        gdb = None;
+                    }
+                    self.flush_audio(&audio_data);
+                    ran_frame
+                } else {
+                    if frames.is_multiple_of(REWIND_INTERVAL) {
+                        rewind.push(gameboy.save_state());
+                    }
+                    if let Some(movie) = playback.as_ref() {
+                        if let Some(movie_pressed) = movie.frame(movie_frame) {
+                            pressed = movie_pressed;
+                        }
+                    }
+                    if let Some(recording) = recording.as_mut() {
+                        recording.record_frame(&pressed);
+                    }
+                    if !debugger.tick(&mut gameboy, &pressed, &mut self.frame_buffer, &mut audio_data) {
+                        break 'running;
+                    }
+                    self.flush_audio(&audio_data);
+
+                    movie_frame += 1;
+                    if let Some(movie) = playback.as_ref().filter(|movie| movie.len() == movie_frame) {
+                        report_playback(movie, &self.frame_buffer);
+                    }
+                    true
+                };
                 audio_data.clear();
-                gameboy.fill_tile_framebuffer(&mut self.bg_tile_map_frame_buffer);
 
-                self.wait_for_frame();
-                self.draw_frame(&mut game_texture, &mut bg_tile_map_texture);
+                if ran_frame {
+                    frames += 1;
+                    for window in &mut self.debug_windows {
+                        window.view.refresh(&mut gameboy);
+                    }
+                    self.wait_for_frame();
+                    self.draw_frame(&mut game_texture);
+                } else {
+                    thread::sleep(time::Duration::from_millis(10));
+                }
             } else {
                 thread::sleep(time::Duration::from_millis(10));
             }
 
             for event in self.event_pump.poll_iter() {
+                let window_id = event_window_id(&event);
+                if let Some(index) = self.debug_windows.iter().position(|w| Some(w.id()) == window_id) {
+                    if !self.debug_windows[index].handle(&event, &mut gameboy) {
+                        self.debug_windows.remove(index);
+                    }
+                    continue;
+                }
                 match event {
                     Event::Quit { .. }
+                    | Event::Window {
+                        win_event: WindowEvent::Close,
+                        ..
+                    }
                     | Event::KeyDown {
                         keycode: Some(Keycode::Escape),
                         ..
@@ -116,10 +523,19 @@ impl Renderer {
                     Event::KeyDown {
                         keycode: Some(Keycode::R),
                         ..
-                    } => {
+                    } if !movie_running => {
                         paused = false;
+                        let profiler = gameboy.take_profiler();
+                        let log = gameboy.take_cdl();
+                        let cheats = gameboy.cheats().to_vec();
+                        let cheats_enabled = gameboy.cheats_enabled();
                         gameboy = Gameboy::new(debug, mode);
                         gameboy.boot(cartridge.clone(), skip_boot_rom);
+                        gameboy.set_profiler(profiler);
+                        gameboy.set_cdl(log);
+                        gameboy.set_cheats(cheats);
+                        gameboy.set_cheats_enabled(cheats_enabled);
+                        rewind.clear();
                     }
                     Event::KeyDown {
                         keycode: Some(Keycode::P),
@@ -128,6 +544,65 @@ impl Renderer {
                         fs::write(format!("./{}.sav", saves), gameboy.dump_ram()).unwrap();
                         saves += 1;
                     }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F5),
+                        ..
+                    } => {
+                        let path = save_state_path(&cartridge, slot);
+                        match fs::write(&path, gameboy.save_state()) {
+                            Ok(()) => println!("Saved state to {}", path.display()),
+                            Err(e) => println!("Failed to save state to {}: {}", path.display(), e)
+                        }
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F9),
+                        ..
+                    } => debugger.request_break(),
+                    Event::KeyDown {
+                        keycode: Some(keycode),
+                        ..
+                    } if VIEW_KEYS.contains(&keycode) => {
+                        match self.debug_windows.iter().position(|w| w.key == keycode) {
+                            Some(index) => { self.debug_windows.remove(index); }
+                            None => {
+                                let window = DebugWindow::open(self.canvas.window().subsystem(), keycode, &mut gameboy);
+                                self.debug_windows.push(window);
+                            }
+                        }
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F6),
+                        ..
+                    } if !gameboy.cheats().is_empty() => {
+                        let enabled = !gameboy.cheats_enabled();
+                        gameboy.set_cheats_enabled(enabled);
+                        println!("Cheats {}", if enabled { "on" } else { "off" });
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F8),
+                        ..
+                    } if !movie_running => {
+                        let path = save_state_path(&cartridge, slot);
+                        match fs::read(&path) {
+                            Ok(data) => match gameboy.load_state(&data) {
+                                Ok(()) => {
+                                    rewind.clear();
+                                    println!("Loaded state from {}", path.display())
+                                }
+                                Err(e) => println!("Failed to load state from {}: {}", path.display(), e)
+                            },
+                            Err(e) => println!("Failed to read {}: {}", path.display(), e)
+                        }
+                    }
+                    Event::KeyDown {
+                        keycode: Some(keycode),
+                        ..
+                    } => {
+                        if let Some(n) = keycode_to_slot(keycode) {
+                            slot = n;
+                            println!("Selected save state slot {}", slot);
+                        }
+                    }
                     Event::Window {
                         win_event: WindowEvent::Resized(_, h),
                         ..
@@ -139,6 +614,31 @@ impl Renderer {
                     _ => {}
                 }
             }
+            for window in &mut self.debug_windows {
+                window.present(&mut gameboy);
+            }
+        }
+
+        if let (Some(mut recording), Some(path)) = (recording, record) {
+            recording.set_final_frame_hash(gameboy::frame_hash(&self.frame_buffer));
+            match recording.write(&path) {
+                Ok(()) => println!("Wrote {} frame movie to {}", recording.len(), path.display()),
+                Err(e) => println!("Failed to write movie to {}: {}", path.display(), e)
+            }
+        }
+        if let (Some(profiler), Some(path)) = (gameboy.profiler(), profile) {
+            profiler.write_report(&mut io::stdout()).unwrap();
+            let result = fs::File::create(&path).and_then(|mut file| profiler.write_folded(&mut file));
+            match result {
+                Ok(()) => println!("Wrote call stacks to {}", path.display()),
+                Err(e) => println!("Failed to write call stacks to {}: {}", path.display(), e)
+            }
+        }
+        if let (Some(log), Some(path)) = (gameboy.cdl(), cdl) {
+            match fs::write(&path, log.save()) {
+                Ok(()) => println!("Wrote code/data log to {}", path.display()),
+                Err(e) => println!("Failed to write code/data log to {}: {}", path.display(), e)
+            }
         }
     }
 
@@ -154,15 +654,14 @@ impl Renderer {
             None => {
                 debug!("Dropping frame...");
             }
-            Some(d) => {
-                if d.as_nanos() > NS_PER_SCREEN_REFRESH as u128 {
-                    thread::sleep(d.checked_sub(time::Duration::from_nanos(NS_PER_SCREEN_REFRESH)).unwrap());
-                }
+            Some(d) if d.as_nanos() > NS_PER_SCREEN_REFRESH as u128 => {
+                thread::sleep(d.checked_sub(time::Duration::from_nanos(NS_PER_SCREEN_REFRESH)).unwrap());
             }
+            Some(_) => {}
         }
     }
 
-    fn draw_frame(&mut self, texture: &mut Texture, bg_tile_map_texture: &mut Texture) {
+    fn draw_frame(&mut self, texture: &mut Texture) {
         texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
             for y in 0..GAME_HEIGHT {
                 for x in 0..GAME_WIDTH {
@@ -174,37 +673,17 @@ impl Renderer {
                 }
             }
         }).unwrap();
-        self.update_bg_tile_map_texture(bg_tile_map_texture);
-
-        let game_rect = Rect::new(0, 0, 2 * GAME_WIDTH as u32, 2 * GAME_HEIGHT as u32);
-        let bg_tile_rect = Rect::new(game_rect.width() as i32 + 50, 0, 128 * 4, 192 * 4);
 
         self.canvas.clear();
-        self.canvas.copy(texture, None, game_rect).unwrap();
-        self.canvas.copy(bg_tile_map_texture, None, bg_tile_rect).unwrap();
+        self.canvas.copy(texture, None, None).unwrap();
         self.canvas.present();
     }
 
-    pub fn update_bg_tile_map_texture(&mut self, texture: &mut Texture) {
-        texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
-            for y in 0..192 {
-                for x in 0..128 {
-                    let offset = y*pitch + x*3;
-                    let color = to_pcolor(self.bg_tile_map_frame_buffer[y * 128 + x]);
-                    buffer[offset] = color.r;
-                    buffer[offset + 1] = color.g;
-                    buffer[offset + 2] = color.b;
-                }
-            }
-        }).unwrap();
-    }
-
-
     fn flush_audio(&mut self, audio_data: &[f32]) {
         if self.audio_queue.size() < 100 {
             debug!("AUDIO QUEUE SIZE: {}", self.audio_queue.size());
         }
-        let data = self.audio_converter.process(&audio_data).unwrap();
+        let data = self.audio_converter.process(audio_data).unwrap();
         self.audio_queue.queue(&data);
     }
 }
@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+use std::convert::TryInto;
+
+/// A bounded history of save states for rewinding.
+///
+/// Only the newest state is kept whole. Every older state is stored as the
+/// run length encoded XOR against the state that came after it, which is
+/// small since most of the machine doesn't change between frames. Once
+/// `capacity` states are held the oldest one is dropped.
+pub struct RewindBuffer {
+    capacity: usize,
+    newest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "RewindBuffer needs room for at least one state");
+        RewindBuffer {
+            capacity,
+            newest: None,
+            deltas: VecDeque::with_capacity(capacity)
+        }
+    }
+
+    /// Adds a state made by [`Gameboy::save_state`](crate::Gameboy::save_state).
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(newest) = self.newest.take() {
+            match encode_delta(&state, &newest) {
+                Some(delta) => {
+                    self.deltas.push_back(delta);
+                    if self.deltas.len() >= self.capacity {
+                        self.deltas.pop_front();
+                    }
+                }
+                // The size changed so the older states can't be rebuilt.
+                None => self.deltas.clear()
+            }
+        }
+        self.newest = Some(state);
+    }
+
+    /// Removes and returns the newest state.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let newest = self.newest.take()?;
+        self.newest = self.deltas.pop_back().map(|delta| decode_delta(&newest, &delta));
+        Some(newest)
+    }
+
+    /// The newest state, without removing it.
+    pub fn peek(&self) -> Option<&[u8]> {
+        self.newest.as_deref()
+    }
+
+    pub fn len(&self) -> usize {
+        match self.newest {
+            None => 0,
+            Some(_) => self.deltas.len() + 1
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+    }
+
+    /// The number of bytes held, to keep an eye on how well states compress.
+    pub fn size_in_bytes(&self) -> usize {
+        self.newest.as_ref().map_or(0, Vec::len) + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+}
+
+// A delta is a series of (unchanged run, changed run, changed bytes XORed)
+// records, the run lengths as little endian u32s.
+fn encode_delta(base: &[u8], target: &[u8]) -> Option<Vec<u8>> {
+    if base.len() != target.len() {
+        return None;
+    }
+
+    let mut delta = Vec::new();
+    let mut i = 0;
+    while i < base.len() {
+        let same_start = i;
+        while i < base.len() && base[i] == target[i] { i += 1; }
+        let diff_start = i;
+        while i < base.len() && base[i] != target[i] { i += 1; }
+        if diff_start == i {
+            break;
+        }
+
+        delta.extend_from_slice(&((diff_start - same_start) as u32).to_le_bytes());
+        delta.extend_from_slice(&((i - diff_start) as u32).to_le_bytes());
+        delta.extend(base[diff_start..i].iter().zip(&target[diff_start..i]).map(|(b, t)| b ^ t));
+    }
+    Some(delta)
+}
+
+fn decode_delta(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut target = base.to_vec();
+    let mut pos = 0;
+    let mut i = 0;
+    while i < delta.len() {
+        let same = u32::from_le_bytes(delta[i..i + 4].try_into().unwrap()) as usize;
+        let diff = u32::from_le_bytes(delta[i + 4..i + 8].try_into().unwrap()) as usize;
+        i += 8;
+        pos += same;
+        for (t, d) in target[pos..pos + diff].iter_mut().zip(&delta[i..i + diff]) {
+            *t ^= d;
+        }
+        pos += diff;
+        i += diff;
+    }
+    target
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(n: u8) -> Vec<u8> {
+        let mut state = vec![0; 1000];
+        state[10] = n;
+        state[500..510].iter_mut().for_each(|v| *v = n.wrapping_mul(3));
+        state[999] = n;
+        state
+    }
+
+    #[test]
+    fn test_push_pop() {
+        let mut buffer = RewindBuffer::new(10);
+        for n in 0..5 {
+            buffer.push(state(n));
+        }
+        assert_eq!(buffer.len(), 5);
+        assert!(buffer.size_in_bytes() < 1000 + 4 * 50);
+        for n in (0..5).rev() {
+            assert_eq!(buffer.peek(), Some(&state(n)[..]));
+            assert_eq!(buffer.pop(), Some(state(n)));
+        }
+        assert_eq!(buffer.pop(), None);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut buffer = RewindBuffer::new(3);
+        for n in 0..10 {
+            buffer.push(state(n));
+        }
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.pop(), Some(state(9)));
+        assert_eq!(buffer.pop(), Some(state(8)));
+        assert_eq!(buffer.pop(), Some(state(7)));
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn test_size_change() {
+        let mut buffer = RewindBuffer::new(10);
+        buffer.push(state(1));
+        buffer.push(vec![1, 2, 3]);
+        assert_eq!(buffer.len(), 1);
+        assert_eq!(buffer.pop(), Some(vec![1, 2, 3]));
+    }
+}
@@ -0,0 +1,195 @@
+use std::fmt;
+
+const MAGIC: &[u8; 4] = b"GBSS";
+const VERSION: u32 = 3;
+
+#[derive(Debug, PartialEq)]
+pub enum SaveStateError {
+    UnexpectedEof,
+    BadMagic,
+    UnsupportedVersion(u32),
+    ModeMismatch,
+    RomMismatch,
+    Invalid(&'static str)
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::UnexpectedEof => write!(f, "save state is truncated"),
+            SaveStateError::BadMagic => write!(f, "not a save state"),
+            SaveStateError::UnsupportedVersion(v) => write!(f, "unsupported save state version {}", v),
+            SaveStateError::ModeMismatch => write!(f, "save state was made in a different mode"),
+            SaveStateError::RomMismatch => write!(f, "save state was made with a different ROM"),
+            SaveStateError::Invalid(what) => write!(f, "invalid {} in save state", what)
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+pub type Result<T> = std::result::Result<T, SaveStateError>;
+
+/// Implemented by every piece of machine state that ends up in a save state.
+/// Fields are written and read back in declaration order.
+pub trait SaveState {
+    fn save_state(&self, w: &mut StateWriter);
+    fn load_state(&mut self, r: &mut StateReader) -> Result<()>;
+}
+
+pub struct StateWriter {
+    data: Vec<u8>
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        let mut w = StateWriter { data: Vec::new() };
+        w.data.extend_from_slice(MAGIC);
+        w.write_u32(VERSION);
+        w
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn write_u8(&mut self, v: u8) {
+        self.data.push(v);
+    }
+
+    pub fn write_bool(&mut self, v: bool) {
+        self.write_u8(v as u8);
+    }
+
+    pub fn write_u16(&mut self, v: u16) {
+        self.data.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, v: u32) {
+        self.data.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, v: u64) {
+        self.data.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn write_usize(&mut self, v: usize) {
+        self.write_u64(v as u64);
+    }
+
+    /// Writes a length prefixed byte slice.
+    pub fn write_bytes(&mut self, v: &[u8]) {
+        self.write_usize(v.len());
+        self.data.extend_from_slice(v);
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self> {
+        let mut r = StateReader { data, pos: 0 };
+        if r.take(MAGIC.len())? != MAGIC {
+            return Err(SaveStateError::BadMagic)
+        }
+        match r.read_u32()? {
+            VERSION => Ok(r),
+            v => Err(SaveStateError::UnsupportedVersion(v))
+        }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.data.len() - self.pos < n {
+            return Err(SaveStateError::UnexpectedEof)
+        }
+        let bytes = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveStateError::Invalid("bool"))
+        }
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.take(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn read_usize(&mut self) -> Result<usize> {
+        Ok(self.read_u64()? as usize)
+    }
+
+    /// Reads a length prefixed byte slice that must exactly fill `dst`.
+    pub fn read_bytes_into(&mut self, dst: &mut [u8]) -> Result<()> {
+        if self.read_usize()? != dst.len() {
+            return Err(SaveStateError::Invalid("buffer length"))
+        }
+        dst.copy_from_slice(self.take(dst.len())?);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut w = StateWriter::new();
+        w.write_u8(0x12);
+        w.write_bool(true);
+        w.write_u16(0xBEEF);
+        w.write_u32(0xDEADBEEF);
+        w.write_u64(42);
+        w.write_bytes(&[1, 2, 3]);
+        let data = w.into_inner();
+
+        let mut r = StateReader::new(&data).unwrap();
+        assert_eq!(r.read_u8(), Ok(0x12));
+        assert_eq!(r.read_bool(), Ok(true));
+        assert_eq!(r.read_u16(), Ok(0xBEEF));
+        assert_eq!(r.read_u32(), Ok(0xDEADBEEF));
+        assert_eq!(r.read_u64(), Ok(42));
+        let mut buf = [0; 3];
+        assert_eq!(r.read_bytes_into(&mut buf), Ok(()));
+        assert_eq!(buf, [1, 2, 3]);
+        assert!(r.is_empty());
+        assert_eq!(r.read_u8(), Err(SaveStateError::UnexpectedEof));
+    }
+
+    #[test]
+    fn test_bad_header() {
+        assert_eq!(StateReader::new(b"nope").err(), Some(SaveStateError::BadMagic));
+        assert_eq!(StateReader::new(b"GB").err(), Some(SaveStateError::UnexpectedEof));
+        assert_eq!(StateReader::new(b"GBSS\x09\0\0\0").err(), Some(SaveStateError::UnsupportedVersion(9)));
+    }
+}
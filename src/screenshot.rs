@@ -0,0 +1,76 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::Path;
+use crate::gameboy::{Color, GAME_WIDTH, GAME_HEIGHT};
+
+/// Packs a frame buffer into 24 bit RGB bytes, row by row.
+pub fn to_rgb24(frame_buffer: &[Color]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(frame_buffer.len() * 3);
+    for color in frame_buffer {
+        data.extend_from_slice(&color.to_rgb24());
+    }
+    data
+}
+
+/// Writes a `GAME_WIDTH` x `GAME_HEIGHT` frame buffer as an RGB PNG.
+pub fn write_png(path: &Path, frame_buffer: &[Color]) -> io::Result<()> {
+    write_rgb24_png(path, GAME_WIDTH as u32, GAME_HEIGHT as u32, &to_rgb24(frame_buffer))
+}
+
+/// Writes 24 bit RGB bytes of any size as a PNG.
+pub fn write_rgb24_png(path: &Path, width: u32, height: u32, data: &[u8]) -> io::Result<()> {
+    let file = File::create(path)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+    Ok(())
+}
+
+/// Reads a PNG as 24 bit RGB bytes, returning its width, height and data.
+/// Alpha is dropped and grayscale, palette and 16 bit images are converted.
+pub fn read_png(path: &Path) -> io::Result<(u32, u32, Vec<u8>)> {
+    let to_io = |e: png::DecodingError| io::Error::new(io::ErrorKind::InvalidData, e);
+
+    let mut decoder = png::Decoder::new(File::open(path)?);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let (info, mut reader) = decoder.read_info().map_err(to_io)?;
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf).map_err(to_io)?;
+
+    let data = match info.color_type {
+        png::ColorType::RGB => buf,
+        png::ColorType::RGBA => buf.chunks(4).flat_map(|p| p[..3].to_vec()).collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|v| vec![*v; 3]).collect(),
+        png::ColorType::GrayscaleAlpha => buf.chunks(2).flat_map(|p| vec![p[0]; 3]).collect(),
+        png::ColorType::Indexed => {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpanded palette PNG"))
+        }
+    };
+    Ok((info.width, info.height, data))
+}
+
+/// Writes a frame buffer as headerless 24 bit RGB.
+pub fn write_raw(path: &Path, frame_buffer: &[Color]) -> io::Result<()> {
+    fs::write(path, to_rgb24(frame_buffer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_png_round_trip() {
+        let path = std::env::temp_dir().join(format!("gameboy-screenshot-{}.png", std::process::id()));
+        let frame_buffer: Vec<Color> = (0..GAME_WIDTH * GAME_HEIGHT)
+            .map(|i| Color::new((i % 32) as u8, (i / 32 % 32) as u8, 7))
+            .collect();
+        write_png(&path, &frame_buffer).unwrap();
+        let (width, height, data) = read_png(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!((width, height), (GAME_WIDTH as u32, GAME_HEIGHT as u32));
+        assert_eq!(data, to_rgb24(&frame_buffer));
+    }
+}
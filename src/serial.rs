@@ -1,6 +1,7 @@
 use crate::clocks::CLOCKS_PER_SERIAL_BIT_SHIFT;
 use crate::memory::memory_map::{MemoryMappedDevice, MappedArea};
 use crate::interrupt_controller::{Interrupt};
+use crate::save_state::{self, SaveState, StateReader, StateWriter};
 
 const SB: u16 = 0xFF01;
 const SC: u16 = 0xFF02;
@@ -9,7 +10,8 @@ const RP: u16 = 0xFF56;
 pub struct SerialController {
     sb: u8,
     sc: u8,
-    clocks_to_shift: u32
+    clocks_to_shift: u32,
+    capture: Option<Vec<u8>>
 }
 
 impl SerialController {
@@ -17,10 +19,21 @@ impl SerialController {
         SerialController {
             sb: 0,
             sc: 0,
-            clocks_to_shift: 0
+            clocks_to_shift: 0,
+            capture: None
         }
     }
 
+    /// Starts or stops keeping the bytes sent out over the link port.
+    pub fn set_capture(&mut self, capture: bool) {
+        self.capture = if capture { Some(Vec::new()) } else { None };
+    }
+
+    /// Returns the bytes sent since the last call.
+    pub fn take_captured(&mut self) -> Vec<u8> {
+        self.capture.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
     pub fn mapped_areas() -> [MappedArea; 2] {
         [
             MappedArea(SB, 2),
@@ -33,7 +46,7 @@ impl SerialController {
         if b7!(self.sc) == 1 && b0!(self.sc) == 1 {
             if self.clocks_to_shift < clocks {
                 self.sb = (self.sb << 1) | 0x1;
-                self.sc = self.sc ^ (1 << 7);
+                self.sc ^= 1 << 7;
                 fire_interrupt(Interrupt::Serial);
             } else {
                 self.clocks_to_shift -= clocks;
@@ -60,6 +73,9 @@ impl MemoryMappedDevice for SerialController {
             SC => {
                 if b7!(self.sc) == 0 && b7!(byte) == 1 {
                     self.clocks_to_shift = CLOCKS_PER_SERIAL_BIT_SHIFT;
+                    if let Some(capture) = self.capture.as_mut() {
+                        capture.push(self.sb);
+                    }
                 }
                 self.sc = byte;
             }
@@ -68,3 +84,18 @@ impl MemoryMappedDevice for SerialController {
         }
     }
 }
+
+impl SaveState for SerialController {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.sb);
+        w.write_u8(self.sc);
+        w.write_u32(self.clocks_to_shift);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        self.sb = r.read_u8()?;
+        self.sc = r.read_u8()?;
+        self.clocks_to_shift = r.read_u32()?;
+        Ok(())
+    }
+}
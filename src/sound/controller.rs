@@ -5,6 +5,7 @@ use super::square::{SquareWave};
 use super::wave::{Wave};
 use super::noise::{Noise, Lfsr};
 use super::envelope::VolumeEnvelope;
+use crate::save_state::{self, SaveState, StateReader, StateWriter};
 
 const NR10: u16 = 0xFF10;
 const NR11: u16 = 0xFF11;
@@ -135,6 +136,32 @@ impl SoundController {
     }
 }
 
+impl SaveState for SoundController {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.regs);
+        w.write_bool(self.on);
+        self.square_a.save_state(w);
+        self.square_b.save_state(w);
+        self.wave.save_state(w);
+        self.noise.save_state(w);
+        w.write_u8(self.nr50);
+        w.write_u8(self.nr51);
+        self.frame_sequencer.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        r.read_bytes_into(&mut self.regs)?;
+        self.on = r.read_bool()?;
+        self.square_a.load_state(r)?;
+        self.square_b.load_state(r)?;
+        self.wave.load_state(r)?;
+        self.noise.load_state(r)?;
+        self.nr50 = r.read_u8()?;
+        self.nr51 = r.read_u8()?;
+        self.frame_sequencer.load_state(r)
+    }
+}
+
 impl MemoryMappedDevice for SoundController {
     fn set8(&mut self, addr: u16, byte: u8) {
         if addr == NR52 {
@@ -212,7 +239,7 @@ impl MemoryMappedDevice for SoundController {
                 self.wave.set_freq_upper(byte & 0x7);
                 self.wave.set_length_enabled(b6!(byte) == 1, &self.frame_sequencer);
             }
-            WAVE_START ... WAVE_END => {
+            WAVE_START ..= WAVE_END => {
                 self.wave.set_wave((addr - WAVE_START) as u8, byte);
             }
             NR41 => {
@@ -256,9 +283,8 @@ impl MemoryMappedDevice for SoundController {
                 }
                 val
             }
-            NR10 ... WAVE_END => {
-                let val = self.regs[offset] | REG_ORS[offset];
-                val
+            NR10 ..= WAVE_END => {
+                self.regs[offset] | REG_ORS[offset]
             }
             _ => panic!("Invalid get address 0x{:X} mapped to Sound Controller", addr)
         }
@@ -1,4 +1,5 @@
 use crate::clocks::CLOCK_FREQ;
+use crate::save_state::{self, SaveState, SaveStateError, StateReader, StateWriter};
 
 #[derive(Debug, PartialEq)]
 pub enum Direction {
@@ -8,6 +9,17 @@ pub enum Direction {
 
 use Direction::*;
 
+impl SaveState for Direction {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(*self == Increase);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        *self = if r.read_bool()? { Increase } else { Decrease };
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub struct VolumeEnvelope {
     volume: u8,
@@ -51,3 +63,23 @@ impl VolumeEnvelope {
         input * self.volume
     }
  }
+
+impl SaveState for VolumeEnvelope {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.volume);
+        self.direction.save_state(w);
+        w.write_u8(self.period);
+        w.write_u32(self.ticks_left);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        self.volume = r.read_u8()?;
+        self.direction.load_state(r)?;
+        self.period = r.read_u8()?;
+        self.ticks_left = r.read_u32()?;
+        if self.volume > 15 {
+            return Err(SaveStateError::Invalid("envelope volume"))
+        }
+        Ok(())
+    }
+}
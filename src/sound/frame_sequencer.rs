@@ -1,4 +1,5 @@
 use crate::clocks::CLOCK_FREQ;
+use crate::save_state::{self, SaveState, StateReader, StateWriter};
 
 pub struct FrameSequencer {
     ticks_left: u32,
@@ -27,14 +28,32 @@ impl FrameSequencer {
     }
 
     pub fn is_length_counter(&self) -> bool {
-        self.clock.map_or(false, |v| v % 2 == 0)
+        self.clock.is_some_and(|v| v % 2 == 0)
     }
 
     pub fn is_sweep(&self) -> bool {
-        self.clock.map_or(false, |v| v == 2 || v == 6)
+        self.clock.is_some_and(|v| v == 2 || v == 6)
     }
 
     pub fn next_is_length_counter(&self) -> bool {
-        self.step % 2 == 0
+        b0!(self.step) == 0
+    }
+}
+
+impl SaveState for FrameSequencer {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u32(self.ticks_left);
+        w.write_u32(self.step);
+        w.write_bool(self.clock.is_some());
+        w.write_u32(self.clock.unwrap_or(0));
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        self.ticks_left = r.read_u32()?;
+        self.step = r.read_u32()?;
+        let has_clock = r.read_bool()?;
+        let clock = r.read_u32()?;
+        self.clock = if has_clock { Some(clock) } else { None };
+        Ok(())
     }
 }
@@ -1,3 +1,5 @@
+use crate::save_state::{self, SaveState, StateReader, StateWriter};
+
 pub enum LengthCounterAction {
     Disable,
     Nop
@@ -58,3 +60,16 @@ impl LengthCounter {
         }
     }
 }
+
+impl SaveState for LengthCounter {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.enabled);
+        w.write_u16(self.length);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        self.enabled = r.read_bool()?;
+        self.length = r.read_u16()?;
+        Ok(())
+    }
+}
@@ -2,6 +2,7 @@ use super::frame_sequencer::FrameSequencer;
 use crate::clocks::CLOCK_FREQ;
 use super::envelope::VolumeEnvelope;
 use super::length_counter::{LengthCounter, LengthCounterAction};
+use crate::save_state::{self, SaveState, SaveStateError, StateReader, StateWriter};
 
 #[derive(Debug)]
 pub struct Lfsr {
@@ -54,6 +55,28 @@ impl Lfsr {
     }
 }
 
+impl SaveState for Lfsr {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u16(self.bits);
+        w.write_u8(self.freq_shift);
+        w.write_u8(self.len);
+        w.write_usize(self.divisor_code);
+        w.write_u16(self.timer);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        self.bits = r.read_u16()?;
+        self.freq_shift = r.read_u8()?;
+        self.len = r.read_u8()?;
+        self.divisor_code = r.read_usize()?;
+        self.timer = r.read_u16()?;
+        if self.freq_shift > 0xF || self.divisor_code > 0x7 {
+            return Err(SaveStateError::Invalid("noise frequency"))
+        }
+        Ok(())
+    }
+}
+
 pub struct Noise {
     dac_on: bool,
     enabled: bool,
@@ -138,3 +161,21 @@ impl Noise {
         self.lfsr.reset();
     }
 }
+
+impl SaveState for Noise {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.dac_on);
+        w.write_bool(self.enabled);
+        self.lfsr.save_state(w);
+        self.volume_envelope.save_state(w);
+        self.length_counter.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        self.dac_on = r.read_bool()?;
+        self.enabled = r.read_bool()?;
+        self.lfsr.load_state(r)?;
+        self.volume_envelope.load_state(r)?;
+        self.length_counter.load_state(r)
+    }
+}
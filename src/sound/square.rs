@@ -2,6 +2,7 @@ use super::frame_sequencer::FrameSequencer;
 use super::envelope::{VolumeEnvelope};
 use super::sweep::{Sweep, SweepAction};
 use super::length_counter::{LengthCounter, LengthCounterAction};
+use crate::save_state::{self, SaveState, SaveStateError, StateReader, StateWriter};
 
 const DUTY_CYCLES: [[u8; 8]; 4] = [
     [0, 0, 0, 0, 0, 0, 0, 1],
@@ -52,6 +53,26 @@ impl WaveGen {
     }
 }
 
+impl SaveState for WaveGen {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u16(self.frequency);
+        w.write_u16(self.timer);
+        w.write_usize(self.duty_index);
+        w.write_usize(self.cycle_offset);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        self.frequency = r.read_u16()?;
+        self.timer = r.read_u16()?;
+        self.duty_index = r.read_usize()?;
+        self.cycle_offset = r.read_usize()?;
+        if self.duty_index >= DUTY_CYCLES.len() || self.cycle_offset >= 8 {
+            return Err(SaveStateError::Invalid("square wave duty"))
+        }
+        Ok(())
+    }
+}
+
 pub struct SquareWave {
     dac_on: bool,
     enabled: bool,
@@ -160,11 +181,28 @@ impl SquareWave {
             self.enabled = true;
         }
         self.length_counter.trigger(will_enable);
-        match self.sweep.trigger(self.square_wave.frequency) {
-            SweepAction::Disable => {
-                self.enabled = false
-            }
-            _ => { }
+        if let SweepAction::Disable = self.sweep.trigger(self.square_wave.frequency) {
+            self.enabled = false
         }
     }
 }
+
+impl SaveState for SquareWave {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.dac_on);
+        w.write_bool(self.enabled);
+        self.sweep.save_state(w);
+        self.square_wave.save_state(w);
+        self.volume_envelope.save_state(w);
+        self.length_counter.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        self.dac_on = r.read_bool()?;
+        self.enabled = r.read_bool()?;
+        self.sweep.load_state(r)?;
+        self.square_wave.load_state(r)?;
+        self.volume_envelope.load_state(r)?;
+        self.length_counter.load_state(r)
+    }
+}
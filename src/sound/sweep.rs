@@ -1,4 +1,5 @@
 use super::envelope::{Direction};
+use crate::save_state::{self, SaveState, StateReader, StateWriter};
 
 pub enum SweepAction {
     Disable,
@@ -96,3 +97,24 @@ impl Sweep {
         }
     }
 }
+
+impl SaveState for Sweep {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.enabled);
+        w.write_u8(self.sweep_period);
+        self.direction.save_state(w);
+        w.write_u8(self.shift);
+        w.write_u16(self.frequency);
+        w.write_u8(self.periods_left);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        self.enabled = r.read_bool()?;
+        self.sweep_period = r.read_u8()?;
+        self.direction.load_state(r)?;
+        self.shift = r.read_u8()?;
+        self.frequency = r.read_u16()?;
+        self.periods_left = r.read_u8()?;
+        Ok(())
+    }
+}
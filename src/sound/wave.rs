@@ -1,5 +1,6 @@
 use super::frame_sequencer::FrameSequencer;
 use super::length_counter::{LengthCounter, LengthCounterAction};
+use crate::save_state::{self, SaveState, SaveStateError, StateReader, StateWriter};
 
 #[derive(Clone, Copy, Debug)]
 enum Volume {
@@ -64,14 +65,33 @@ impl CustomWave {
     }
 }
 
+impl SaveState for CustomWave {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.wave_table);
+        w.write_u16(self.frequency);
+        w.write_u16(self.timer);
+        w.write_usize(self.cycle_offset);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        r.read_bytes_into(&mut self.wave_table)?;
+        self.frequency = r.read_u16()?;
+        self.timer = r.read_u16()?;
+        self.cycle_offset = r.read_usize()?;
+        if self.cycle_offset >= self.wave_table.len() {
+            return Err(SaveStateError::Invalid("wave position"))
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub struct Wave {
     dac_on: bool,
     enabled: bool,
     custom_wave: CustomWave,
     volume: Volume,
-    length_counter: LengthCounter,
-    frequency: u16
+    length_counter: LengthCounter
 }
 
 impl Wave {
@@ -81,8 +101,7 @@ impl Wave {
             enabled:false,
             custom_wave: CustomWave::new(),
             volume: Mute,
-            length_counter: LengthCounter::new(256),
-            frequency: 0
+            length_counter: LengthCounter::new(256)
         }
     }
 
@@ -166,3 +185,27 @@ impl Wave {
         self.custom_wave.reset();
     }
 }
+
+impl SaveState for Wave {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.dac_on);
+        w.write_bool(self.enabled);
+        self.custom_wave.save_state(w);
+        w.write_u8(match self.volume {
+            Mute => 0,
+            Full => 1,
+            Half => 2,
+            Quarter => 3
+        });
+        self.length_counter.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        self.dac_on = r.read_bool()?;
+        self.enabled = r.read_bool()?;
+        self.custom_wave.load_state(r)?;
+        self.volume = *VOLUMES.get(r.read_u8()? as usize)
+            .ok_or(SaveStateError::Invalid("wave volume"))?;
+        self.length_counter.load_state(r)
+    }
+}
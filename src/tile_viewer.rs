@@ -0,0 +1,136 @@
+use crate::draw::{self, BACKGROUND, GLYPH_HEIGHT, GLYPH_WIDTH, MARGIN, TEXT};
+use crate::gameboy::{Color, Gameboy, Mode};
+use crate::lcd::TilePalette;
+
+const SHEET_WIDTH: usize = 16 * 8;
+const SHEET_HEIGHT: usize = 24 * 8;
+const GAP: usize = 8;
+const SHEETS_Y: usize = MARGIN + GLYPH_HEIGHT + 2;
+const STATUS_Y: usize = SHEETS_Y + SHEET_HEIGHT + 2;
+
+const HOVER: Color = Color { r: 31, g: 10, b: 8 };
+
+/// Shows all 384 tiles of both VRAM banks, bank 0 on the left, colored
+/// with a chosen palette, and describes the tile under the mouse.
+pub struct TileViewer {
+    palette: TilePalette,
+    // The VRAM bank and tile number under the mouse.
+    hover: Option<(usize, usize)>
+}
+
+impl TileViewer {
+    pub const WIDTH: usize = 2 * MARGIN + 2 * SHEET_WIDTH + GAP;
+    pub const HEIGHT: usize = STATUS_Y + GLYPH_HEIGHT + MARGIN;
+
+    pub fn new(mode: Mode) -> Self {
+        let palette = if mode.is_cgb() { TilePalette::Bg(0) } else { TilePalette::Bgp };
+        TileViewer { palette, hover: None }
+    }
+
+    pub fn palette(&self) -> TilePalette {
+        self.palette
+    }
+
+    pub fn set_palette(&mut self, palette: TilePalette) {
+        self.palette = palette;
+    }
+
+    /// Steps through BGP, OBP0 and OBP1 and then, on CGB, the background
+    /// and object palettes.
+    pub fn next_palette(&mut self, mode: Mode, delta: isize) {
+        let mut palettes = vec![TilePalette::Bgp, TilePalette::Obp0, TilePalette::Obp1];
+        if mode.is_cgb() {
+            palettes.extend((0..8).map(TilePalette::Bg));
+            palettes.extend((0..8).map(TilePalette::Obj));
+        }
+        let i = palettes.iter().position(|p| *p == self.palette).unwrap_or(0) as isize;
+        self.palette = palettes[(i + delta).rem_euclid(palettes.len() as isize) as usize];
+    }
+
+    /// The VRAM bank and tile number drawn at (`x`, `y`).
+    pub fn tile_at(&self, mode: Mode, x: usize, y: usize) -> Option<(usize, usize)> {
+        let row = y.checked_sub(SHEETS_Y).filter(|y| *y < SHEET_HEIGHT)? / 8;
+        let x = x.checked_sub(MARGIN)?;
+        let (bank, x) = if x < SHEET_WIDTH {
+            (0, x)
+        } else {
+            (1, x.checked_sub(SHEET_WIDTH + GAP).filter(|x| *x < SHEET_WIDTH)?)
+        };
+        if bank == 1 && !mode.is_cgb() {
+            return None;
+        }
+        Some((bank, row * 16 + x / 8))
+    }
+
+    /// Tracks the mouse at (`x`, `y`), or off the view with None.
+    pub fn set_hover(&mut self, mode: Mode, position: Option<(usize, usize)>) {
+        self.hover = position.and_then(|(x, y)| self.tile_at(mode, x, y));
+    }
+
+    /// The address of a tile and the index that refers to it. Tiles $80 to
+    /// $FF can be reached with either addressing mode, the rest with one.
+    pub fn describe(bank: usize, tile: usize) -> String {
+        format!("VRAM{} {:04X}  TILE {:03X}  INDEX {:02X}", bank, 0x8000 + tile * 16, tile, tile & 0xFF)
+    }
+
+    pub fn draw(&self, gameboy: &mut Gameboy, frame_buffer: &mut [Color]) {
+        let width = Self::WIDTH;
+        draw::fill_rect(frame_buffer, width, 0, 0, width, Self::HEIGHT, BACKGROUND);
+        let name = self.palette.to_string();
+        draw::draw_text(frame_buffer, width, MARGIN, MARGIN, &name, TEXT);
+        for value in 0..4 {
+            let x = MARGIN + (name.len() + 1) * GLYPH_WIDTH + value as usize * 8;
+            draw::fill_rect(frame_buffer, width, x, MARGIN, 7, GLYPH_HEIGHT - 1, gameboy.palette_color(self.palette, value));
+        }
+
+        let banks = if gameboy.mode().is_cgb() { 2 } else { 1 };
+        for bank in 0..banks {
+            let x = MARGIN + bank * (SHEET_WIDTH + GAP);
+            gameboy.draw_tiles(bank, self.palette, &mut frame_buffer[SHEETS_Y * width + x..], width);
+        }
+
+        if let Some((bank, tile)) = self.hover {
+            let x = MARGIN + bank * (SHEET_WIDTH + GAP) + (tile % 16) * 8;
+            let y = SHEETS_Y + (tile / 16) * 8;
+            draw::outline_rect(frame_buffer, width, x - 1, y - 1, 10, 10, HOVER);
+            draw::draw_text(frame_buffer, width, MARGIN, STATUS_Y, &Self::describe(bank, tile), TEXT);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::idle_gameboy;
+
+    #[test]
+    fn test_tile_viewer() {
+        let mut gameboy = idle_gameboy("tile-viewer");
+        // The top row of tile $17F in bank 1 is color 3, the rest color 0.
+        gameboy.write_vram(1, 0x97F0, 0xFF);
+        gameboy.write_vram(1, 0x97F1, 0xFF);
+        // Color 3 of background palette 2 is pure red.
+        gameboy.write8(0xFF68, 0x80 | (2 * 8 + 3 * 2));
+        gameboy.write8(0xFF69, 0x1F);
+        gameboy.write8(0xFF69, 0x00);
+
+        let mut viewer = TileViewer::new(Mode::CGB);
+        viewer.next_palette(Mode::CGB, 2);
+        assert_eq!(viewer.palette(), TilePalette::Bg(2));
+        let (x, y) = (MARGIN + SHEET_WIDTH + GAP + 15 * 8, SHEETS_Y + 23 * 8);
+        assert_eq!(viewer.tile_at(Mode::CGB, x + 3, y + 3), Some((1, 0x17F)));
+        assert_eq!(viewer.tile_at(Mode::DMG, x + 3, y + 3), None);
+        assert_eq!(viewer.tile_at(Mode::CGB, MARGIN + SHEET_WIDTH + 1, y), None);
+        assert_eq!(TileViewer::describe(1, 0x17F), "VRAM1 97F0  TILE 17F  INDEX 7F");
+
+        viewer.set_hover(Mode::CGB, Some((x, y)));
+        let mut frame_buffer = vec![Color::new(0, 0, 0); TileViewer::WIDTH * TileViewer::HEIGHT];
+        viewer.draw(&mut gameboy, &mut frame_buffer);
+        assert_eq!(frame_buffer[(y - 1) * TileViewer::WIDTH + x], HOVER);
+        let red = Color::new(31, 0, 0);
+        assert_eq!(frame_buffer[y * TileViewer::WIDTH + x], red);
+        assert_ne!(frame_buffer[(y + 1) * TileViewer::WIDTH + x], red);
+        // Bank 0 is blank.
+        assert_ne!(frame_buffer[y * TileViewer::WIDTH + x - SHEET_WIDTH - GAP], red);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::memory::memory_map::{MemoryMappedDevice, MappedArea};
 use crate::interrupt_controller::Interrupt;
 use crate::clocks::CLOCK_FREQ;
+use crate::save_state::{self, SaveState, StateReader, StateWriter};
 
 const DIV_FREQ: u32 = 16384;
 const TAC_FREQS: [u32; 4] = [4096, 262144, 65536, 16384];
@@ -32,7 +33,7 @@ impl Timer {
         let mut overflow = false;
         while to_tick > 0 {
             if self.clocks_left <= to_tick {
-                to_tick = to_tick - self.clocks_left;
+                to_tick -= self.clocks_left;
                 overflow |= self.value == 0xFF;
                 self.value = if self.value == 0xFF { self.default_value } else { self.value + 1 };
                 self.clocks_left = self.clocks_per_tick;
@@ -75,11 +76,9 @@ impl TimerController {
     pub fn tick<F>(&mut self, clocks: u32, mut fire_interrupt: F) where
     F: FnMut(Interrupt) {
         self.div_ticker.tick(clocks);
-        if self.tima_running {
-            if self.tima_ticker.tick(clocks) {
-                fire_interrupt(Interrupt::Timer)
-            }
-        };
+        if self.tima_running && self.tima_ticker.tick(clocks) {
+            fire_interrupt(Interrupt::Timer)
+        }
     }
 }
 
@@ -113,6 +112,48 @@ impl MemoryMappedDevice for TimerController {
     }
 }
 
+impl SaveState for Timer {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u32(self.clocks_per_tick);
+        w.write_u32(self.clocks_left);
+        w.write_u8(self.value);
+        w.write_u8(self.default_value);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        let clocks_per_tick = r.read_u32()?;
+        if !TAC_FREQS.iter().chain(&[DIV_FREQ]).any(|freq| CLOCK_FREQ / freq == clocks_per_tick) {
+            return Err(save_state::SaveStateError::Invalid("timer divider"))
+        }
+        let clocks_left = r.read_u32()?;
+        if clocks_left == 0 || clocks_left > clocks_per_tick {
+            return Err(save_state::SaveStateError::Invalid("timer clocks"))
+        }
+        self.clocks_per_tick = clocks_per_tick;
+        self.clocks_left = clocks_left;
+        self.value = r.read_u8()?;
+        self.default_value = r.read_u8()?;
+        Ok(())
+    }
+}
+
+impl SaveState for TimerController {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.tac);
+        self.div_ticker.save_state(w);
+        self.tima_ticker.save_state(w);
+        w.write_bool(self.tima_running);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> save_state::Result<()> {
+        self.tac = r.read_u8()?;
+        self.div_ticker.load_state(r)?;
+        self.tima_ticker.load_state(r)?;
+        self.tima_running = r.read_bool()?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -165,4 +206,20 @@ mod tests {
         t.tick(63, fire);
         assert_eq!(t.get8(DIV), 1);
     }
+
+    #[test]
+    fn test_load_state_rejects_bad_divider() {
+        let t = TimerController::new();
+        let mut w = StateWriter::new();
+        t.save_state(&mut w);
+        let mut state = w.into_inner();
+        // The DIV ticker's clocks per tick follow the magic, version and TAC.
+        state[9..13].copy_from_slice(&0u32.to_le_bytes());
+        let mut r = StateReader::new(&state).unwrap();
+        assert!(TimerController::new().load_state(&mut r).is_err());
+
+        state[9..13].copy_from_slice(&3u32.to_le_bytes());
+        let mut r = StateReader::new(&state).unwrap();
+        assert!(TimerController::new().load_state(&mut r).is_err());
+    }
 }
@@ -0,0 +1,144 @@
+use std::io::{self, Write};
+use crate::cpu::{Register::*, Registers};
+
+/// Logs the registers before every instruction, one line each, in the
+/// format used by gameboy-doctor:
+///
+/// `A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02`
+///
+/// Start the machine with the boot ROM skipped to compare against its
+/// reference logs.
+pub struct Tracer {
+    out: Box<dyn Write>,
+    limit: Option<u64>,
+    stop_at: Option<u16>,
+    lines: u64,
+    done: bool,
+    error: Option<io::Error>
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write>) -> Self {
+        Tracer { out, limit: None, stop_at: None, lines: 0, done: false, error: None }
+    }
+
+    /// Stops after logging `limit` instructions.
+    pub fn stop_after(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Stops when PC reaches `pc`, without logging that instruction.
+    pub fn stop_at(mut self, pc: u16) -> Self {
+        self.stop_at = Some(pc);
+        self
+    }
+
+    /// Whether a stop condition was reached. Nothing more is logged after.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// The number of instructions logged.
+    pub fn lines(&self) -> u64 {
+        self.lines
+    }
+
+    /// The error that stopped the log, if writing it failed. The tracer is
+    /// done after one.
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    pub(crate) fn log(&mut self, registers: &Registers, pcmem: [u8; 4]) {
+        if self.done || self.stop_at == Some(registers.pc()) {
+            self.done = true;
+            return;
+        }
+        let result = writeln!(self.out, "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+                 registers.get8(A), registers.get8(F), registers.get8(B), registers.get8(C),
+                 registers.get8(D), registers.get8(E), registers.get8(H), registers.get8(L),
+                 registers.get16(SP), registers.pc(), pcmem[0], pcmem[1], pcmem[2], pcmem[3]);
+        if let Err(e) = result {
+            self.error = Some(e);
+            self.done = true;
+            return;
+        }
+        self.lines += 1;
+        if self.limit == Some(self.lines) {
+            self.done = true;
+        }
+    }
+}
+
+impl Drop for Tracer {
+    fn drop(&mut self) {
+        let _ = self.out.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use super::*;
+    use crate::gameboy::{booted_gameboy, test_frame_buffer, Mode};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run(tracer: Tracer, buffer: &SharedBuffer) -> Vec<String> {
+        // LD A,$42; INC A; JR -3
+        let mut gameboy = booted_gameboy("trace", &[0x3E, 0x42, 0x3C, 0x18, 0xFD], Mode::DMG);
+        gameboy.set_tracer(Some(tracer));
+        let mut frame_buffer = test_frame_buffer();
+        while !gameboy.tracer().unwrap().is_done() {
+            gameboy.step(&mut frame_buffer, &mut Vec::new());
+        }
+        String::from_utf8(buffer.0.borrow().clone()).unwrap().lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_trace() {
+        let buffer = SharedBuffer::default();
+        let lines = run(Tracer::new(Box::new(buffer.clone())).stop_after(3), &buffer);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:3E,42,3C,18");
+        assert_eq!(lines[1], "A:42 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0102 PCMEM:3C,18,FD,00");
+        assert!(lines[2].starts_with("A:43 F:10 "));
+
+        let buffer = SharedBuffer::default();
+        let lines = run(Tracer::new(Box::new(buffer.clone())).stop_at(0x103), &buffer);
+        assert_eq!(lines.len(), 2);
+    }
+
+    struct FullDisk;
+
+    impl Write for FullDisk {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_error_stops_tracing() {
+        let tracer = Tracer::new(Box::new(FullDisk));
+        let lines = run(tracer, &SharedBuffer::default());
+        assert!(lines.is_empty());
+    }
+}
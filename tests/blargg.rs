@@ -0,0 +1,61 @@
+//! Runs Blargg's test ROMs and checks what they print over the link port.
+//!
+//! Put the ROMs (`cpu_instrs`, `instr_timing`, `mem_timing`, `dmg_sound`...)
+//! under `test_roms/blargg` or point `BLARGG_ROMS` at them, then run
+//! `cargo test --test blargg -- --nocapture` to see the per-ROM report.
+
+mod common;
+
+use std::path::Path;
+use common::{Outcome, TestMachine, FRAMES_PER_SECOND};
+
+const TIMEOUT_SECONDS: u64 = 120;
+// Frames to keep running after "Failed" so the rest of the message arrives.
+const FAILURE_FRAMES: u64 = 30;
+
+fn run(path: &Path) -> (Outcome, String) {
+    let mut machine = TestMachine::new(path);
+    machine.gameboy.set_serial_capture(true);
+
+    let mut output = String::new();
+    let mut failed_at = None;
+    for frame in 0..TIMEOUT_SECONDS * FRAMES_PER_SECOND {
+        machine.run_frame();
+        output.extend(machine.gameboy.take_serial_output().into_iter().map(char::from));
+
+        if output.contains("Passed") {
+            return (Outcome::Passed, output);
+        }
+        match failed_at {
+            None if output.contains("Failed") => failed_at = Some(frame),
+            Some(failed_at) if frame - failed_at >= FAILURE_FRAMES => break,
+            _ => {}
+        }
+    }
+
+    let outcome = match failed_at {
+        Some(_) => {
+            let line = output.lines().find(|line| line.contains("Failed")).unwrap_or_default();
+            Outcome::Failed(line.trim().to_string())
+        }
+        None => Outcome::TimedOut
+    };
+    (outcome, output)
+}
+
+#[test]
+fn blargg() {
+    let dir = match common::rom_dir("BLARGG_ROMS", "test_roms/blargg") {
+        Some(dir) => dir,
+        None => return
+    };
+
+    let results: Vec<_> = common::rom_paths(&dir).into_iter().map(|path| {
+        let (outcome, output) = run(&path);
+        if outcome != Outcome::Passed {
+            println!("--- {}\n{}", path.display(), output);
+        }
+        (path, outcome)
+    }).collect();
+    common::report(&dir, &results);
+}
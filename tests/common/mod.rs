@@ -0,0 +1,100 @@
+//! Helpers shared by the test ROM harnesses. The ROMs aren't part of the
+//! repository, so each harness looks for them in a directory named by an
+//! environment variable and skips itself when there is none.
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use gameboy::{Cartridge, Color, Gameboy, Mode, GAME_WIDTH, GAME_HEIGHT};
+
+pub const FRAMES_PER_SECOND: u64 = 60;
+
+/// The directory in `var`, or `default` under the crate root, if it exists.
+pub fn rom_dir(var: &str, default: &str) -> Option<PathBuf> {
+    let dir = match env::var_os(var) {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(env!("CARGO_MANIFEST_DIR")).join(default)
+    };
+    if dir.is_dir() {
+        Some(dir)
+    } else {
+        println!("Skipping, no test ROMs in {} (set {})", dir.display(), var);
+        None
+    }
+}
+
+/// Every `.gb` and `.gbc` file under `dir`, sorted.
+pub fn rom_paths(dir: &Path) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            paths.extend(rom_paths(&path));
+        } else if path.extension().is_some_and(|ext| ext == "gb" || ext == "gbc") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    paths
+}
+
+/// A booted machine along with the buffers `Gameboy::tick` draws into.
+pub struct TestMachine {
+    pub gameboy: Gameboy,
+    pub frame_buffer: Vec<Color>,
+    audio: Vec<f32>
+}
+
+impl TestMachine {
+    /// Boots `path` through the boot ROM, as a CGB if the header allows it.
+    pub fn new(path: &Path) -> Self {
+        let cartridge = Cartridge::new(path.to_path_buf(), None).unwrap();
+        let mode = if cartridge.cgb_compatible() { Mode::CGB } else { Mode::DMG };
+        Self::with_mode(cartridge, mode)
+    }
+
+    pub fn with_mode(cartridge: Cartridge, mode: Mode) -> Self {
+        let mut gameboy = Gameboy::new(false, mode);
+        gameboy.boot(cartridge, false);
+        TestMachine {
+            gameboy,
+            frame_buffer: vec![Color::new(31, 31, 31); GAME_WIDTH * GAME_HEIGHT],
+            audio: Vec::new()
+        }
+    }
+
+    pub fn run_frame(&mut self) {
+        self.gameboy.tick(&[], &mut self.frame_buffer, &mut self.audio);
+        self.audio.clear();
+    }
+
+    /// Executes a single instruction. Returns true when the LCD entered VBlank.
+    pub fn step(&mut self) -> bool {
+        let vblank = self.gameboy.step(&mut self.frame_buffer, &mut self.audio);
+        self.audio.clear();
+        vblank
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Passed,
+    Failed(String),
+    TimedOut
+}
+
+/// Prints one line per ROM and fails the test unless every ROM passed.
+pub fn report(dir: &Path, results: &[(PathBuf, Outcome)]) {
+    for (path, outcome) in results {
+        let name = path.strip_prefix(dir).unwrap_or(path).display();
+        match outcome {
+            Outcome::Passed => println!("PASS    {}", name),
+            Outcome::Failed(why) => println!("FAIL    {}: {}", name, why),
+            Outcome::TimedOut => println!("TIMEOUT {}", name)
+        }
+    }
+    let passed = results.iter().filter(|(_, outcome)| *outcome == Outcome::Passed).count();
+    println!("{}/{} passed", passed, results.len());
+    assert_eq!(passed, results.len(), "some test ROMs did not pass");
+}
@@ -0,0 +1,52 @@
+//! Runs Mooneye's test ROMs. They finish by executing `LD B,B` with
+//! B/C/D/E/H/L holding 3/5/8/13/21/34 on success.
+//!
+//! Put the ROMs (e.g. the `acceptance` directory) under `test_roms/mooneye`
+//! or point `MOONEYE_ROMS` at them, then run
+//! `cargo test --test mooneye -- --nocapture` to see the per-ROM report.
+
+mod common;
+
+use std::path::Path;
+use gameboy::Register::*;
+use common::{Outcome, TestMachine, FRAMES_PER_SECOND};
+
+const TIMEOUT_SECONDS: u64 = 30;
+const FIBONACCI: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+fn run(path: &Path) -> Outcome {
+    let mut machine = TestMachine::new(path);
+    machine.gameboy.set_ld_b_b_breakpoint(true);
+
+    let mut frames = 0;
+    while frames < TIMEOUT_SECONDS * FRAMES_PER_SECOND {
+        if machine.step() {
+            frames += 1;
+        }
+        // Check the registers before the ROM gets to change them again.
+        if machine.gameboy.take_ld_b_b_hit() {
+            let registers = machine.gameboy.registers();
+            let values: Vec<u8> = [B, C, D, E, H, L].iter().map(|r| registers.get8(*r)).collect();
+            return if values == FIBONACCI {
+                Outcome::Passed
+            } else {
+                Outcome::Failed(format!("B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X}",
+                                        values[0], values[1], values[2], values[3], values[4], values[5]))
+            };
+        }
+    }
+    Outcome::TimedOut
+}
+
+#[test]
+fn mooneye() {
+    let dir = match common::rom_dir("MOONEYE_ROMS", "test_roms/mooneye") {
+        Some(dir) => dir,
+        None => return
+    };
+
+    let results: Vec<_> = common::rom_paths(&dir).into_iter()
+        .map(|path| { let outcome = run(&path); (path, outcome) })
+        .collect();
+    common::report(&dir, &results);
+}
@@ -0,0 +1,160 @@
+//! Compares the screen against reference PNGs, e.g. for dmg-acid2 and
+//! cgb-acid2.
+//!
+//! The ROM directory (`test_roms/reference` or `REFERENCE_ROMS`) holds a
+//! `reference.txt` with one test per line:
+//!
+//! ```text
+//! # rom            reference      stop at         mode
+//! dmg-acid2.gb     dmg-acid2.png  ld_b_b          dmg
+//! cgb-acid2.gbc    cgb-acid2.png  ld_b_b          cgb
+//! title.gb         title.png      600
+//! ```
+//!
+//! The stop column is either a frame number or `ld_b_b` to run until the ROM
+//! executes `LD B,B`. The mode is taken from the header when left out. Any
+//! frame written by `gameboy-headless` can be used as a reference. When the
+//! screen doesn't match, an image with the reference, the screen and the
+//! differing pixels side by side is written to `target/tmp/reference_diffs`.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use gameboy::{Cartridge, Mode, GAME_WIDTH, GAME_HEIGHT};
+use gameboy::screenshot;
+use common::{Outcome, TestMachine, FRAMES_PER_SECOND};
+
+const LD_B_B_TIMEOUT_SECONDS: u64 = 30;
+// Channels may differ by less than one RGB555 step, so references made by
+// other emulators with slightly different DMG shades still match.
+const TOLERANCE: u8 = 7;
+
+enum Stop {
+    Frame(u64),
+    LdBB
+}
+
+struct Test {
+    rom: PathBuf,
+    reference: PathBuf,
+    stop: Stop,
+    mode: Option<Mode>
+}
+
+fn parse_manifest(dir: &Path, data: &str) -> Result<Vec<Test>, String> {
+    let mut tests = Vec::new();
+    for (line_num, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue
+        }
+        let parts: Vec<_> = line.split_whitespace().collect();
+        if parts.len() < 3 || parts.len() > 4 {
+            return Err(format!("line {}: expected <rom> <reference> <stop> [mode]", line_num + 1));
+        }
+        let stop = match parts[2] {
+            "ld_b_b" => Stop::LdBB,
+            frame => Stop::Frame(frame.parse().map_err(|e| format!("line {}: bad frame: {}", line_num + 1, e))?)
+        };
+        let mode = match parts.get(3) {
+            None => None,
+            Some(&"dmg") => Some(Mode::DMG),
+            Some(&"cgb") => Some(Mode::CGB),
+            Some(mode) => return Err(format!("line {}: unknown mode {}", line_num + 1, mode))
+        };
+        tests.push(Test { rom: dir.join(parts[0]), reference: dir.join(parts[1]), stop, mode });
+    }
+    Ok(tests)
+}
+
+fn run(test: &Test) -> Result<Vec<u8>, Outcome> {
+    let mut machine = match test.mode {
+        Some(mode) => TestMachine::with_mode(Cartridge::new(test.rom.clone(), None).unwrap(), mode),
+        None => TestMachine::new(&test.rom)
+    };
+    match test.stop {
+        Stop::Frame(frames) => {
+            for _ in 0..frames {
+                machine.run_frame();
+            }
+        }
+        Stop::LdBB => {
+            machine.gameboy.set_ld_b_b_breakpoint(true);
+            let mut frames = 0;
+            while !machine.gameboy.take_ld_b_b_hit() {
+                if frames == LD_B_B_TIMEOUT_SECONDS * FRAMES_PER_SECOND {
+                    return Err(Outcome::TimedOut);
+                }
+                machine.run_frame();
+                frames += 1;
+            }
+            // Finish drawing the frame the breakpoint was hit in.
+            machine.run_frame();
+        }
+    }
+    Ok(screenshot::to_rgb24(&machine.frame_buffer))
+}
+
+fn pixel_matches(expected: &[u8], actual: &[u8]) -> bool {
+    expected.iter().zip(actual).all(|(e, a)| (*e as i16 - *a as i16).abs() <= TOLERANCE as i16)
+}
+
+// The reference, the screen and the mismatched pixels in red over a dimmed
+// copy of the screen, side by side.
+fn diff_image(expected: &[u8], actual: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(expected.len() * 3);
+    for y in 0..GAME_HEIGHT {
+        let row = y * GAME_WIDTH * 3..(y + 1) * GAME_WIDTH * 3;
+        data.extend_from_slice(&expected[row.clone()]);
+        data.extend_from_slice(&actual[row.clone()]);
+        for (e, a) in expected[row.clone()].chunks(3).zip(actual[row].chunks(3)) {
+            if pixel_matches(e, a) {
+                data.extend(a.iter().map(|v| v / 4));
+            } else {
+                data.extend_from_slice(&[0xFF, 0, 0]);
+            }
+        }
+    }
+    data
+}
+
+fn check(test: &Test, diff_dir: &Path) -> Outcome {
+    let expected = match screenshot::read_png(&test.reference) {
+        Ok((width, height, data)) if (width as usize, height as usize) == (GAME_WIDTH, GAME_HEIGHT) => data,
+        Ok((width, height, _)) => return Outcome::Failed(format!("reference is {}x{}", width, height)),
+        Err(e) => return Outcome::Failed(format!("can't read {}: {}", test.reference.display(), e))
+    };
+    let actual = match run(test) {
+        Ok(actual) => actual,
+        Err(outcome) => return outcome
+    };
+
+    let mismatched = expected.chunks(3).zip(actual.chunks(3)).filter(|(e, a)| !pixel_matches(e, a)).count();
+    if mismatched == 0 {
+        return Outcome::Passed;
+    }
+    let name = test.reference.file_stem().unwrap().to_string_lossy();
+    let diff_path = diff_dir.join(format!("{}-diff.png", name));
+    screenshot::write_rgb24_png(&diff_path, GAME_WIDTH as u32 * 3, GAME_HEIGHT as u32, &diff_image(&expected, &actual))
+        .unwrap();
+    Outcome::Failed(format!("{} pixels differ, see {}", mismatched, diff_path.display()))
+}
+
+#[test]
+fn reference_images() {
+    let dir = match common::rom_dir("REFERENCE_ROMS", "test_roms/reference") {
+        Some(dir) => dir,
+        None => return
+    };
+    let manifest = dir.join("reference.txt");
+    let data = fs::read_to_string(&manifest)
+        .unwrap_or_else(|e| panic!("Can't read {}: {}", manifest.display(), e));
+    let tests = parse_manifest(&dir, &data).unwrap_or_else(|e| panic!("{}: {}", manifest.display(), e));
+
+    let diff_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("reference_diffs");
+    fs::create_dir_all(&diff_dir).unwrap();
+
+    let results: Vec<_> = tests.iter().map(|test| (test.reference.clone(), check(test, &diff_dir))).collect();
+    common::report(&dir, &results);
+}